@@ -0,0 +1,48 @@
+//! Packs every PNG under `src/sprites` into a texture atlas in `OUT_DIR`, loaded at
+//! runtime with `render::atlas::TextureAtlas::bundled`.
+
+use std::path::{Path, PathBuf};
+
+#[allow(dead_code)]
+#[path = "src/render/atlas/pack.rs"]
+mod pack;
+
+const SPRITES_DIR: &str = "src/sprites";
+
+fn main() -> anyhow::Result<()> {
+    println!("cargo:rerun-if-changed={SPRITES_DIR}");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+
+    let mut builder = pack::AtlasBuilder::new(pack::AtlasOptions::default());
+    for path in glob::glob(&format!("{SPRITES_DIR}/**/*.png"))? {
+        let path = path?;
+        println!("cargo:rerun-if-changed={}", path.display());
+        builder.add_file(sprite_name(&path)?, &path)?;
+    }
+    let packed = builder.build()?;
+    packed.save(&out_dir, "sprites")?;
+
+    let mut module = String::from("pub const PAGES: &[&[u8]] = &[\n");
+    for index in 0..packed.pages.len() {
+        module.push_str(&format!(
+            "    include_bytes!(concat!(env!(\"OUT_DIR\"), \"/sprites_{index}.png\")),\n"
+        ));
+    }
+    module.push_str("];\n");
+    module.push_str(
+        "pub const MANIFEST: &str = include_str!(concat!(env!(\"OUT_DIR\"), \"/sprites.json\"));\n",
+    );
+    std::fs::write(out_dir.join("sprites_atlas.rs"), module)?;
+
+    Ok(())
+}
+
+/// `src/sprites/ui/panel.png` becomes `ui/panel`, with `/` on every platform.
+fn sprite_name(path: &Path) -> anyhow::Result<String> {
+    let relative = path.strip_prefix(SPRITES_DIR)?.with_extension("");
+    let parts = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    Ok(parts.join("/"))
+}
@@ -0,0 +1,72 @@
+mod shader_type;
+mod vertex;
+
+use proc_macro::TokenStream;
+use syn::{
+    parse_macro_input, punctuated::Punctuated, spanned::Spanned, token::Comma, Data, DeriveInput,
+    Field, Fields,
+};
+
+/// Implements `game_lib::render::types::VertexDescription` for a `#[repr(C)]` struct.
+///
+/// Each field becomes one attribute (matrices become one attribute per column) with
+/// consecutive shader locations and offsets taken from the struct layout.
+///
+/// Struct attributes:
+/// - `#[vertex(step_mode = "instance")]` — `"vertex"` (default) or `"instance"`
+/// - `#[vertex(location_offset = 5)]` — shader location of the first attribute
+///
+/// Field attributes:
+/// - `#[vertex(location = 3)]` — explicit location, following fields continue from it
+/// - `#[vertex(format = "Unorm8x4")]` — override the inferred `wgpu::VertexFormat`
+/// - `#[vertex(skip)]` — padding or CPU-only data that isn't a vertex attribute
+#[proc_macro_derive(VertexDescription, attributes(vertex))]
+pub fn derive_vertex_description(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    vertex::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `game_lib::render::uniform::ShaderType` for a `#[repr(C)]` struct and checks
+/// at compile time that its Rust layout is identical to the WGSL host-shareable layout.
+///
+/// Every field must itself implement `ShaderType`. A field whose Rust offset differs from
+/// the offset WGSL would give it (e.g. an `f32` followed by a `Vec3`) is a compile error.
+///
+/// Field attributes:
+/// - `#[shader(padding)]` — explicit Rust padding with no WGSL counterpart
+#[proc_macro_derive(ShaderType, attributes(shader))]
+pub fn derive_shader_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    shader_type::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Fields of a non-generic struct with named fields, the only shape the derives here accept.
+fn named_fields<'a>(
+    input: &'a DeriveInput,
+    derive: &str,
+) -> syn::Result<&'a Punctuated<Field, Comma>> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            format!("{} can't be derived for generic structs", derive),
+        ));
+    }
+
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(&fields.named),
+            _ => Err(syn::Error::new(
+                input.ident.span(),
+                format!("{} requires a struct with named fields", derive),
+            )),
+        },
+        _ => Err(syn::Error::new(
+            input.ident.span(),
+            format!("{} can only be derived for structs", derive),
+        )),
+    }
+}
@@ -0,0 +1,119 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let fields = crate::named_fields(&input, "ShaderType")?;
+
+    let mut members = Vec::new();
+    for field in fields {
+        let mut padding = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("shader")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("padding") {
+                    padding = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `padding`"))
+                }
+            })?;
+        }
+
+        if !padding {
+            members.push((field.ident.as_ref().expect("named field"), &field.ty));
+        }
+    }
+
+    if members.is_empty() {
+        return Err(syn::Error::new(
+            ident.span(),
+            "ShaderType requires at least one non-padding field",
+        ));
+    }
+
+    let module = quote!(::game_lib::render::uniform);
+
+    let aligns = members.iter().map(|(_, ty)| {
+        quote! {
+            if <#ty as #module::ShaderType>::ALIGN > align {
+                align = <#ty as #module::ShaderType>::ALIGN;
+            }
+        }
+    });
+
+    let uniform_members = members.iter().map(|(name, ty)| {
+        quote! {
+            valid &= <#ty as #module::ShaderType>::UNIFORM_VALID
+                && ::std::mem::offset_of!(#ident, #name)
+                    % <#ty as #module::ShaderType>::UNIFORM_ALIGN
+                    == 0;
+        }
+    });
+
+    // Uniform buffers also require a struct or array member to be followed by at least
+    // its size rounded up to 16 bytes.
+    let uniform_spans = members.windows(2).map(|pair| {
+        let (prev, prev_ty) = pair[0];
+        let (next, _) = pair[1];
+        quote! {
+            valid &= ::std::mem::offset_of!(#ident, #next)
+                >= ::std::mem::offset_of!(#ident, #prev)
+                    + <#prev_ty as #module::ShaderType>::UNIFORM_SPAN;
+        }
+    });
+
+    let offset_checks = members.iter().map(|(name, ty)| {
+        let offset_message = format!(
+            "`{}::{}` isn't at the offset WGSL expects, add a #[shader(padding)] field before it",
+            ident, name
+        );
+        let size_message = format!(
+            "`{}::{}` has a different size in Rust than in WGSL (vec3 array or matrix?)",
+            ident, name
+        );
+        quote! {
+            offset = #module::round_up(<#ty as #module::ShaderType>::ALIGN, offset);
+            assert!(::std::mem::offset_of!(#ident, #name) == offset, #offset_message);
+            assert!(
+                ::std::mem::size_of::<#ty>() == <#ty as #module::ShaderType>::SIZE,
+                #size_message
+            );
+            offset += <#ty as #module::ShaderType>::SIZE;
+        }
+    });
+
+    let struct_message = format!(
+        "`{}` has a different size in Rust than in WGSL, add trailing #[shader(padding)]",
+        ident
+    );
+
+    Ok(quote! {
+        impl #module::ShaderType for #ident {
+            const ALIGN: usize = {
+                let mut align = 1;
+                #(#aligns)*
+                align
+            };
+            const SIZE: usize = ::std::mem::size_of::<Self>();
+            const UNIFORM_ALIGN: usize = #module::round_up(16, Self::ALIGN);
+            const UNIFORM_SPAN: usize = #module::round_up(16, Self::SIZE);
+            const UNIFORM_VALID: bool = {
+                let mut valid = true;
+                #(#uniform_members)*
+                #(#uniform_spans)*
+                valid
+            };
+        }
+
+        const _: () = {
+            let mut offset = 0usize;
+            #(#offset_checks)*
+            assert!(
+                ::std::mem::size_of::<#ident>()
+                    == #module::round_up(<#ident as #module::ShaderType>::ALIGN, offset),
+                #struct_message
+            );
+        };
+    })
+}
@@ -0,0 +1,174 @@
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{spanned::Spanned, DeriveInput, LitInt, LitStr, Type};
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+
+    let fields = crate::named_fields(&input, "VertexDescription")?;
+
+    let mut step_mode = quote!(::wgpu::VertexStepMode::Vertex);
+    let mut location = 0u32;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("step_mode") {
+                let value: LitStr = meta.value()?.parse()?;
+                step_mode = match value.value().as_str() {
+                    "vertex" => quote!(::wgpu::VertexStepMode::Vertex),
+                    "instance" => quote!(::wgpu::VertexStepMode::Instance),
+                    _ => {
+                        return Err(syn::Error::new(
+                            value.span(),
+                            "expected \"vertex\" or \"instance\"",
+                        ))
+                    }
+                };
+                Ok(())
+            } else if meta.path.is_ident("location_offset") {
+                location = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("expected `step_mode` or `location_offset`"))
+            }
+        })?;
+    }
+
+    let mut attributes = Vec::new();
+    for field in fields {
+        let name = field.ident.as_ref().expect("named field");
+
+        let mut skip = false;
+        let mut format = None;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("location") {
+                    location = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    Ok(())
+                } else if meta.path.is_ident("format") {
+                    let value: LitStr = meta.value()?.parse()?;
+                    format = Some(format_ident!("{}", value.value(), span = value.span()));
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `skip`, `location` or `format`"))
+                }
+            })?;
+        }
+
+        if skip {
+            continue;
+        }
+
+        let (format, columns) = match format {
+            Some(format) => (format, 1),
+            None => infer_format(&field.ty)?,
+        };
+
+        for column in 0..columns {
+            attributes.push(quote! {
+                ::wgpu::VertexAttribute {
+                    format: ::wgpu::VertexFormat::#format,
+                    offset: (::std::mem::offset_of!(#ident, #name)
+                        + #column * ::wgpu::VertexFormat::#format.size() as usize)
+                        as ::wgpu::BufferAddress,
+                    shader_location: #location,
+                }
+            });
+            location += 1;
+        }
+    }
+
+    Ok(quote! {
+        impl ::game_lib::render::types::VertexDescription for #ident {
+            type Data = Self;
+            const ATTRIBS: &'static [::wgpu::VertexAttribute] = &[#(#attributes),*];
+
+            fn desc() -> ::wgpu::VertexBufferLayout<'static> {
+                ::wgpu::VertexBufferLayout {
+                    array_stride: ::std::mem::size_of::<Self::Data>() as ::wgpu::BufferAddress,
+                    step_mode: #step_mode,
+                    attributes: Self::ATTRIBS,
+                }
+            }
+        }
+    })
+}
+
+/// Maps a field type to its vertex format and the number of consecutive attributes it
+/// occupies, recognising scalars, fixed size arrays and the `glam` vector/matrix types.
+fn infer_format(ty: &Type) -> syn::Result<(Ident, usize)> {
+    let unsupported = || {
+        syn::Error::new(
+            ty.span(),
+            "can't infer a vertex format for this type, use #[vertex(format = \"...\")]",
+        )
+    };
+
+    let (scalar, components, columns) = match ty {
+        Type::Path(path) => {
+            let segment = path.path.segments.last().ok_or_else(unsupported)?;
+            match segment.ident.to_string().as_str() {
+                "f32" | "u32" | "i32" => (scalar_name(ty).ok_or_else(unsupported)?, 1, 1),
+                "Vec2" => ("Float32", 2, 1),
+                "Vec3" => ("Float32", 3, 1),
+                "Vec4" | "Quat" => ("Float32", 4, 1),
+                "UVec2" => ("Uint32", 2, 1),
+                "UVec3" => ("Uint32", 3, 1),
+                "UVec4" => ("Uint32", 4, 1),
+                "IVec2" => ("Sint32", 2, 1),
+                "IVec3" => ("Sint32", 3, 1),
+                "IVec4" => ("Sint32", 4, 1),
+                "Mat2" => ("Float32", 2, 2),
+                "Mat3" => ("Float32", 3, 3),
+                "Mat4" => ("Float32", 4, 4),
+                _ => return Err(unsupported()),
+            }
+        }
+        Type::Array(array) => {
+            let len = array_len(&array.len).ok_or_else(unsupported)?;
+            match &*array.elem {
+                Type::Array(inner) => (
+                    scalar_name(&inner.elem).ok_or_else(unsupported)?,
+                    array_len(&inner.len).ok_or_else(unsupported)?,
+                    len,
+                ),
+                elem => (scalar_name(elem).ok_or_else(unsupported)?, len, 1),
+            }
+        }
+        _ => return Err(unsupported()),
+    };
+
+    if !(1..=4).contains(&components) {
+        return Err(unsupported());
+    }
+
+    let format = match components {
+        1 => scalar.to_string(),
+        n => format!("{}x{}", scalar, n),
+    };
+    Ok((Ident::new(&format, Span::call_site()), columns))
+}
+
+fn array_len(expr: &syn::Expr) -> Option<usize> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(int),
+            ..
+        }) => int.base10_parse().ok(),
+        _ => None,
+    }
+}
+
+fn scalar_name(ty: &Type) -> Option<&'static str> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    match path.path.get_ident()?.to_string().as_str() {
+        "f32" => Some("Float32"),
+        "u32" => Some("Uint32"),
+        "i32" => Some("Sint32"),
+        _ => None,
+    }
+}
@@ -0,0 +1,96 @@
+//! Generates lower levels of detail for an OBJ model, for `render::lod::LodGroupDesc`.
+//! Each level keeps `ratio` of the triangles of the one before and is written next to
+//! the model as `<name>_lod1.obj`, `<name>_lod2.obj` and so on.
+//!
+//! ```sh
+//! cargo run --bin simplify -- <model.obj> [levels=3] [ratio=0.5] [max_error=0.01]
+//! ```
+
+use std::{error::Error, fmt::Write as _, path::Path};
+
+use game_lib::render::{lod, types};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let Some(path) = args.first() else {
+        return Err("usage: simplify <model.obj> [levels] [ratio] [max_error]".into());
+    };
+    let levels = args.get(1).map_or(Ok(3), |arg| arg.parse::<usize>())?;
+    let options = lod::SimplifyOptions {
+        target_ratio: args.get(2).map_or(Ok(0.5), |arg| arg.parse())?,
+        max_error: args.get(3).map_or(Ok(0.01), |arg| arg.parse())?,
+        ..Default::default()
+    };
+
+    let path = Path::new(path);
+    let (models, _) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+    let mut meshes = models
+        .iter()
+        .map(|model| {
+            let mesh = &model.mesh;
+            let vertices = mesh
+                .positions
+                .chunks_exact(3)
+                .enumerate()
+                .map(|(index, position)| {
+                    let tex_coords = mesh
+                        .texcoords
+                        .get(index * 2..index * 2 + 2)
+                        .map_or(glam::Vec2::ZERO, glam::Vec2::from_slice);
+                    types::Vertex::new(glam::Vec3::from_slice(position), tex_coords)
+                })
+                .collect();
+            lod::LodMesh::new(vertices, mesh.indices.clone())
+        })
+        .collect::<Vec<_>>();
+
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or("model path has no file name")?;
+    for level in 1..=levels {
+        meshes = meshes.iter().map(|mesh| mesh.simplified(options)).collect();
+        let output = path.with_file_name(format!("{stem}_lod{level}.obj"));
+        std::fs::write(&output, to_obj(&models, &meshes))?;
+
+        let triangles = meshes
+            .iter()
+            .map(lod::LodMesh::triangle_count)
+            .sum::<usize>();
+        println!("{}: {triangles} triangles", output.display());
+    }
+    Ok(())
+}
+
+/// `meshes` as OBJ objects named after `models`, with texture coordinates where the model
+/// had them.
+fn to_obj(models: &[tobj::Model], meshes: &[lod::LodMesh]) -> String {
+    let mut obj = String::new();
+    let mut offset = 1;
+    for (model, mesh) in models.iter().zip(meshes) {
+        let textured = !model.mesh.texcoords.is_empty();
+        let _ = writeln!(obj, "o {}", model.name);
+        for vertex in &mesh.vertices {
+            let [x, y, z] = vertex.position().to_array();
+            let _ = writeln!(obj, "v {x} {y} {z}");
+            if textured {
+                let [u, v] = vertex.tex_coords().to_array();
+                let _ = writeln!(obj, "vt {u} {v}");
+            }
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            let _ = write!(obj, "f");
+            for index in triangle {
+                let index = index + offset;
+                let _ = if textured {
+                    write!(obj, " {index}/{index}")
+                } else {
+                    write!(obj, " {index}")
+                };
+            }
+            obj.push('\n');
+        }
+        offset += mesh.vertices.len() as u32;
+    }
+    obj
+}
@@ -0,0 +1,122 @@
+// Shades the scene mesh for `RenderPath::ClusteredForward`, see `render::clustered`.
+// Each fragment only loops over the point lights binned into its cluster.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+struct Clusters {
+    inverse_projection: mat4x4<f32>,
+    view: mat4x4<f32>,
+    screen_size: vec2<f32>,
+    z_near: f32,
+    z_far: f32,
+    grid: vec3<u32>,
+    light_count: u32,
+};
+
+struct ClusterRange {
+    offset: u32,
+    count: u32,
+};
+
+struct Material {
+    roughness: f32,
+    metallic: f32,
+};
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+
+@group(3) @binding(0) var<uniform> clusters: Clusters;
+@group(3) @binding(1) var<storage, read> point_lights: array<PointLight>;
+@group(3) @binding(2) var<storage, read> cluster_ranges: array<ClusterRange>;
+@group(3) @binding(3) var<storage, read> light_indices: array<u32>;
+@group(3) @binding(4) var<uniform> material: Material;
+// Ambient visibility, see `render::ssao`.
+@group(3) @binding(5) var ao_texture: texture_2d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    // The depth prepass runs this too, the main pass has to land on the same depth.
+    @builtin(position) @invariant clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+fn cluster_index(pixel: vec2<f32>, world_position: vec3<f32>) -> u32 {
+    let grid = clusters.grid;
+    let view_depth = -(clusters.view * vec4<f32>(world_position, 1.0)).z;
+    let tile = vec2<u32>(pixel / clusters.screen_size * vec2<f32>(grid.xy));
+    let slice = u32(max(
+        log(view_depth / clusters.z_near) * f32(grid.z) / log(clusters.z_far / clusters.z_near),
+        0.0,
+    ));
+    let clamped = min(vec3<u32>(tile, slice), grid - 1u);
+    return clamped.x + grid.x * (clamped.y + grid.y * clamped.z);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var surface: Surface;
+    surface.position = in.world_position;
+    // The scene's vertices have no normals, so it's the flat face's. Framebuffer y points
+    // down, so this order faces the camera.
+    surface.normal = normalize(cross(dpdy(in.world_position), dpdx(in.world_position)));
+    surface.albedo = textureSample(t_diffuse, s_diffuse, in.tex_coords).rgb;
+    surface.roughness = material.roughness;
+    surface.metallic = material.metallic;
+
+    let ao = textureLoad(ao_texture, vec2<i32>(in.clip_position.xy), 0).r;
+    var color = surface.albedo * lighting.ambient.rgb * ao
+        + shade(surface, lighting.sun_direction.xyz, lighting.sun_color.rgb);
+
+    let range = cluster_ranges[cluster_index(in.clip_position.xy, in.world_position)];
+    for (var i = 0u; i < range.count; i++) {
+        let light = point_lights[light_indices[range.offset + i]];
+        let to_light = light.position_range.xyz - surface.position;
+        let distance = length(to_light);
+        if distance < light.position_range.w {
+            let radiance = light.color.rgb * attenuation(distance, light.position_range.w);
+            color += shade(surface, to_light / distance, radiance);
+        }
+    }
+    return vec4<f32>(mix(color, lighting.fog_color.rgb, fog_amount(surface.position)), 1.0);
+}
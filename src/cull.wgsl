@@ -0,0 +1,134 @@
+// GPU-driven culling, see `render::culling`.
+
+struct Cull {
+    // Left, right, bottom, top, near, far; xyz points inside, w is the distance.
+    planes: array<vec4<f32>, 6>,
+    // The frame the depth pyramid was built from.
+    previous_view_proj: mat4x4<f32>,
+    object_count: u32,
+    // Whether the pyramid is valid and occlusion culling is on.
+    occlusion: u32,
+    pyramid_size: vec2<u32>,
+    pyramid_mips: u32,
+};
+
+struct Object {
+    model: mat4x4<f32>,
+    mesh: u32,
+};
+
+struct MeshInfo {
+    // Bounding sphere in model space, center in xyz and radius in w.
+    bounds: vec4<f32>,
+    // Where this mesh's visible instances start in `visible`.
+    instance_offset: u32,
+};
+
+// Laid out like `wgpu::util::DrawIndexedIndirectArgs`.
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+
+@group(0) @binding(0) var<uniform> cull: Cull;
+
+@group(1) @binding(0) var<storage, read> objects: array<Object>;
+@group(1) @binding(1) var<storage, read> meshes: array<MeshInfo>;
+@group(1) @binding(2) var<storage, read_write> draws: array<DrawArgs>;
+// Model matrices of visible objects, read as instance vertex data by the scene pipeline.
+@group(1) @binding(3) var<storage, read_write> visible: array<mat4x4<f32>>;
+
+// Farthest depth in each texel's footprint, one mip per halving.
+@group(2) @binding(0) var depth_pyramid: texture_2d<f32>;
+
+fn outside_frustum(center: vec3<f32>, radius: f32) -> bool {
+    for (var i = 0u; i < 6u; i += 1u) {
+        let plane = cull.planes[i];
+        if dot(plane.xyz, center) + plane.w < -radius {
+            return true;
+        }
+    }
+    return false;
+}
+
+fn occluded(center: vec3<f32>, radius: f32) -> bool {
+    var min_uv = vec2<f32>(1.0);
+    var max_uv = vec2<f32>(0.0);
+    var nearest = 1.0;
+    for (var i = 0u; i < 8u; i += 1u) {
+        let corner = center + radius * vec3<f32>(
+            select(-1.0, 1.0, (i & 1u) != 0u),
+            select(-1.0, 1.0, (i & 2u) != 0u),
+            select(-1.0, 1.0, (i & 4u) != 0u),
+        );
+        let clip = cull.previous_view_proj * vec4<f32>(corner, 1.0);
+        // Crossing the camera plane, the projection is meaningless.
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+        min_uv = min(min_uv, uv);
+        max_uv = max(max_uv, uv);
+        nearest = min(nearest, ndc.z);
+    }
+    // Off screen last frame, so the pyramid knows nothing about it.
+    if any(max_uv < vec2<f32>(0.0)) || any(min_uv > vec2<f32>(1.0)) {
+        return false;
+    }
+    min_uv = clamp(min_uv, vec2<f32>(0.0), vec2<f32>(1.0));
+    max_uv = clamp(max_uv, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // The mip where the bounds cover at most 2x2 texels.
+    let extent = (max_uv - min_uv) * vec2<f32>(cull.pyramid_size);
+    let level = min(
+        u32(ceil(log2(max(max(extent.x, extent.y), 1.0)))),
+        cull.pyramid_mips - 1u,
+    );
+    let size = max(cull.pyramid_size >> vec2<u32>(level), vec2<u32>(1u));
+    let last = vec2<i32>(size) - 1;
+    let min_texel = min(vec2<i32>(min_uv * vec2<f32>(size)), last);
+    let max_texel = min(vec2<i32>(max_uv * vec2<f32>(size)), last);
+
+    let farthest = max(
+        max(
+            textureLoad(depth_pyramid, min_texel, i32(level)).r,
+            textureLoad(depth_pyramid, vec2<i32>(max_texel.x, min_texel.y), i32(level)).r,
+        ),
+        max(
+            textureLoad(depth_pyramid, vec2<i32>(min_texel.x, max_texel.y), i32(level)).r,
+            textureLoad(depth_pyramid, max_texel, i32(level)).r,
+        ),
+    );
+    return nearest > farthest;
+}
+
+@compute @workgroup_size(64)
+fn cull_objects(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= cull.object_count {
+        return;
+    }
+
+    let object = objects[index];
+    let mesh = meshes[object.mesh];
+    let center = (object.model * vec4<f32>(mesh.bounds.xyz, 1.0)).xyz;
+    let scale = max(
+        length(object.model[0].xyz),
+        max(length(object.model[1].xyz), length(object.model[2].xyz)),
+    );
+    let radius = mesh.bounds.w * scale;
+
+    if outside_frustum(center, radius) {
+        return;
+    }
+    if cull.occlusion != 0u && occluded(center, radius) {
+        return;
+    }
+
+    let slot = atomicAdd(&draws[object.mesh].instance_count, 1u);
+    visible[mesh.instance_offset + slot] = object.model;
+}
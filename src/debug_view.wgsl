@@ -0,0 +1,110 @@
+// Scene shaders for the debug view modes, see `render::debug_view`. Inputs match
+// `shader.wgsl` so the same vertex and instance buffers can be drawn with them.
+
+// Each overdraw fragment adds this much to the count target, which
+// `debug_view_resolve.wgsl` maps onto a heat gradient.
+const OVERDRAW_STEP: f32 = 0.0625;
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) barycentric: vec3<f32>,
+};
+
+fn transform(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    out.barycentric = vec3<f32>(0.0);
+    return out;
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    return transform(model, instance);
+}
+
+// Expects a de-indexed triangle list so every triangle's corners are three consecutive
+// vertices.
+@vertex
+fn vs_barycentric(
+    @builtin(vertex_index) vertex_index: u32,
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out = transform(model, instance);
+    switch vertex_index % 3u {
+        case 0u: { out.barycentric = vec3<f32>(1.0, 0.0, 0.0); }
+        case 1u: { out.barycentric = vec3<f32>(0.0, 1.0, 0.0); }
+        default: { out.barycentric = vec3<f32>(0.0, 0.0, 1.0); }
+    }
+    return out;
+}
+
+// Used with `PolygonMode::Line`, where the rasterizer only produces the edges.
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+
+@fragment
+fn fs_barycentric(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Distance to the nearest edge in pixels, for lines about one pixel wide.
+    let edges = in.barycentric / fwidth(in.barycentric);
+    let distance = min(min(edges.x, edges.y), edges.z);
+    let coverage = 1.0 - smoothstep(0.0, 1.5, distance);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(1.0, 1.0, 1.0, coverage);
+}
+
+// The mesh has no normal attribute, so these are flat face normals reconstructed from
+// screen-space derivatives.
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(cross(dpdy(in.world_position), dpdx(in.world_position)));
+    return vec4<f32>(normal * 0.5 + 0.5, 1.0);
+}
+
+@fragment
+fn fs_uv_checker(in: VertexOutput) -> @location(0) vec4<f32> {
+    let cell = floor(in.tex_coords * 8.0);
+    let checker = abs(cell.x + cell.y) % 2.0;
+    let shade = mix(0.35, 1.0, checker);
+    // Red and green follow U and V so flipped or stretched mappings stand out.
+    return vec4<f32>(vec3<f32>(fract(in.tex_coords), 1.0) * shade, 1.0);
+}
+
+@fragment
+fn fs_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(OVERDRAW_STEP);
+}
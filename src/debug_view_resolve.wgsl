@@ -0,0 +1,48 @@
+// Full-screen passes that turn the depth buffer and the overdraw count into colours,
+// see `render::debug_view`.
+
+struct DebugViewUniform {
+    znear: f32,
+    zfar: f32,
+};
+
+@group(0) @binding(0) var depth_texture: texture_depth_2d;
+@group(0) @binding(1) var overdraw_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var<uniform> params: DebugViewUniform;
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle that covers the whole screen.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_depth(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let depth = textureLoad(depth_texture, vec2<i32>(position.xy), 0);
+    let linear = params.znear * params.zfar / (params.zfar - depth * (params.zfar - params.znear));
+    // Logarithmic so nearby geometry doesn't all end up the same shade.
+    let t = log(linear / params.znear) / log(params.zfar / params.znear);
+    return vec4<f32>(vec3<f32>(1.0 - t), 1.0);
+}
+
+fn heat(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 4.0;
+    if x < 1.0 {
+        return mix(vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), x);
+    } else if x < 2.0 {
+        return mix(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(0.0, 1.0, 0.0), x - 1.0);
+    } else if x < 3.0 {
+        return mix(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 1.0, 0.0), x - 2.0);
+    }
+    return mix(vec3<f32>(1.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), x - 3.0);
+}
+
+@fragment
+fn fs_overdraw(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // Each layer adds `OVERDRAW_STEP` from `debug_view.wgsl`, so 16 or more layers
+    // saturate to red.
+    let layers = textureLoad(overdraw_texture, vec2<i32>(position.xy), 0).r;
+    return vec4<f32>(heat(layers), 1.0);
+}
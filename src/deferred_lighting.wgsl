@@ -0,0 +1,96 @@
+// Lights the G-buffer, see `render::deferred`. The sun and ambient light are one
+// full-screen pass, each point light is drawn as a sphere around its range.
+
+@group(0) @binding(0) var albedo_texture: texture_2d<f32>;
+@group(0) @binding(1) var normal_texture: texture_2d<f32>;
+@group(0) @binding(2) var material_texture: texture_2d<f32>;
+@group(0) @binding(3) var depth_texture: texture_depth_2d;
+// Ambient visibility, see `render::ssao`.
+@group(0) @binding(4) var ao_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var<uniform> lighting: Lighting;
+
+// Nothing was drawn here.
+fn is_empty(pixel: vec2<f32>) -> bool {
+    return textureLoad(depth_texture, vec2<i32>(pixel), 0) >= 1.0;
+}
+
+fn load_surface(pixel: vec2<f32>) -> Surface {
+    let texel = vec2<i32>(pixel);
+    let depth = textureLoad(depth_texture, texel, 0);
+    let uv = pixel / vec2<f32>(textureDimensions(depth_texture));
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = lighting.inverse_view_proj * ndc;
+    let material = textureLoad(material_texture, texel, 0);
+
+    var surface: Surface;
+    surface.position = world.xyz / world.w;
+    surface.normal = textureLoad(normal_texture, texel, 0).xyz;
+    surface.albedo = textureLoad(albedo_texture, texel, 0).rgb;
+    surface.roughness = material.r;
+    surface.metallic = material.g;
+    return surface;
+}
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle that covers the whole screen.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Replaces the frame where the G-buffer has a surface, leaving the clear color around.
+@fragment
+fn fs_directional(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    if is_empty(position.xy) {
+        discard;
+    }
+    let surface = load_surface(position.xy);
+    let ao = textureLoad(ao_texture, vec2<i32>(position.xy), 0).r;
+    let color = surface.albedo * lighting.ambient.rgb * ao
+        + shade(surface, lighting.sun_direction.xyz, lighting.sun_color.rgb);
+    return vec4<f32>(mix(color, lighting.fog_color.rgb, fog_amount(surface.position)), 1.0);
+}
+
+struct VolumeInput {
+    // Unit sphere.
+    @location(0) position: vec3<f32>,
+    @location(1) light_position_range: vec4<f32>,
+    @location(2) light_color: vec4<f32>,
+};
+
+struct VolumeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) light_position_range: vec4<f32>,
+    @location(1) @interpolate(flat) light_color: vec3<f32>,
+};
+
+@vertex
+fn vs_volume(in: VolumeInput) -> VolumeOutput {
+    let world = in.light_position_range.xyz + in.position * in.light_position_range.w;
+
+    var out: VolumeOutput;
+    out.clip_position = lighting.view_proj * vec4<f32>(world, 1.0);
+    out.light_position_range = in.light_position_range;
+    out.light_color = in.light_color.rgb;
+    return out;
+}
+
+// Added to the frame wherever the sphere's back faces are behind the scene.
+@fragment
+fn fs_point(in: VolumeOutput) -> @location(0) vec4<f32> {
+    if is_empty(in.clip_position.xy) {
+        discard;
+    }
+    let surface = load_surface(in.clip_position.xy);
+    let to_light = in.light_position_range.xyz - surface.position;
+    let distance = length(to_light);
+    let range = in.light_position_range.w;
+    if distance >= range {
+        discard;
+    }
+    // The sun pass already blended in the fog color, what's added here is covered by it.
+    let radiance = in.light_color * attenuation(distance, range)
+        * (1.0 - fog_amount(surface.position));
+    return vec4<f32>(shade(surface, to_light / distance, radiance), 0.0);
+}
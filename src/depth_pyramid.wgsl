@@ -0,0 +1,31 @@
+// Hi-Z depth pyramid reduction, see `render::culling::pyramid`. Mip 0 is copied from the
+// depth buffer by `depth_pyramid_copy.wgsl`.
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var destination: texture_storage_2d<r32float, write>;
+
+// Keeps the farthest depth of the 2x2 texels below, widened to 3 at the last row or
+// column of an odd-sized source so no texel is skipped.
+@compute @workgroup_size(8, 8)
+fn downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(destination);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    let source_size = vec2<i32>(textureDimensions(source));
+    let base = vec2<i32>(id.xy) * 2;
+    let extra = vec2<i32>(
+        select(0, 1, (source_size.x & 1) == 1 && id.x == size.x - 1u),
+        select(0, 1, (source_size.y & 1) == 1 && id.y == size.y - 1u),
+    );
+
+    var depth = 0.0;
+    for (var y = 0; y <= 1 + extra.y; y += 1) {
+        for (var x = 0; x <= 1 + extra.x; x += 1) {
+            let texel = min(base + vec2<i32>(x, y), source_size - 1);
+            depth = max(depth, textureLoad(source, texel, 0).r);
+        }
+    }
+    textureStore(destination, id.xy, vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
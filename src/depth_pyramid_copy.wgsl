@@ -0,0 +1,14 @@
+// Copies the depth buffer into mip 0 of the Hi-Z depth pyramid, see
+// `render::culling::pyramid`.
+
+@group(0) @binding(0) var depth: texture_depth_2d;
+@group(0) @binding(1) var destination: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn copy_depth(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(destination)) {
+        return;
+    }
+    let value = textureLoad(depth, vec2<i32>(id.xy), 0);
+    textureStore(destination, id.xy, vec4<f32>(value, 0.0, 0.0, 0.0));
+}
@@ -19,36 +19,224 @@ use crate::{input, render, GameError};
 pub struct Game<'a> {
     score: i32,
     input: input::Input,
+    screenshot_held: bool,
+    show_debug: bool,
+    debug_held: bool,
+    view_mode_held: bool,
+    show_panel: bool,
+    panel_held: bool,
     pub render: render::Render<'a>,
 }
 
 impl<'a> Game<'a> {
-    pub async fn new(window: Arc<Window>) -> Result<Self, GameError> {
+    pub async fn new(
+        window: Arc<Window>,
+        render_path: render::RenderPath,
+    ) -> Result<Self, GameError> {
         let input = input::Input::new();
+        let mut render = render::Render::new(window.clone(), render_path).await?;
+        // A few colored lights over the grid of instances, for the render paths with point lights.
+        render.lights().points.extend(
+            [
+                (
+                    glam::Vec3::new(-3.0, 1.0, -3.0),
+                    glam::Vec3::new(1.0, 0.3, 0.2),
+                ),
+                (
+                    glam::Vec3::new(2.0, 1.0, -3.0),
+                    glam::Vec3::new(0.2, 1.0, 0.3),
+                ),
+                (
+                    glam::Vec3::new(-3.0, 1.0, 2.0),
+                    glam::Vec3::new(0.2, 0.4, 1.0),
+                ),
+                (
+                    glam::Vec3::new(2.0, 1.0, 2.0),
+                    glam::Vec3::new(1.0, 0.9, 0.4),
+                ),
+            ]
+            .map(|(position, color)| render::light::PointLight::new(position, color, 4.0, 4.0)),
+        );
+        if render.render_path() == render::RenderPath::ClusteredForward {
+            // Clustering keeps hundreds of small lights cheap, spread them in a spiral.
+            render.lights().points.extend((0..256).map(|i| {
+                let angle = i as f32 * 2.4;
+                let radius = (i as f32 / 256.0).sqrt() * 8.0;
+                let color = glam::Vec3::new(angle.sin(), (angle + 2.1).sin(), (angle + 4.2).sin())
+                    * 0.5
+                    + 0.5;
+                render::light::PointLight::new(
+                    glam::Vec3::new(angle.cos() * radius, 0.5, angle.sin() * radius),
+                    color,
+                    2.0,
+                    1.5,
+                )
+            }));
+        }
         Ok(Self {
             score: 0,
             input,
-            render: render::Render::new(window.clone()).await?,
+            screenshot_held: false,
+            show_debug: false,
+            debug_held: false,
+            view_mode_held: false,
+            show_panel: false,
+            panel_held: false,
+            render,
         })
     }
 
+    #[tracing::instrument(name = "Game::update", skip(self))]
     pub fn update(&mut self, delta: f64) {
         self.render.update(&self.input, delta);
+
+        if self.show_debug {
+            let debug = self.render.debug_draw();
+            debug.grid(glam::Vec3::ZERO, 20, 1.0, render::debug_draw::GREY);
+            debug.on_top().axes(glam::Mat4::IDENTITY, 1.0);
+        }
     }
 
+    #[tracing::instrument(name = "Game::draw", skip_all)]
     pub fn draw(&mut self, window: &Arc<Window>) -> Result<(), GameError> {
         window.set_title(&format!("Score: {}", self.score));
+        let text = self.render.text();
+        let font = text.default_font();
+        text.draw_screen(
+            font,
+            &format!("Score: {}", self.score),
+            glam::Vec2::new(16.0, 16.0),
+            &render::text::TextStyle::default(),
+        );
+
+        self.debug_panel();
         self.render.render(window, &self.input)
     }
 
+    /// Toggled with F1.
+    fn debug_panel(&mut self) {
+        let mut open = self.show_panel;
+        let mut show_debug = self.show_debug;
+        let mut view_mode = self.render.view_mode();
+        let mut transparency_mode = self.render.transparency_mode();
+        let mut profiling = self.render.profiler().is_enabled();
+        let cpu_ms = self.render.profiler().average_cpu_ms();
+        let main_pass_ms = self.render.profiler().average_pass_ms("main");
+        let upload_bytes = self.render.uploader().stats().average_bytes_per_frame();
+        let gpu_culling = self.render.gpu_scene().is_some();
+        let mut gpu_driven = self.render.is_gpu_driven();
+        let mut occlusion = self
+            .render
+            .gpu_scene()
+            .is_some_and(|gpu_scene| gpu_scene.occlusion);
+        let render_path = self.render.render_path();
+        let mut ssao = self.render.ssao().map(|ssao| ssao.enabled);
+        let mut sky = self.render.atmosphere().sky.enabled;
+
+        self.render.ui().frame(|ctx| {
+            egui::Window::new("Debug").open(&mut open).show(ctx, |ui| {
+                ui.checkbox(&mut show_debug, "Grid and axes (F3)");
+                egui::ComboBox::from_label("View mode (F4)")
+                    .selected_text(format!("{:?}", view_mode))
+                    .show_ui(ui, |ui| {
+                        for mode in render::debug_view::ViewMode::iter() {
+                            ui.selectable_value(&mut view_mode, mode, format!("{:?}", mode));
+                        }
+                    });
+                egui::ComboBox::from_label("Transparency")
+                    .selected_text(format!("{:?}", transparency_mode))
+                    .show_ui(ui, |ui| {
+                        for mode in render::transparent::TransparencyMode::iter() {
+                            ui.selectable_value(
+                                &mut transparency_mode,
+                                mode,
+                                format!("{:?}", mode),
+                            );
+                        }
+                    });
+                ui.label(format!("Render path: {}", render_path));
+                match &mut ssao {
+                    Some(enabled) => {
+                        ui.checkbox(enabled, "SSAO");
+                    }
+                    None => {
+                        ui.add_enabled(false, egui::Checkbox::new(&mut false, "SSAO"))
+                            .on_disabled_hover_text(
+                                "Needs GAME_RENDER_PATH=deferred or clustered_forward",
+                            );
+                    }
+                }
+                ui.checkbox(&mut sky, "Sky");
+                ui.checkbox(&mut profiling, "GPU profiler");
+                if profiling {
+                    ui.label(format!("CPU frame: {:.2} ms", cpu_ms));
+                    ui.label(format!("Main pass: {:.2} ms", main_pass_ms));
+                }
+                ui.label(format!("Uploads: {:.0} B/frame", upload_bytes));
+                if gpu_culling {
+                    ui.checkbox(&mut gpu_driven, "GPU-driven culling");
+                    if gpu_driven {
+                        ui.checkbox(&mut occlusion, "Hi-Z occlusion");
+                    }
+                }
+            });
+        });
+
+        self.show_panel = open;
+        self.show_debug = show_debug;
+        self.render.set_view_mode(view_mode);
+        self.render.set_transparency_mode(transparency_mode);
+        self.render.profiler().set_enabled(profiling);
+        self.render.set_gpu_driven(gpu_driven);
+        if let Some(gpu_scene) = self.render.gpu_scene() {
+            gpu_scene.occlusion = occlusion;
+        }
+        if let (Some(enabled), Some(ssao)) = (ssao, self.render.ssao()) {
+            ssao.enabled = enabled;
+        }
+        self.render.atmosphere().sky.enabled = sky;
+    }
+
+    #[tracing::instrument(name = "Game::handle_event", skip_all)]
     pub fn handle_event(&mut self, event: &Event<()>) -> bool {
         let mut continue_render = true;
-        self.input.event(event);
+        // Events the UI uses (clicks on widgets, typing into fields) don't reach game
+        // bindings.
+        if !self.render.ui().handle_event(event) {
+            self.input.event(event);
+        }
 
         if self.input.get_bool(input::KeyboardButton::Escape) {
             continue_render = false;
         }
 
+        // Only the press edge, so holding F12 doesn't take a screenshot every frame.
+        let screenshot = self.input.get_bool(input::KeyboardButton::F12);
+        if screenshot && !self.screenshot_held {
+            self.render.request_screenshot(None);
+        }
+        self.screenshot_held = screenshot;
+
+        let debug = self.input.get_bool(input::KeyboardButton::F3);
+        if debug && !self.debug_held {
+            self.show_debug = !self.show_debug;
+        }
+        self.debug_held = debug;
+
+        let view_mode = self.input.get_bool(input::KeyboardButton::F4);
+        if view_mode && !self.view_mode_held {
+            let mode = self.render.view_mode().next();
+            log::info!("View mode: {:?}", mode);
+            self.render.set_view_mode(mode);
+        }
+        self.view_mode_held = view_mode;
+
+        let panel = self.input.get_bool(input::KeyboardButton::F1);
+        if panel && !self.panel_held {
+            self.show_panel = !self.show_panel;
+        }
+        self.panel_held = panel;
+
         match event {
             Event::WindowEvent {
                 event: ref window_event,
@@ -0,0 +1,78 @@
+// Writes the scene mesh into the G-buffer, see `render::deferred`.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+struct Material {
+    roughness: f32,
+    metallic: f32,
+};
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0) var<uniform> material: Material;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+struct GBuffer {
+    @location(0) albedo: vec4<f32>,
+    // World space, in xyz.
+    @location(1) normal: vec4<f32>,
+    // Roughness in r, metallic in g.
+    @location(2) material: vec4<f32>,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> GBuffer {
+    // The scene's vertices have no normals, so it's the flat face's. Framebuffer y points
+    // down, so this order faces the camera.
+    let normal = normalize(cross(dpdy(in.world_position), dpdx(in.world_position)));
+
+    var out: GBuffer;
+    out.albedo = vec4<f32>(textureSample(t_diffuse, s_diffuse, in.tex_coords).rgb, 1.0);
+    out.normal = vec4<f32>(normal, 0.0);
+    out.material = vec4<f32>(material.roughness, material.metallic, 0.0, 0.0);
+    return out;
+}
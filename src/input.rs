@@ -93,6 +93,7 @@ impl Input {
             .extend(iter);
     }
 
+    #[tracing::instrument(name = "Input::event", skip_all)]
     pub fn event(&mut self, event: &Event<()>) -> () {
         match event {
             Event::WindowEvent { event, .. } => match event {
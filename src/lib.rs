@@ -1,6 +1,10 @@
+// Lets `game-derive` expand to `::game_lib::...` paths from inside this crate too.
+extern crate self as game_lib;
+
 pub mod game;
 pub mod input;
 pub mod render;
+pub mod trace;
 
 pub type Result<T> = std::result::Result<T, GameError>;
 
@@ -32,6 +36,27 @@ pub async fn run() -> Result<()> {
         }
     }
 
+    // Set GAME_TRACE=trace.json to stream a Chrome trace of the game loop to that file,
+    // closed when the loop exits.
+    #[cfg(not(target_arch = "wasm32"))]
+    let _trace = match std::env::var("GAME_TRACE") {
+        Ok(path) => Some(trace::init_chrome_trace(path)?),
+        Err(_) => None,
+    };
+
+    // Set GAME_RENDER_PATH=deferred or clustered_forward to pick another
+    // `render::RenderPath`.
+    #[cfg(not(target_arch = "wasm32"))]
+    let render_path = match std::env::var("GAME_RENDER_PATH") {
+        Ok(name) => name.parse().unwrap_or_else(|_| {
+            log::warn!("Unknown render path '{}', using the default", name);
+            render::RenderPath::default()
+        }),
+        Err(_) => render::RenderPath::default(),
+    };
+    #[cfg(target_arch = "wasm32")]
+    let render_path = render::RenderPath::default();
+
     let event_loop = EventLoop::new()?;
     let window_size = winit::dpi::PhysicalSize::new(450, 400);
     let window = Arc::new(
@@ -62,7 +87,7 @@ pub async fn run() -> Result<()> {
     // dispatched any events. This is ideal for games and similar applications.
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let game = Game::new(window.clone()).await?;
+    let game = Game::new(window.clone(), render_path).await?;
 
     game_loop(
         event_loop,
@@ -71,9 +96,10 @@ pub async fn run() -> Result<()> {
         240,
         0.1,
         |g| {
+            let _span = tracing::info_span!("update_step").entered();
             g.game.update(g.last_frame_time());
         },
-        |g| match g.game.draw(&g.window) {
+        |g| match tracing::info_span!("render_step").in_scope(|| g.game.draw(&g.window)) {
             Ok(_) => (),
             Err(GameError::SurfaceError(surface_error)) => match surface_error {
                 // Reconfigure the surface if lost
@@ -96,6 +122,7 @@ pub async fn run() -> Result<()> {
             }
         },
         |g, event| {
+            let _span = tracing::info_span!("event_step").entered();
             if !g.game.handle_event(event) {
                 g.exit();
             }
@@ -123,6 +150,30 @@ pub enum GameError {
     SurfaceError(#[from] wgpu::SurfaceError),
     #[error("Image error: {0}")]
     ImageError(#[from] image::ImageError),
+    #[error("Shader parse error: {0}")]
+    ShaderParseError(String),
+    #[error("Shader validation error: {0}")]
+    ShaderValidationError(String),
+    #[error("Shader reflection error: {0}")]
+    ShaderReflectionError(String),
+    #[error("Uniform ring buffer is full ({0} bytes)")]
+    UniformRingFull(u64),
+    #[error("Upload offset {0} and size {1} must be multiples of 4")]
+    UnalignedUpload(u64, u64),
+    #[error("Invalid texture upload: {0}")]
+    InvalidTextureUpload(String),
+    #[error("Trace error: {0}")]
+    TraceError(String),
+    #[error("Font error: {0}")]
+    FontError(String),
+    #[error("Atlas error: {0}")]
+    AtlasError(String),
+    #[error("Animation error: {0}")]
+    AnimationError(String),
+    #[error("glTF error: {0}")]
+    GltfError(String),
+    #[error("LOD error: {0}")]
+    LodError(String),
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -0,0 +1,96 @@
+// Bins point lights into view space froxels, see `render::clustered`. One invocation per
+// cluster tests every light's sphere against the cluster's bounding box.
+
+const MAX_LIGHTS_PER_CLUSTER: u32 = 64u;
+
+struct Clusters {
+    inverse_projection: mat4x4<f32>,
+    view: mat4x4<f32>,
+    screen_size: vec2<f32>,
+    z_near: f32,
+    z_far: f32,
+    grid: vec3<u32>,
+    light_count: u32,
+};
+
+struct PointLight {
+    position_range: vec4<f32>,
+    color: vec4<f32>,
+};
+
+struct ClusterRange {
+    offset: u32,
+    count: u32,
+};
+
+@group(0) @binding(0) var<uniform> clusters: Clusters;
+@group(0) @binding(1) var<storage, read> point_lights: array<PointLight>;
+@group(0) @binding(2) var<storage, read_write> cluster_ranges: array<ClusterRange>;
+@group(0) @binding(3) var<storage, read_write> light_indices: array<u32>;
+// Cleared before every dispatch.
+@group(0) @binding(4) var<storage, read_write> index_count: atomic<u32>;
+
+// Slices are spaced exponentially, so clusters keep about the same shape with distance.
+fn slice_depth(slice: u32) -> f32 {
+    return clusters.z_near * pow(clusters.z_far / clusters.z_near, f32(slice) / f32(clusters.grid.z));
+}
+
+// View space point at depth 1 through `ndc`.
+fn view_ray(ndc: vec2<f32>) -> vec3<f32> {
+    let far = clusters.inverse_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let point = far.xyz / far.w;
+    return point / -point.z;
+}
+
+@compute @workgroup_size(64)
+fn cs_bin(@builtin(global_invocation_id) id: vec3<u32>) {
+    let grid = clusters.grid;
+    let cluster = id.x;
+    if cluster >= grid.x * grid.y * grid.z {
+        return;
+    }
+    let x = cluster % grid.x;
+    let y = cluster / grid.x % grid.y;
+    let z = cluster / (grid.x * grid.y);
+
+    // Tile rows start at the top of the screen.
+    let uv_min = vec2<f32>(f32(x), f32(y)) / vec2<f32>(grid.xy);
+    let uv_max = vec2<f32>(f32(x + 1u), f32(y + 1u)) / vec2<f32>(grid.xy);
+    let ndc_min = vec2<f32>(uv_min.x * 2.0 - 1.0, 1.0 - uv_max.y * 2.0);
+    let ndc_max = vec2<f32>(uv_max.x * 2.0 - 1.0, 1.0 - uv_min.y * 2.0);
+    let near = slice_depth(z);
+    let far = slice_depth(z + 1u);
+
+    var box_min = vec3<f32>(3.4e38);
+    var box_max = vec3<f32>(-3.4e38);
+    for (var corner = 0u; corner < 4u; corner++) {
+        let ray = view_ray(vec2<f32>(
+            select(ndc_min.x, ndc_max.x, (corner & 1u) != 0u),
+            select(ndc_min.y, ndc_max.y, (corner & 2u) != 0u),
+        ));
+        box_min = min(box_min, min(ray * near, ray * far));
+        box_max = max(box_max, max(ray * near, ray * far));
+    }
+
+    var found: array<u32, MAX_LIGHTS_PER_CLUSTER>;
+    var count = 0u;
+    for (var i = 0u; i < clusters.light_count && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+        let light = point_lights[i];
+        let center = (clusters.view * vec4<f32>(light.position_range.xyz, 1.0)).xyz;
+        let offset = clamp(center, box_min, box_max) - center;
+        let range = light.position_range.w;
+        if dot(offset, offset) < range * range {
+            found[count] = i;
+            count++;
+        }
+    }
+
+    // Clusters past the end of the index list lose their lights rather than overflowing.
+    let offset = atomicAdd(&index_count, count);
+    let capacity = arrayLength(&light_indices);
+    let stored = min(count, capacity - min(offset, capacity));
+    for (var i = 0u; i < stored; i++) {
+        light_indices[offset + i] = found[i];
+    }
+    cluster_ranges[cluster] = ClusterRange(offset, stored);
+}
@@ -0,0 +1,100 @@
+// Shared by the lit shaders, which are compiled with this file prepended. Each one
+// declares `lighting` at its own group, see `light::LightBuffers`.
+
+struct Lighting {
+    view_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    // Towards the sun.
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    ambient: vec4<f32>,
+    fog_color: vec4<f32>,
+    // Linear start and end in x and y, exponential density in z.
+    fog_distance: vec4<f32>,
+    // Density, falloff and base height.
+    fog_height: vec4<f32>,
+    point_count: u32,
+    // 0 for no distance fog, then linear, exponential and exponential squared.
+    fog_mode: u32,
+};
+
+struct PointLight {
+    position_range: vec4<f32>,
+    color: vec4<f32>,
+};
+
+struct Surface {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    albedo: vec3<f32>,
+    roughness: f32,
+    metallic: f32,
+};
+
+// Lambert diffuse and normalized Blinn-Phong specular, from a light in direction
+// `to_light` arriving with `radiance`.
+fn shade(surface: Surface, to_light: vec3<f32>, radiance: vec3<f32>) -> vec3<f32> {
+    let n_dot_l = max(dot(surface.normal, to_light), 0.0);
+    if n_dot_l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let to_eye = normalize(lighting.eye.xyz - surface.position);
+    let half_vector = normalize(to_light + to_eye);
+    let shininess = exp2(10.0 * (1.0 - surface.roughness) + 1.0);
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metallic);
+    let specular = f0 * pow(max(dot(surface.normal, half_vector), 0.0), shininess)
+        * (shininess + 8.0) / 8.0;
+    let diffuse = surface.albedo * (1.0 - surface.metallic);
+    return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// Inverse square falloff, windowed to reach 0 at `range`.
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = saturate(1.0 - ratio * ratio * ratio * ratio);
+    return window * window / (distance * distance + 1.0);
+}
+
+// How much of the fog color covers a surface at `position`.
+fn fog_amount(position: vec3<f32>) -> f32 {
+    let ray = position - lighting.eye.xyz;
+    let distance = length(ray);
+    var distance_fog = 0.0;
+    switch lighting.fog_mode {
+        case 1u: {
+            let start = lighting.fog_distance.x;
+            distance_fog = saturate((distance - start) / (lighting.fog_distance.y - start));
+        }
+        case 2u: {
+            distance_fog = 1.0 - exp(-lighting.fog_distance.z * distance);
+        }
+        case 3u: {
+            let optical_depth = lighting.fog_distance.z * distance;
+            distance_fog = 1.0 - exp(-optical_depth * optical_depth);
+        }
+        default: {}
+    }
+
+    // Density falls off exponentially with height, integrated along the ray from the eye.
+    let falloff = max(lighting.fog_height.y, 1e-4);
+    let eye_density = lighting.fog_height.x
+        * exp(-falloff * (lighting.eye.y - lighting.fog_height.z));
+    let height_change = falloff * ray.y;
+    var along_ray = 1.0;
+    if abs(height_change) > 1e-4 {
+        along_ray = (1.0 - exp(-height_change)) / height_change;
+    }
+    let height_fog = 1.0 - exp(-eye_density * distance * along_ray);
+    return 1.0 - (1.0 - distance_fog) * (1.0 - height_fog);
+}
+
+// The sun and ambient light with the fog over them, for the meshes drawn outside the lit
+// render paths. They have no material, so they get `deferred::Material::default()`, and
+// no ambient occlusion, see `render::ssao`.
+fn sunlit(position: vec3<f32>, normal: vec3<f32>, albedo: vec3<f32>) -> vec3<f32> {
+    let surface = Surface(position, normal, albedo, 0.6, 0.0);
+    let color = albedo * lighting.ambient.rgb
+        + shade(surface, lighting.sun_direction.xyz, lighting.sun_color.rgb);
+    return mix(color, lighting.fog_color.rgb, fog_amount(position));
+}
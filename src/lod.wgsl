@@ -0,0 +1,83 @@
+// Instanced meshes with per-instance level of detail, see `render::lod`.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    // How much of the instance to draw while it cross-fades between levels: at 0.4 the
+    // pixels where the dither pattern is below 0.4, at -0.4 the ones where it's 0.4 and
+    // above, so the two levels fill in each other's holes. 1 draws everything.
+    @location(9) fade: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) fade: f32,
+    @location(2) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    out.fade = instance.fade;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+// 4x4 ordered dither threshold of a pixel, in (0, 1).
+fn dither(pixel: vec2<f32>) -> f32 {
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    let cell = vec2<u32>(pixel) % 4u;
+    return (bayer[cell.y * 4u + cell.x] + 0.5) / 16.0;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let threshold = dither(in.clip_position.xy);
+    if (in.fade >= 0.0 && threshold >= in.fade) || (in.fade < 0.0 && threshold < -in.fade) {
+        discard;
+    }
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // No vertex normals, as in `shader.wgsl`.
+    let normal = normalize(cross(dpdy(in.world_position), dpdx(in.world_position)));
+    return vec4<f32>(sunlit(in.world_position, normal, color.rgb), color.a);
+}
@@ -0,0 +1,93 @@
+// Morph target shader, see `render::morph`.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1) var s_diffuse: sampler;
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+struct MorphDelta {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+    tangent: vec4<f32>,
+};
+
+// Target-major: every vertex's delta for the first target, then the second, and so on.
+@group(2) @binding(0) var<storage, read> deltas: array<MorphDelta>;
+// One per target.
+@group(2) @binding(1) var<storage, read> weights: array<f32>;
+
+@group(3) @binding(0) var<uniform> lighting: Lighting;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tangent: vec4<f32>,
+    @location(3) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    in: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let target_count = arrayLength(&weights);
+    let vertex_count = arrayLength(&deltas) / target_count;
+
+    var position = in.position;
+    var normal = in.normal;
+    var tangent = in.tangent.xyz;
+    for (var i = 0u; i < target_count; i += 1u) {
+        let weight = weights[i];
+        if weight == 0.0 {
+            continue;
+        }
+        let delta = deltas[i * vertex_count + vertex_index];
+        position += delta.position.xyz * weight;
+        normal += delta.normal.xyz * weight;
+        tangent += delta.tangent.xyz * weight;
+    }
+
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    let world_position = model_matrix * vec4<f32>(position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    // Instances only rotate and translate, so the model matrix works for directions too.
+    out.normal = (model_matrix * vec4<f32>(normal, 0.0)).xyz;
+    out.tangent = vec4<f32>((model_matrix * vec4<f32>(tangent, 0.0)).xyz, in.tangent.w);
+    out.tex_coords = in.tex_coords;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(sunlit(in.world_position, normalize(in.normal), color.rgb), color.a);
+}
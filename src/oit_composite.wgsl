@@ -0,0 +1,27 @@
+// Blends the weighted blended transparency targets over the frame, see
+// `render::transparent::oit`.
+
+@group(0) @binding(0) var accum_texture: texture_2d<f32>;
+@group(0) @binding(1) var revealage_texture: texture_2d<f32>;
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle that covers the whole screen.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Blended with `SrcAlpha, OneMinusSrcAlpha`, so alpha is the coverage of everything
+// transparent at the pixel.
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    let revealage = textureLoad(revealage_texture, texel, 0).r;
+    if revealage >= 1.0 {
+        discard;
+    }
+    let accum = textureLoad(accum_texture, texel, 0);
+    // Clamped as half floats saturate under a lot of overlap.
+    let average = accum.rgb / clamp(accum.a, 1e-4, 5e4);
+    return vec4<f32>(average, 1.0 - revealage);
+}
@@ -0,0 +1,97 @@
+// Particle billboards, see `render::particles`.
+
+struct Billboard {
+    // Camera axes in world space, so quads always face the camera.
+    right: vec4<f32>,
+    up: vec4<f32>,
+};
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> billboard: Billboard;
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+
+struct InstanceInput {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // -1 to 1 across the quad.
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    // Distance in front of the camera, for the weighted blended weight.
+    @location(2) view_depth: f32,
+    @location(3) world_position: vec3<f32>,
+};
+
+// Drawn as a four vertex triangle strip per instance.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, instance: InstanceInput) -> VertexOutput {
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u)) * 2.0 - 1.0;
+    let offset = (billboard.right.xyz * corner.x + billboard.up.xyz * corner.y) * instance.size * 0.5;
+
+    let world_position = instance.position + offset;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.corner = corner;
+    out.color = instance.color;
+    out.view_depth = out.clip_position.w;
+    return out;
+}
+
+// Soft round particles without needing a texture.
+fn particle_color(in: VertexOutput) -> vec4<f32> {
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(in.corner));
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
+
+// Alpha blended particles, covered by the fog like the meshes behind them.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = particle_color(in);
+    let fogged = mix(color.rgb, lighting.fog_color.rgb, fog_amount(in.world_position));
+    return vec4<f32>(fogged, color.a);
+}
+
+// Additive particles fade out in the fog instead, adding the fog color would brighten
+// whatever is behind them.
+@fragment
+fn fs_additive(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = particle_color(in);
+    return vec4<f32>(color.rgb * (1.0 - fog_amount(in.world_position)), color.a);
+}
+
+struct WeightedBlendedOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: vec4<f32>,
+};
+
+// Same weight as `transparent.wgsl`.
+fn weight(view_depth: f32, alpha: f32) -> f32 {
+    let z = view_depth;
+    return alpha * clamp(10.0 / (1e-5 + pow(z / 5.0, 2.0) + pow(z / 200.0, 6.0)), 1e-2, 3e3);
+}
+
+// Alpha blended particles in the weighted blended transparency pass, see
+// `render::transparent::oit`.
+@fragment
+fn fs_weighted_blended(in: VertexOutput) -> WeightedBlendedOutput {
+    let color = particle_color(in);
+    let fogged = mix(color.rgb, lighting.fog_color.rgb, fog_amount(in.world_position));
+    let w = weight(in.view_depth, color.a);
+
+    var out: WeightedBlendedOutput;
+    out.accum = vec4<f32>(fogged * color.a, color.a) * w;
+    out.revealage = vec4<f32>(color.a);
+    return out;
+}
@@ -0,0 +1,154 @@
+// Particle simulation, see `render::particles`. `render::particles::simulate` mirrors this
+// on the CPU where compute shaders are unavailable, so keep the two in step.
+
+const CURVE_SAMPLES: u32 = 16u;
+const MAX_FORCES: u32 = 4u;
+const FORCE_DIRECTIONAL: u32 = 0u;
+const FORCE_POINT: u32 = 1u;
+
+struct Emitter {
+    position: vec3<f32>,
+    spawn_radius: f32,
+    velocity: vec3<f32>,
+    velocity_spread: f32,
+    gravity: vec3<f32>,
+    drag: f32,
+    lifetime: f32,
+    lifetime_variance: f32,
+    delta: f32,
+    seed: u32,
+    // Slots `spawn_start..spawn_start + spawn_count`, wrapping at `capacity`, respawn
+    // this frame.
+    spawn_start: u32,
+    spawn_count: u32,
+    capacity: u32,
+    force_count: u32,
+    // Directional: acceleration in xyz. Point: position in xyz, strength in w.
+    forces: array<vec4<f32>, 4>,
+    force_kinds: vec4<u32>,
+    // Curves over normalized age, `CURVE_SAMPLES` evenly spaced samples each.
+    speed: array<vec4<f32>, 4>,
+    size: array<vec4<f32>, 4>,
+    color: array<vec4<f32>, 16>,
+};
+
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    // Dead once `age` reaches it.
+    lifetime: f32,
+};
+
+struct ParticleInstance {
+    position: vec3<f32>,
+    size: f32,
+    color: vec4<f32>,
+};
+
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+};
+
+@group(0) @binding(0) var<uniform> emitter: Emitter;
+
+@group(1) @binding(0) var<storage, read_write> particles: array<Particle>;
+// Live particles, packed from the start and counted in `draw.instance_count`.
+@group(1) @binding(1) var<storage, read_write> instances: array<ParticleInstance>;
+@group(1) @binding(2) var<storage, read_write> draw: DrawArgs;
+
+// PCG hash, see "Hash Functions for GPU Rendering" (Jarzynski and Olano, 2020).
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(state: ptr<function, u32>) -> f32 {
+    *state = hash(*state);
+    return f32(*state) / 4294967295.0;
+}
+
+fn random_direction(state: ptr<function, u32>) -> vec3<f32> {
+    let z = random(state) * 2.0 - 1.0;
+    let angle = random(state) * 6.2831855;
+    let radius = sqrt(max(1.0 - z * z, 0.0));
+    return vec3<f32>(radius * cos(angle), radius * sin(angle), z);
+}
+
+// Index of the sample at or before `t` and how far `t` is towards the next one.
+fn curve_position(t: f32) -> vec2<f32> {
+    let x = clamp(t, 0.0, 1.0) * f32(CURVE_SAMPLES - 1u);
+    let index = min(floor(x), f32(CURVE_SAMPLES - 2u));
+    return vec2<f32>(index, x - index);
+}
+
+fn speed_at(t: f32) -> f32 {
+    let at = curve_position(t);
+    let i = u32(at.x);
+    let j = i + 1u;
+    return mix(emitter.speed[i / 4u][i % 4u], emitter.speed[j / 4u][j % 4u], at.y);
+}
+
+fn size_at(t: f32) -> f32 {
+    let at = curve_position(t);
+    let i = u32(at.x);
+    let j = i + 1u;
+    return mix(emitter.size[i / 4u][i % 4u], emitter.size[j / 4u][j % 4u], at.y);
+}
+
+fn color_at(t: f32) -> vec4<f32> {
+    let at = curve_position(t);
+    let i = u32(at.x);
+    return mix(emitter.color[i], emitter.color[i + 1u], at.y);
+}
+
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= emitter.capacity {
+        return;
+    }
+
+    var particle = particles[index];
+    let dt = emitter.delta;
+    if (index + emitter.capacity - emitter.spawn_start) % emitter.capacity < emitter.spawn_count {
+        var state = hash(index ^ hash(emitter.seed));
+        let offset = random_direction(&state) * emitter.spawn_radius * pow(random(&state), 1.0 / 3.0);
+        particle.position = emitter.position + offset;
+        particle.velocity = emitter.velocity
+            + random_direction(&state) * emitter.velocity_spread * random(&state);
+        particle.lifetime = max(
+            emitter.lifetime + (random(&state) * 2.0 - 1.0) * emitter.lifetime_variance,
+            0.001,
+        );
+        particle.age = 0.0;
+    } else if particle.age < particle.lifetime {
+        var acceleration = emitter.gravity;
+        for (var i = 0u; i < min(emitter.force_count, MAX_FORCES); i += 1u) {
+            let force = emitter.forces[i];
+            if emitter.force_kinds[i] == FORCE_DIRECTIONAL {
+                acceleration += force.xyz;
+            } else if emitter.force_kinds[i] == FORCE_POINT {
+                let to_point = force.xyz - particle.position;
+                if dot(to_point, to_point) > 0.0001 {
+                    acceleration += normalize(to_point) * force.w;
+                }
+            }
+        }
+        particle.velocity = (particle.velocity + acceleration * dt) * max(1.0 - emitter.drag * dt, 0.0);
+        particle.age += dt;
+        let t = particle.age / particle.lifetime;
+        particle.position += particle.velocity * speed_at(t) * dt;
+    }
+    particles[index] = particle;
+
+    if particle.age < particle.lifetime {
+        let t = particle.age / particle.lifetime;
+        let slot = atomicAdd(&draw.instance_count, 1u);
+        instances[slot] = ParticleInstance(particle.position, size_at(t), color_at(t));
+    }
+}
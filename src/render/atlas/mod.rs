@@ -0,0 +1,122 @@
+pub mod pack;
+
+use glam::Vec2;
+
+use crate::{GameError, Result};
+
+use super::{
+    sprite::{NineSlice, Sprite, SpriteBatch, TextureId},
+    texture,
+};
+
+pub use pack::{AtlasBuilder, AtlasManifest, AtlasOptions, AtlasRegion, PackError, PackedAtlas};
+
+/// Sprites packed by `build.rs` from `src/sprites/**/*.png`, named by their path
+/// relative to that directory without the extension, e.g. `"ui/panel"`.
+pub mod bundled {
+    include!(concat!(env!("OUT_DIR"), "/sprites_atlas.rs"));
+}
+
+/// Atlas pages uploaded and registered with a `SpriteBatch`, so every region of a page
+/// shares one texture and bind group and sprites using them batch together.
+#[derive(Debug)]
+pub struct TextureAtlas {
+    pages: Vec<TextureId>,
+    manifest: AtlasManifest,
+}
+
+impl TextureAtlas {
+    /// Uploads an atlas packed at runtime with `AtlasBuilder`.
+    pub fn from_packed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sprites: &mut SpriteBatch,
+        packed: PackedAtlas,
+    ) -> Result<Self> {
+        let mut pages = Vec::with_capacity(packed.pages.len());
+        for (index, page) in packed.pages.into_iter().enumerate() {
+            let page = texture::Texture::from_image(
+                device,
+                queue,
+                &image::DynamicImage::ImageRgba8(page),
+                Some(&format!("atlas_page_{index}")),
+            )?;
+            pages.push(sprites.add_texture(device, page));
+        }
+
+        Ok(Self::from_pages(pages, packed.manifest))
+    }
+
+    /// An atlas over pages already registered with a `SpriteBatch`, in manifest order.
+    pub(crate) fn from_pages(pages: Vec<TextureId>, manifest: AtlasManifest) -> Self {
+        Self { pages, manifest }
+    }
+
+    /// Loads an atlas saved with `PackedAtlas::save`, from the manifest JSON and the
+    /// encoded pages in order.
+    pub fn from_saved(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sprites: &mut SpriteBatch,
+        manifest: &str,
+        pages: &[&[u8]],
+    ) -> Result<Self> {
+        let manifest: AtlasManifest =
+            serde_json::from_str(manifest).map_err(|err| GameError::AtlasError(err.to_string()))?;
+        if manifest.pages != pages.len() {
+            return Err(GameError::AtlasError(format!(
+                "manifest lists {} pages but {} were given",
+                manifest.pages,
+                pages.len()
+            )));
+        }
+
+        let pages = pages
+            .iter()
+            .map(|page| image::load_from_memory(page).map(|page| page.to_rgba8()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Self::from_packed(device, queue, sprites, PackedAtlas { pages, manifest })
+    }
+
+    /// Loads the atlas `build.rs` packed from `src/sprites`.
+    pub fn bundled(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sprites: &mut SpriteBatch,
+    ) -> Result<Self> {
+        Self::from_saved(device, queue, sprites, bundled::MANIFEST, bundled::PAGES)
+    }
+
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.manifest.regions.get(name)
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = (&str, &AtlasRegion)> {
+        self.manifest
+            .regions
+            .iter()
+            .map(|(name, region)| (name.as_str(), region))
+    }
+
+    pub fn page(&self, page: usize) -> TextureId {
+        self.pages[page]
+    }
+
+    /// A sprite showing region `name` at its size in texels.
+    pub fn sprite(&self, name: &str, position: Vec2) -> Option<Sprite> {
+        let region = self.region(name)?;
+        Some(Sprite {
+            uv: region.uv,
+            ..Sprite::new(self.pages[region.page], position)
+        })
+    }
+
+    /// Region `name` as a nine-slice panel, with borders in texels of the region.
+    pub fn nine_slice(&self, name: &str, borders: [f32; 4]) -> Option<NineSlice> {
+        let region = self.region(name)?;
+        Some(NineSlice {
+            uv: region.uv,
+            ..NineSlice::new(self.pages[region.page], borders)
+        })
+    }
+}
@@ -0,0 +1,444 @@
+//! CPU side of texture atlas building. Only uses `std`, `image` and `serde` so
+//! `build.rs` can compile it on its own with `#[path = "src/render/atlas/pack.rs"]`.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasOptions {
+    /// Largest page width and height in texels. Pages shrink to the smallest power of
+    /// two that fits what was packed into them.
+    pub max_page_size: u32,
+    /// Empty texels between neighbouring images.
+    pub padding: u32,
+    /// Texels each image's edge is repeated outwards, so filtering at the edge of a
+    /// region samples the image itself instead of its neighbours or padding.
+    pub extrude: u32,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            max_page_size: 2048,
+            padding: 2,
+            extrude: 1,
+        }
+    }
+}
+
+/// Where a named image ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AtlasRegion {
+    pub page: usize,
+    /// Normalized page coordinates, `[min_u, min_v, max_u, max_v]`.
+    pub uv: [f32; 4],
+    /// Size of the source image in texels.
+    pub size: [u32; 2],
+}
+
+/// Everything needed to look regions up once the pages are loaded. Serialized as JSON
+/// next to the pages by `PackedAtlas::save`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AtlasManifest {
+    pub pages: usize,
+    pub regions: BTreeMap<String, AtlasRegion>,
+}
+
+#[derive(Debug)]
+pub struct PackedAtlas {
+    pub pages: Vec<RgbaImage>,
+    pub manifest: AtlasManifest,
+}
+
+#[derive(Debug)]
+pub enum PackError {
+    /// The image doesn't fit on an empty page.
+    TooLarge {
+        name: String,
+        width: u32,
+        height: u32,
+    },
+    DuplicateName(String),
+    Image(image::ImageError),
+    Io(std::io::Error),
+    Manifest(serde_json::Error),
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::TooLarge {
+                name,
+                width,
+                height,
+            } => write!(
+                f,
+                "'{name}' ({width}x{height}) doesn't fit on an atlas page"
+            ),
+            PackError::DuplicateName(name) => write!(f, "'{name}' was added twice"),
+            PackError::Image(err) => write!(f, "{err}"),
+            PackError::Io(err) => write!(f, "{err}"),
+            PackError::Manifest(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for PackError {}
+
+impl From<image::ImageError> for PackError {
+    fn from(err: image::ImageError) -> Self {
+        PackError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for PackError {
+    fn from(err: std::io::Error) -> Self {
+        PackError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for PackError {
+    fn from(err: serde_json::Error) -> Self {
+        PackError::Manifest(err)
+    }
+}
+
+/// Collects named images and packs them into as few pages as fit.
+#[derive(Debug, Default)]
+pub struct AtlasBuilder {
+    options: AtlasOptions,
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(options: AtlasOptions) -> Self {
+        Self {
+            options,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, image: RgbaImage) {
+        self.images.push((name.into(), image));
+    }
+
+    /// Decodes an encoded image, e.g. from `include_bytes!`.
+    pub fn add_bytes(&mut self, name: impl Into<String>, bytes: &[u8]) -> Result<(), PackError> {
+        self.add(name, image::load_from_memory(bytes)?.to_rgba8());
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, name: impl Into<String>, path: &Path) -> Result<(), PackError> {
+        self.add(name, image::open(path)?.to_rgba8());
+        Ok(())
+    }
+
+    pub fn build(mut self) -> Result<PackedAtlas, PackError> {
+        let AtlasOptions {
+            max_page_size,
+            padding,
+            extrude,
+        } = self.options;
+
+        let mut names = HashSet::new();
+        if let Some((name, _)) = self.images.iter().find(|(name, _)| !names.insert(name)) {
+            return Err(PackError::DuplicateName(name.clone()));
+        }
+
+        // Tallest first keeps shelves evenly filled.
+        self.images.sort_by(|(a_name, a), (b_name, b)| {
+            b.height()
+                .cmp(&a.height())
+                .then(b.width().cmp(&a.width()))
+                .then(a_name.cmp(b_name))
+        });
+
+        let mut packers: Vec<ShelfPacker> = Vec::new();
+        let mut placements = Vec::with_capacity(self.images.len());
+        for (name, image) in self.images.iter() {
+            // Padding goes right of and below every cell; the page edge needs none.
+            let cell = (
+                image.width() + extrude * 2 + padding,
+                image.height() + extrude * 2 + padding,
+            );
+            let placed = packers
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, packer)| Some((page, packer.allocate(cell.0, cell.1)?)));
+            let (page, (x, y)) = match placed {
+                Some(placed) => placed,
+                None => {
+                    let mut packer = ShelfPacker::new(max_page_size, max_page_size);
+                    let position =
+                        packer
+                            .allocate(cell.0, cell.1)
+                            .ok_or_else(|| PackError::TooLarge {
+                                name: name.clone(),
+                                width: image.width(),
+                                height: image.height(),
+                            })?;
+                    packers.push(packer);
+                    (packers.len() - 1, position)
+                }
+            };
+            placements.push((name.clone(), page, x + extrude, y + extrude));
+        }
+
+        let mut pages = packers
+            .iter()
+            .map(|packer| {
+                let (width, height) = packer.used();
+                RgbaImage::new(
+                    width.next_power_of_two().min(max_page_size),
+                    height.next_power_of_two().min(max_page_size),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut manifest = AtlasManifest {
+            pages: pages.len(),
+            regions: BTreeMap::new(),
+        };
+        for ((name, page, x, y), (_, image)) in placements.into_iter().zip(&self.images) {
+            let target = &mut pages[page];
+            blit_extruded(target, image, x, y, extrude);
+
+            let (page_width, page_height) = (target.width() as f32, target.height() as f32);
+            manifest.regions.insert(
+                name,
+                AtlasRegion {
+                    page,
+                    uv: [
+                        x as f32 / page_width,
+                        y as f32 / page_height,
+                        (x + image.width()) as f32 / page_width,
+                        (y + image.height()) as f32 / page_height,
+                    ],
+                    size: [image.width(), image.height()],
+                },
+            );
+        }
+
+        Ok(PackedAtlas { pages, manifest })
+    }
+}
+
+impl PackedAtlas {
+    /// Writes `{name}_{page}.png` for every page and `{name}.json` for the manifest into
+    /// `dir`.
+    pub fn save(&self, dir: &Path, name: &str) -> Result<(), PackError> {
+        for (index, page) in self.pages.iter().enumerate() {
+            page.save(dir.join(format!("{name}_{index}.png")))?;
+        }
+        std::fs::write(
+            dir.join(format!("{name}.json")),
+            serde_json::to_string_pretty(&self.manifest)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Copies `image` to `(x, y)` in `page`, repeating its edge texels `extrude` texels
+/// outwards on every side.
+fn blit_extruded(page: &mut RgbaImage, image: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let extrude = extrude as i64;
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    if width == 0 || height == 0 {
+        return;
+    }
+    for dy in -extrude..height + extrude {
+        for dx in -extrude..width + extrude {
+            let source = image.get_pixel(
+                dx.clamp(0, width - 1) as u32,
+                dy.clamp(0, height - 1) as u32,
+            );
+            page.put_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32, *source);
+        }
+    }
+}
+
+/// Packs rectangles into rows ("shelves") as tall as the first rectangle placed on them.
+/// Wastes some space on mixed heights, so sort rectangles tallest first where possible.
+#[derive(Debug)]
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    shelves: Vec<Shelf>,
+}
+
+#[derive(Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    x: u32,
+}
+
+impl ShelfPacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            shelves: Vec::new(),
+        }
+    }
+
+    /// Returns the top-left corner of a free `width` by `height` rectangle, or `None`
+    /// if there is no room left.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if let Some(shelf) = self
+            .shelves
+            .iter_mut()
+            .find(|shelf| height <= shelf.height && shelf.x + width <= self.width)
+        {
+            let x = shelf.x;
+            shelf.x += width;
+            return Some((x, shelf.y));
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+        if y + height > self.height || width > self.width {
+            return None;
+        }
+
+        self.shelves.push(Shelf {
+            y,
+            height,
+            x: width,
+        });
+        Some((0, y))
+    }
+
+    /// Extent of everything allocated so far.
+    pub fn used(&self) -> (u32, u32) {
+        let width = self.shelves.iter().map(|shelf| shelf.x).max().unwrap_or(0);
+        let height = self
+            .shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+        (width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    /// `(page, x, y, width, height)` of a region in texels.
+    fn texels(atlas: &PackedAtlas, name: &str) -> (usize, u32, u32, u32, u32) {
+        let region = atlas.manifest.regions[name];
+        let page = &atlas.pages[region.page];
+        let x = (region.uv[0] * page.width() as f32).round() as u32;
+        let y = (region.uv[1] * page.height() as f32).round() as u32;
+        (region.page, x, y, region.size[0], region.size[1])
+    }
+
+    #[test]
+    fn cells_do_not_overlap() {
+        let options = AtlasOptions {
+            max_page_size: 128,
+            padding: 2,
+            extrude: 1,
+        };
+        let mut builder = AtlasBuilder::new(options);
+        let sizes = [(10, 4), (3, 17), (8, 8), (20, 6), (1, 1), (12, 12), (5, 9)];
+        for (index, (width, height)) in sizes.into_iter().enumerate() {
+            builder.add(format!("{index}"), solid(width, height, [255; 4]));
+        }
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.pages.len(), 1);
+
+        // Each image plus its extruded border and padding must stay clear of the others.
+        let margin = options.extrude;
+        let cells = (0..sizes.len())
+            .map(|index| {
+                let (_, x, y, width, height) = texels(&atlas, &format!("{index}"));
+                (
+                    x - margin,
+                    y - margin,
+                    x + width + margin + options.padding,
+                    y + height + margin + options.padding,
+                )
+            })
+            .collect::<Vec<_>>();
+        for (i, a) in cells.iter().enumerate() {
+            for b in cells.iter().skip(i + 1) {
+                let disjoint = a.2 <= b.0 || b.2 <= a.0 || a.3 <= b.1 || b.3 <= a.1;
+                assert!(disjoint, "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn extrudes_edges_and_leaves_padding_empty() {
+        let mut image = solid(2, 2, [255, 0, 0, 255]);
+        image.put_pixel(1, 1, Rgba([0, 0, 255, 255]));
+        let mut builder = AtlasBuilder::new(AtlasOptions {
+            max_page_size: 64,
+            padding: 2,
+            extrude: 1,
+        });
+        builder.add("a", image);
+        builder.add("b", solid(2, 2, [0, 255, 0, 255]));
+        let atlas = builder.build().unwrap();
+
+        let (page, x, y, width, height) = texels(&atlas, "a");
+        assert_eq!((x, y, width, height), (1, 1, 2, 2));
+        let page = &atlas.pages[page];
+        // The corner texel is repeated outwards in both directions.
+        assert_eq!(page.get_pixel(x + 2, y + 2), &Rgba([0, 0, 255, 255]));
+        assert_eq!(page.get_pixel(x - 1, y - 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(page.get_pixel(x + 2, y), &Rgba([255, 0, 0, 255]));
+        // Past the extruded border comes padding, then the next cell's border.
+        assert_eq!(page.get_pixel(x + 3, y), &Rgba([0; 4]));
+        assert_eq!(page.get_pixel(x + 4, y), &Rgba([0; 4]));
+        assert_eq!(page.get_pixel(x + 5, y), &Rgba([0, 255, 0, 255]));
+        assert_eq!(texels(&atlas, "b").1, x + 6);
+    }
+
+    #[test]
+    fn spills_to_another_page() {
+        let options = AtlasOptions {
+            max_page_size: 16,
+            padding: 0,
+            extrude: 0,
+        };
+        let mut builder = AtlasBuilder::new(options);
+        for index in 0..5 {
+            builder.add(format!("{index}"), solid(8, 8, [255; 4]));
+        }
+        let atlas = builder.build().unwrap();
+
+        assert_eq!(atlas.pages.len(), 2);
+        assert_eq!(atlas.manifest.pages, 2);
+        let on_second_page = atlas
+            .manifest
+            .regions
+            .values()
+            .filter(|region| region.page == 1)
+            .count();
+        assert_eq!(on_second_page, 1);
+        // Pages shrink to what was packed into them.
+        assert_eq!(atlas.pages[0].dimensions(), (16, 16));
+        assert_eq!(atlas.pages[1].dimensions(), (8, 8));
+
+        let mut builder = AtlasBuilder::new(options);
+        builder.add("huge", solid(17, 1, [255; 4]));
+        assert!(matches!(builder.build(), Err(PackError::TooLarge { .. })));
+    }
+}
@@ -0,0 +1,234 @@
+use glam::{Vec3, Vec4};
+
+use crate::Result;
+
+use super::{
+    camera, reflect, texture,
+    uniform::{self, ShaderType},
+};
+
+/// Optical depth of the atmosphere straight up from sea level for Rayleigh scattering,
+/// which gives the sky its blue. Scattering coefficients times an 8 km scale height.
+const RAYLEIGH_DEPTH: Vec3 = Vec3::new(0.0464, 0.108, 0.265);
+/// The same for Mie scattering off haze, before `Sky::haze`. Grey, it mostly brightens
+/// around the sun.
+const MIE_DEPTH: f32 = 0.028;
+
+/// How fog thickens with distance from the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FogFalloff {
+    #[default]
+    None,
+    /// None before `start`, full at `end`.
+    Linear { start: f32, end: f32 },
+    /// `1 - exp(-density * distance)`, an evenly thick medium.
+    Exponential { density: f32 },
+    /// `1 - exp(-(density * distance)^2)`, clearer close by and a sharper wall further out.
+    ExponentialSquared { density: f32 },
+}
+
+/// Fog blended over the shaded scene in every render path, the sky excepted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    pub color: Vec3,
+    pub falloff: FogFalloff,
+    /// Density of the height fog at `height_base`, 0 turns it off. It thins out
+    /// exponentially above and thickens below, and is integrated along each view ray so
+    /// looking down into it is denser than looking across it.
+    pub height_density: f32,
+    /// How quickly height fog thins with every unit above `height_base`.
+    pub height_falloff: f32,
+    pub height_base: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            color: Vec3::new(0.6, 0.7, 0.8),
+            falloff: FogFalloff::None,
+            height_density: 0.0,
+            height_falloff: 1.0,
+            height_base: 0.0,
+        }
+    }
+}
+
+/// Analytic single scattering sky, lit by `Lights::sun`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sky {
+    /// Drawn where no geometry is, instead of the clear color.
+    pub enabled: bool,
+    /// Scales the sky and the sun disc.
+    pub intensity: f32,
+    /// Multiplies Mie scattering: more is hazier, with a paler sky and a wider glow
+    /// around the sun.
+    pub haze: f32,
+    /// Scales the lit paths' sun by what makes it through the atmosphere, so it reddens
+    /// and dims towards the horizon and goes out below it.
+    pub tint_sun: bool,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 1.0,
+            haze: 1.0,
+            tint_sun: true,
+        }
+    }
+}
+
+impl Sky {
+    /// Zenith optical depth, Rayleigh in rgb and Mie in a.
+    fn optical_depth(&self) -> Vec4 {
+        RAYLEIGH_DEPTH.extend(MIE_DEPTH * self.haze)
+    }
+
+    /// Fraction of sunlight from `direction`, towards the sun, that reaches the ground.
+    pub fn sun_transmittance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize_or_zero();
+        let depth = self.optical_depth();
+        let extinction = depth.truncate() + depth.w;
+        // Fades out as the sun sets rather than at the horizon, the sky still glows.
+        let horizon = smoothstep(-0.1, 0.02, direction.y);
+        (-extinction * air_mass(direction.y)).exp() * horizon
+    }
+}
+
+/// Optical depth along a ray relative to straight up, by the Kasten-Young formula, which
+/// accounts for the curvature of the atmosphere near the horizon.
+fn air_mass(cos_zenith: f32) -> f32 {
+    let cos_zenith = cos_zenith.clamp(0.0, 1.0);
+    let zenith_degrees = cos_zenith.acos().to_degrees();
+    1.0 / (cos_zenith + 0.50572 * (96.07995 - zenith_degrees).powf(-1.6364))
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Fog and sky of the scene.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Atmosphere {
+    pub fog: Fog,
+    pub sky: Sky,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct SkyUniform {
+    inverse_view_proj: glam::Mat4,
+    eye: Vec4,
+    /// Towards the sun.
+    sun_direction: Vec4,
+    /// Rayleigh in rgb, Mie in a.
+    optical_depth: Vec4,
+    intensity: f32,
+    #[shader(padding)]
+    _padding: [u32; 3],
+}
+
+/// Draws `Sky` as a full-screen triangle on the far plane, so only where the depth buffer
+/// is still clear.
+#[derive(Debug)]
+pub struct SkyRenderer {
+    pipeline: wgpu::RenderPipeline,
+    uniform: uniform::UniformBuffer<SkyUniform>,
+    enabled: bool,
+}
+
+impl SkyRenderer {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Result<Self> {
+        let shader_source = include_str!("../../sky.wgsl");
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        let layout =
+            reflection.create_bind_group_layout(device, 0, Some("sky_bind_group_layout"))?;
+        let uniform = uniform::UniformBuffer::new(
+            device,
+            &layout,
+            bytemuck::Zeroable::zeroed(),
+            "sky_uniform",
+        );
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Sky Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Ok(Self {
+            pipeline,
+            uniform,
+            enabled: false,
+        })
+    }
+
+    /// `sun_direction` is towards the sun.
+    pub fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &camera::Camera,
+        sun_direction: Vec3,
+        sky: &Sky,
+    ) {
+        self.enabled = sky.enabled;
+        if !sky.enabled {
+            return;
+        }
+        self.uniform.set(
+            queue,
+            SkyUniform {
+                inverse_view_proj: camera.build_view_projection_matrix().inverse(),
+                eye: camera.get_eye().extend(1.0),
+                sun_direction: sun_direction.normalize_or_zero().extend(0.0),
+                optical_depth: sky.optical_depth(),
+                intensity: sky.intensity,
+                _padding: [0; 3],
+            },
+        );
+    }
+
+    /// Draw before anything blended, which doesn't write depth and would be covered.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.enabled {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, self.uniform.bind_group(), &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -50,13 +50,16 @@ impl Camera {
     }
 
     pub fn build_view_projection_matrix(&self) -> glam::Mat4 {
-        // 1.
-        let view = glam::Mat4::look_at_rh(self.controller.get_eye(), self.target, self.up);
-        // 2.
-        let proj = glam::Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar);
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+
+    pub fn build_view_matrix(&self) -> glam::Mat4 {
+        glam::Mat4::look_at_rh(self.controller.get_eye(), self.target, self.up)
+    }
 
-        // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+    pub fn build_projection_matrix(&self) -> glam::Mat4 {
+        let proj = glam::Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar);
+        OPENGL_TO_WGPU_MATRIX * proj
     }
 
     pub fn get_target(&self) -> glam::Vec3 {
@@ -67,15 +70,70 @@ impl Camera {
         self.target = target;
     }
 
+    pub fn get_eye(&self) -> glam::Vec3 {
+        self.controller.get_eye()
+    }
+
     pub fn get_up(&self) -> glam::Vec3 {
         self.up
     }
 
+    /// Vertical field of view in radians.
+    pub fn get_fovy(&self) -> f32 {
+        self.fovy
+    }
+
     pub fn get_uniform(&self) -> types::CameraUniform {
         self.uniform
     }
 
+    /// Near and far plane distances.
+    pub fn depth_range(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
+
     pub fn set_aspect(&mut self, aspect: f32) {
         self.aspect = aspect;
     }
 }
+
+/// A 2D camera looking down -Z. One world unit is one pixel at a zoom of 1, with +Y up
+/// and `position` at the centre of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrthoCamera {
+    pub position: glam::Vec2,
+    /// Counter-clockwise, in radians.
+    pub rotation: f32,
+    /// Values above 1 zoom in.
+    pub zoom: f32,
+}
+
+impl Default for OrthoCamera {
+    fn default() -> Self {
+        Self {
+            position: glam::Vec2::ZERO,
+            rotation: 0.0,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl OrthoCamera {
+    pub fn build_view_projection_matrix(&self, width: u32, height: u32) -> glam::Mat4 {
+        let half = glam::vec2(width.max(1) as f32, height.max(1) as f32) * 0.5 / self.zoom;
+        let view = glam::Mat4::from_rotation_z(-self.rotation)
+            * glam::Mat4::from_translation(-self.position.extend(0.0));
+        let proj = glam::Mat4::orthographic_rh(-half.x, half.x, -half.y, half.y, -1.0, 1.0);
+        proj * view
+    }
+
+    /// Converts a position in window pixels (top-left origin, y down) to world space,
+    /// e.g. to find what the cursor is over.
+    pub fn screen_to_world(&self, screen: glam::Vec2, width: u32, height: u32) -> glam::Vec2 {
+        let centered = glam::vec2(
+            screen.x - width as f32 * 0.5,
+            height as f32 * 0.5 - screen.y,
+        );
+        self.position + glam::Vec2::from_angle(self.rotation).rotate(centered / self.zoom)
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Copies presented frames back from the GPU and writes them out as PNGs, either one at a
+/// time (screenshots) or as a numbered sequence sampled at a fixed simulation timestep.
+///
+/// Needs the surface to be configured with `COPY_SRC`; on surfaces that don't support it
+/// requests are logged and dropped.
+#[derive(Debug)]
+pub struct Capture {
+    supported: bool,
+    screenshot: Option<PathBuf>,
+    recording: Option<Recording>,
+    readbacks: Vec<Readback>,
+}
+
+#[derive(Debug)]
+struct Recording {
+    directory: PathBuf,
+    frame_time: f64,
+    sim_time: f64,
+    next_capture: f64,
+    frame: u32,
+}
+
+#[derive(Debug)]
+struct Readback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+    /// Every file this frame is written to, more than one when a recording fell behind.
+    paths: Vec<PathBuf>,
+    mapped: bool,
+    ready: Arc<AtomicBool>,
+}
+
+impl Capture {
+    pub fn new(config: &wgpu::SurfaceConfiguration) -> Self {
+        Self {
+            supported: config.usage.contains(wgpu::TextureUsages::COPY_SRC),
+            screenshot: None,
+            recording: None,
+            readbacks: Vec::new(),
+        }
+    }
+
+    /// Saves the next presented frame to `path`, or to a timestamped file in
+    /// `screenshots/` when `None`.
+    pub fn request_screenshot(&mut self, path: Option<PathBuf>) {
+        if !self.supported {
+            log::warn!("Screenshots aren't supported by this surface");
+            return;
+        }
+        self.screenshot = Some(path.unwrap_or_else(default_screenshot_path));
+    }
+
+    /// Dumps `frame_00000.png`, `frame_00001.png`, ... into `directory`, one for every
+    /// `1 / fps` seconds of simulated time, regardless of how fast frames render. When
+    /// several steps elapse between two rendered frames, the rendered frame is written once
+    /// per step so frame `n` always shows simulated time `n / fps`.
+    pub fn start_recording(&mut self, directory: PathBuf, fps: f64) {
+        if !self.supported {
+            log::warn!("Frame capture isn't supported by this surface");
+            return;
+        }
+        self.recording = Some(Recording {
+            directory,
+            frame_time: 1.0 / fps,
+            sim_time: 0.0,
+            next_capture: 0.0,
+            frame: 0,
+        });
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.recording = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Advances the recording clock by one simulation step.
+    pub fn update(&mut self, delta: f64) {
+        if let Some(recording) = &mut self.recording {
+            recording.sim_time += delta;
+        }
+    }
+
+    /// Records a copy of `frame` into `encoder` if a screenshot or sequence frame is due.
+    pub fn copy_frame(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &wgpu::Texture,
+    ) {
+        let mut readbacks = Vec::new();
+        readbacks.extend(self.screenshot.take().map(|path| vec![path]));
+        if let Some(recording) = &mut self.recording {
+            let mut paths = Vec::new();
+            while recording.sim_time >= recording.next_capture {
+                paths.push(
+                    recording
+                        .directory
+                        .join(format!("frame_{:05}.png", recording.frame)),
+                );
+                recording.frame += 1;
+                recording.next_capture += recording.frame_time;
+            }
+            if !paths.is_empty() {
+                readbacks.push(paths);
+            }
+        }
+
+        for paths in readbacks {
+            let width = frame.width();
+            let height = frame.height();
+            let padded_bytes_per_row =
+                wgpu::util::align_to(width * 4, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Capture Buffer"),
+                size: padded_bytes_per_row as u64 * height as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+
+            encoder.copy_texture_to_buffer(
+                frame.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_bytes_per_row),
+                        rows_per_image: Some(height),
+                    },
+                },
+                frame.size(),
+            );
+
+            self.readbacks.push(Readback {
+                buffer,
+                width,
+                height,
+                padded_bytes_per_row,
+                format: frame.format(),
+                paths,
+                mapped: false,
+                ready: Arc::new(AtomicBool::new(false)),
+            });
+        }
+    }
+
+    /// Starts mapping the copies recorded this frame. Call after the frame is submitted.
+    pub fn map_pending(&mut self) {
+        for readback in self.readbacks.iter_mut().filter(|r| !r.mapped) {
+            let ready = readback.ready.clone();
+            readback
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| match result {
+                    Ok(()) => ready.store(true, Ordering::Release),
+                    Err(err) => log::error!("Failed to map capture buffer: {}", err),
+                });
+            readback.mapped = true;
+        }
+    }
+
+    /// Writes out every readback the GPU has finished. Needs the device to have been
+    /// polled since `map_pending`.
+    pub fn save_ready(&mut self) {
+        let (ready, pending) = std::mem::take(&mut self.readbacks)
+            .into_iter()
+            .partition(|r| r.ready.load(Ordering::Acquire));
+        self.readbacks = pending;
+
+        for readback in ready {
+            let rgba = readback.to_rgba();
+            readback.buffer.unmap();
+            if let Some(rgba) = rgba {
+                save_png(readback.paths, readback.width, readback.height, rgba);
+            }
+        }
+    }
+}
+
+impl Readback {
+    /// Strips the row padding and swizzles to tightly packed RGBA8. The bytes of an
+    /// `*Srgb` surface are already sRGB encoded, which is what PNG expects.
+    fn to_rgba(&self) -> Option<Vec<u8>> {
+        let swizzle = match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => {
+                log::error!("Can't capture frames in {:?}", format);
+                return None;
+            }
+        };
+
+        let view = self.buffer.slice(..).get_mapped_range();
+        let mut rgba = Vec::with_capacity((self.width * self.height * 4) as usize);
+        for row in view.chunks_exact(self.padded_bytes_per_row as usize) {
+            rgba.extend_from_slice(&row[..(self.width * 4) as usize]);
+        }
+
+        if swizzle {
+            rgba.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        Some(rgba)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_png(paths: Vec<PathBuf>, width: u32, height: u32, rgba: Vec<u8>) {
+    // PNG encoding is slow enough to cause hitches, so it happens off the render thread.
+    std::thread::spawn(move || {
+        let Some((path, duplicates)) = paths.split_first() else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            if let Err(err) = std::fs::create_dir_all(parent) {
+                log::error!("Failed to create {}: {}", parent.display(), err);
+                return;
+            }
+        }
+
+        match image::RgbaImage::from_raw(width, height, rgba).map(|img| img.save(path)) {
+            Some(Ok(())) => log::info!("Saved capture to {}", path.display()),
+            Some(Err(err)) => {
+                log::error!("Failed to save {}: {}", path.display(), err);
+                return;
+            }
+            None => {
+                log::error!("Capture buffer for {} has the wrong size", path.display());
+                return;
+            }
+        }
+
+        // Encoded once, copied for the steps the recording had to catch up on.
+        for duplicate in duplicates {
+            if let Err(err) = std::fs::copy(path, duplicate) {
+                log::error!("Failed to save {}: {}", duplicate.display(), err);
+            }
+        }
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_png(paths: Vec<PathBuf>, _width: u32, _height: u32, _rgba: Vec<u8>) {
+    for path in paths {
+        log::warn!("Can't write {} on the web", path.display());
+    }
+}
+
+fn default_screenshot_path() -> PathBuf {
+    let timestamp = instant::SystemTime::now()
+        .duration_since(instant::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    PathBuf::from("screenshots").join(format!("screenshot_{}.png", timestamp))
+}
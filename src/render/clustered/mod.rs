@@ -0,0 +1,425 @@
+use glam::{UVec3, Vec2};
+
+use crate::Result;
+
+use super::{
+    atmosphere, camera, deferred, light, reflect, ssao, texture,
+    types::{self, VertexDescription},
+    uniform::{self, ShaderType},
+};
+
+/// Froxels across, down and into the screen.
+pub const CLUSTER_GRID: UVec3 = UVec3::new(16, 9, 24);
+/// Capacity of the light index list shared by all clusters, per cluster. Any one cluster
+/// can still hold up to `MAX_LIGHTS_PER_CLUSTER` in `light_clusters.wgsl`.
+const AVERAGE_LIGHTS_PER_CLUSTER: u32 = 32;
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Shared by `light_clusters.wgsl` and `clustered.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct ClusterUniform {
+    inverse_projection: glam::Mat4,
+    view: glam::Mat4,
+    screen_size: Vec2,
+    z_near: f32,
+    z_far: f32,
+    grid: UVec3,
+    light_count: u32,
+}
+
+#[derive(Debug)]
+struct ClusterBuffers {
+    uniform: uniform::UniformBuffer<ClusterUniform>,
+    material: uniform::UniformBuffer<deferred::Material>,
+    ranges: wgpu::Buffer,
+    light_indices: wgpu::Buffer,
+    /// Next free entry of `light_indices`.
+    index_count: wgpu::Buffer,
+}
+
+/// Clustered forward shading. Every frame a compute pass splits the view frustum into
+/// `CLUSTER_GRID` froxels, sliced exponentially in depth, and lists the point lights
+/// whose range touches each one. The scene mesh is then shaded in the main pass as
+/// usual, each fragment looping only over its cluster's lights, so hundreds of lights
+/// cost about as much as the few that overlap any one spot. A depth prepass before it
+/// gives SSAO the scene's depth and keeps hidden fragments from looping at all.
+#[derive(Debug)]
+pub struct ClusteredRenderer {
+    pipeline: wgpu::RenderPipeline,
+    depth_prepass_pipeline: wgpu::RenderPipeline,
+    bin_pipeline: wgpu::ComputePipeline,
+    lights: light::LightBuffers,
+    buffers: ClusterBuffers,
+    bin_layout: wgpu::BindGroupLayout,
+    clusters_layout: wgpu::BindGroupLayout,
+    bin_bind_group: wgpu::BindGroup,
+    clusters_bind_group: wgpu::BindGroup,
+}
+
+impl ClusteredRenderer {
+    /// Binning needs compute shaders, and shading reads three storage buffers per
+    /// fragment.
+    pub fn is_supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && device.limits().max_storage_buffers_per_shader_stage >= 4
+    }
+
+    /// `texture_bind_group_layout` and `camera_bind_group_layout` are the scene mesh's,
+    /// groups 0 and 1 of its pipeline. `ao` is `ssao::Ssao::view`.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        ao: &wgpu::TextureView,
+    ) -> Result<Self> {
+        let shader_source = concat!(
+            include_str!("../../lighting.wgsl"),
+            include_str!("../../clustered.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers(
+            "vs_main",
+            &[types::Vertex::desc(), types::InstanceRaw::desc()],
+        )?;
+        let lighting_layout = reflection.create_bind_group_layout(
+            device,
+            2,
+            Some("clustered_lighting_bind_group_layout"),
+        )?;
+        let clusters_layout =
+            reflection.create_bind_group_layout(device, 3, Some("clusters_bind_group_layout"))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Clustered Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Clustered Pipeline Layout"),
+            bind_group_layouts: &[
+                texture_bind_group_layout,
+                camera_bind_group_layout,
+                &lighting_layout,
+                &clusters_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Clustered Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[types::Vertex::desc(), types::InstanceRaw::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            // LessEqual so fragments at the depth the prepass already wrote still pass.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let depth_prepass_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Clustered Depth Prepass Pipeline Layout"),
+            bind_group_layouts: &[texture_bind_group_layout, camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let depth_prepass_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Clustered Depth Prepass Pipeline"),
+                layout: Some(&depth_prepass_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[types::Vertex::desc(), types::InstanceRaw::desc()],
+                },
+                fragment: None,
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        let bin_source = include_str!("../../light_clusters.wgsl");
+        let reflection = reflect::ShaderReflection::new(bin_source)?;
+        let bin_layout =
+            reflection.create_bind_group_layout(device, 0, Some("light_bin_bind_group_layout"))?;
+        let bin_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Light Clusters Shader"),
+            source: wgpu::ShaderSource::Wgsl(bin_source.into()),
+        });
+        let bin_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Bin Pipeline Layout"),
+            bind_group_layouts: &[&bin_layout],
+            push_constant_ranges: &[],
+        });
+        let bin_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Light Bin Pipeline"),
+            layout: Some(&bin_pipeline_layout),
+            module: &bin_shader,
+            entry_point: "cs_bin",
+        });
+
+        let cluster_count = CLUSTER_GRID.x * CLUSTER_GRID.y * CLUSTER_GRID.z;
+        // Both are bound in the shared groups below, this layout only serves the bind
+        // group each `UniformBuffer` makes for itself.
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("cluster_uniform_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let uniform = uniform::UniformBuffer::new(
+            device,
+            &uniform_layout,
+            ClusterUniform {
+                screen_size: Vec2::new(config.width as f32, config.height as f32),
+                grid: CLUSTER_GRID,
+                ..bytemuck::Zeroable::zeroed()
+            },
+            "cluster_uniform",
+        );
+        let material = uniform::UniformBuffer::new(
+            device,
+            &uniform_layout,
+            deferred::Material::default(),
+            "clustered_material_uniform",
+        );
+        let ranges = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Range Buffer"),
+            size: cluster_count as wgpu::BufferAddress * 8,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let light_indices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Light Index Buffer"),
+            size: (cluster_count * AVERAGE_LIGHTS_PER_CLUSTER) as wgpu::BufferAddress * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let index_count = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cluster Light Index Count Buffer"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let buffers = ClusterBuffers {
+            uniform,
+            material,
+            ranges,
+            light_indices,
+            index_count,
+        };
+
+        let lights = light::LightBuffers::new(device, &lighting_layout);
+        let (bin_bind_group, clusters_bind_group) =
+            buffers.bind_groups(device, &bin_layout, &clusters_layout, lights.points(), ao);
+        Ok(Self {
+            pipeline,
+            depth_prepass_pipeline,
+            bin_pipeline,
+            lights,
+            buffers,
+            bin_layout,
+            clusters_layout,
+            bin_bind_group,
+            clusters_bind_group,
+        })
+    }
+
+    /// `ao` is the resized `ssao::Ssao::view`.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        ao: &wgpu::TextureView,
+    ) {
+        self.buffers.uniform.update(queue, |uniform| {
+            uniform.screen_size = Vec2::new(config.width as f32, config.height as f32);
+        });
+        self.create_bind_groups(device, ao);
+    }
+
+    fn create_bind_groups(&mut self, device: &wgpu::Device, ao: &wgpu::TextureView) {
+        (self.bin_bind_group, self.clusters_bind_group) = self.buffers.bind_groups(
+            device,
+            &self.bin_layout,
+            &self.clusters_layout,
+            self.lights.points(),
+            ao,
+        );
+    }
+
+    pub fn set_material(&mut self, queue: &wgpu::Queue, material: deferred::Material) {
+        self.buffers.material.set(queue, material);
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        lights: &light::Lights,
+        atmosphere: &atmosphere::Atmosphere,
+        camera: &camera::Camera,
+        ssao: &ssao::Ssao,
+    ) {
+        if self
+            .lights
+            .update(device, queue, lights, atmosphere, camera)
+        {
+            self.create_bind_groups(device, ssao.view());
+        }
+
+        let (z_near, z_far) = camera.depth_range();
+        let light_count = self.lights.point_count();
+        self.buffers.uniform.update(queue, |uniform| {
+            uniform.inverse_projection = camera.build_projection_matrix().inverse();
+            uniform.view = camera.build_view_matrix();
+            uniform.z_near = z_near;
+            uniform.z_far = z_far;
+            uniform.light_count = light_count;
+        });
+    }
+
+    /// Rebuilds every cluster's light list, before the main pass.
+    pub fn bin_lights(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites<'_>>,
+    ) {
+        encoder.clear_buffer(&self.buffers.index_count, 0, None);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light Bin Pass"),
+            timestamp_writes,
+        });
+        compute_pass.set_pipeline(&self.bin_pipeline);
+        compute_pass.set_bind_group(0, &self.bin_bind_group, &[]);
+        let cluster_count = CLUSTER_GRID.x * CLUSTER_GRID.y * CLUSTER_GRID.z;
+        compute_pass.dispatch_workgroups(cluster_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// Draws the scene mesh's depth before `Ssao::run` and the main pass, which then loads
+    /// it.
+    pub fn depth_prepass_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.depth_prepass_pipeline
+    }
+
+    /// Replaces the scene pipeline in the main pass, after `bind`.
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    /// Sets groups 2 and 3, groups 0 and 1 are left to the scene mesh.
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(2, self.lights.bind_group(), &[]);
+        render_pass.set_bind_group(3, &self.clusters_bind_group, &[]);
+    }
+}
+
+impl ClusterBuffers {
+    fn bind_groups(
+        &self,
+        device: &wgpu::Device,
+        bin_layout: &wgpu::BindGroupLayout,
+        clusters_layout: &wgpu::BindGroupLayout,
+        points: &wgpu::Buffer,
+        ao: &wgpu::TextureView,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let bin_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: bin_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.ranges.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.light_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.index_count.as_entire_binding(),
+                },
+            ],
+            label: Some("light_bin_bind_group"),
+        });
+        let clusters_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: clusters_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.ranges.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.light_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.material.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(ao),
+                },
+            ],
+            label: Some("clusters_bind_group"),
+        });
+        (bin_bind_group, clusters_bind_group)
+    }
+}
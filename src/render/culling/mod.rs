@@ -0,0 +1,531 @@
+pub mod pyramid;
+
+use std::ops::Range;
+
+use glam::{Mat4, UVec2, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::Result;
+
+use super::{
+    reflect, texture,
+    types::{self, InstanceRaw},
+    uniform::{self, ShaderType},
+};
+
+pub use pyramid::DepthPyramid;
+
+/// Optional features that let every mesh draw with one `multi_draw_indexed_indirect`.
+/// Without them each mesh gets its own `draw_indexed_indirect`.
+pub const FEATURES: wgpu::Features =
+    wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuMeshId(u32);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct CullUniform {
+    planes: [Vec4; 6],
+    previous_view_proj: Mat4,
+    object_count: u32,
+    occlusion: u32,
+    pyramid_size: UVec2,
+    pyramid_mips: u32,
+    #[shader(padding)]
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct GpuObject {
+    model: Mat4,
+    mesh: u32,
+    #[shader(padding)]
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct GpuMeshInfo {
+    bounds: Vec4,
+    instance_offset: u32,
+    #[shader(padding)]
+    _padding: [u32; 3],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MeshRecord {
+    first_index: u32,
+    index_count: u32,
+    base_vertex: i32,
+    /// Bounding sphere in model space, center in xyz and radius in w.
+    bounds: Vec4,
+}
+
+/// Buffers sized for the current meshes and objects, recreated when they outgrow them.
+#[derive(Debug)]
+struct SceneBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    object_buffer: wgpu::Buffer,
+    mesh_buffer: wgpu::Buffer,
+    draw_buffer: wgpu::Buffer,
+    visible_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pyramid_bind_group: wgpu::BindGroup,
+}
+
+/// Instances whose transforms live in a storage buffer, culled on the GPU and drawn
+/// with indirect draws, so the CPU never touches per-instance data after upload.
+///
+/// A compute pass tests every object's bounding sphere against the view frustum and,
+/// with occlusion culling on, against a Hi-Z pyramid of the previous frame's depth. The
+/// model matrices of survivors are packed per mesh into a buffer the scene pipeline reads
+/// as instance data, and each mesh's instance count is written straight into its
+/// indirect draw arguments.
+///
+/// Occlusion uses last frame's depth, so objects coming into view from behind an
+/// occluder can pop in a frame late.
+#[derive(Debug)]
+pub struct GpuScene {
+    pipeline: wgpu::ComputePipeline,
+    scene_layout: wgpu::BindGroupLayout,
+    pyramid_layout: wgpu::BindGroupLayout,
+    uniform: uniform::UniformBuffer<CullUniform>,
+    pyramid: DepthPyramid,
+    vertices: Vec<types::Vertex>,
+    indices: Vec<u32>,
+    meshes: Vec<MeshRecord>,
+    objects: Vec<GpuObject>,
+    /// Objects per mesh, and where each mesh's run starts in the visible buffer.
+    instance_ranges: Vec<Range<u32>>,
+    buffers: Option<SceneBuffers>,
+    geometry_dirty: bool,
+    objects_dirty: bool,
+    multi_draw: bool,
+    previous_view_proj: Option<Mat4>,
+    pub occlusion: bool,
+}
+
+impl GpuScene {
+    pub fn is_supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter.get_downlevel_capabilities().flags.contains(
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION,
+        ) && device.limits().max_storage_buffers_per_shader_stage >= 4
+    }
+
+    pub fn new(device: &wgpu::Device, depth_texture: &texture::Texture) -> Result<Self> {
+        let shader_source = include_str!("../../cull.wgsl");
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        let cull_layout =
+            reflection.create_bind_group_layout(device, 0, Some("cull_bind_group_layout"))?;
+        let scene_layout =
+            reflection.create_bind_group_layout(device, 1, Some("cull_scene_bind_group_layout"))?;
+        let pyramid_layout =
+            pyramid::unfilterable_layout(device, &reflection, 2, "cull_pyramid_bind_group_layout")?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Cull Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts: &[&cull_layout, &scene_layout, &pyramid_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "cull_objects",
+        });
+
+        let uniform = uniform::UniformBuffer::new(
+            device,
+            &cull_layout,
+            bytemuck::Zeroable::zeroed(),
+            "cull_uniform",
+        );
+
+        Ok(Self {
+            pipeline,
+            scene_layout,
+            pyramid_layout,
+            uniform,
+            pyramid: DepthPyramid::new(device, depth_texture)?,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            meshes: Vec::new(),
+            objects: Vec::new(),
+            instance_ranges: Vec::new(),
+            buffers: None,
+            geometry_dirty: false,
+            objects_dirty: false,
+            multi_draw: device.features().contains(FEATURES),
+            previous_view_proj: None,
+            occlusion: true,
+        })
+    }
+
+    /// Adds a mesh for instances to use. All meshes share one vertex and index buffer.
+    pub fn add_mesh(&mut self, vertices: &[types::Vertex], indices: &[u32]) -> GpuMeshId {
+        let center = vertices
+            .iter()
+            .fold(Vec3::ZERO, |sum, vertex| sum + vertex.position())
+            / vertices.len().max(1) as f32;
+        let radius = vertices
+            .iter()
+            .map(|vertex| vertex.position().distance(center))
+            .fold(0.0, f32::max);
+
+        self.meshes.push(MeshRecord {
+            first_index: self.indices.len() as u32,
+            index_count: indices.len() as u32,
+            base_vertex: self.vertices.len() as i32,
+            bounds: center.extend(radius),
+        });
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend_from_slice(indices);
+        self.instance_ranges.push(0..0);
+        self.geometry_dirty = true;
+        self.objects_dirty = true;
+        GpuMeshId(self.meshes.len() as u32 - 1)
+    }
+
+    /// Adds instances of `mesh`, returning their object indices for `set_instance`.
+    pub fn add_instances(
+        &mut self,
+        mesh: GpuMeshId,
+        instances: &[types::Instance],
+    ) -> Range<usize> {
+        let start = self.objects.len();
+        self.objects
+            .extend(instances.iter().map(|instance| GpuObject {
+                model: instance.to_raw().model(),
+                mesh: mesh.0,
+                _padding: [0; 3],
+            }));
+        self.objects_dirty = true;
+        start..self.objects.len()
+    }
+
+    /// Moves one object, uploading only its transform.
+    pub fn set_instance(&mut self, queue: &wgpu::Queue, object: usize, instance: &types::Instance) {
+        let model = instance.to_raw().model();
+        self.objects[object].model = model;
+        if let (false, Some(buffers)) = (self.objects_dirty, &self.buffers) {
+            queue.write_buffer(
+                &buffers.object_buffer,
+                (object * std::mem::size_of::<GpuObject>()) as wgpu::BufferAddress,
+                bytemuck::bytes_of(&model),
+            );
+        }
+    }
+
+    pub fn clear_instances(&mut self) {
+        self.objects.clear();
+        self.objects_dirty = true;
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Whether every mesh draws with a single `multi_draw_indexed_indirect`.
+    pub fn is_multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    /// Recreates the depth pyramid for a resized depth texture.
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &texture::Texture) {
+        self.pyramid.resize(device, depth_texture);
+        self.previous_view_proj = None;
+        // The pyramid bind group points at the old texture.
+        self.buffers = None;
+        self.objects_dirty = true;
+        self.geometry_dirty = true;
+    }
+
+    /// Uploads changed meshes and objects and this frame's culling parameters for a
+    /// camera with `view_proj`.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: Mat4) {
+        if self.geometry_dirty || self.objects_dirty {
+            self.upload(device, queue);
+        }
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+
+        // Instance counts start at zero and are counted up by the cull pass.
+        let draws = self
+            .meshes
+            .iter()
+            .zip(&self.instance_ranges)
+            .map(|(mesh, range)| wgpu::util::DrawIndexedIndirectArgs {
+                index_count: mesh.index_count,
+                instance_count: 0,
+                first_index: mesh.first_index,
+                base_vertex: mesh.base_vertex,
+                first_instance: if self.multi_draw { range.start } else { 0 },
+            })
+            .flat_map(|args| args.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        queue.write_buffer(&buffers.draw_buffer, 0, &draws);
+
+        let (width, height) = self.pyramid.size();
+        let occlusion = self.occlusion && self.pyramid.is_valid();
+        self.uniform.set(
+            queue,
+            CullUniform {
+                planes: frustum_planes(view_proj),
+                previous_view_proj: self.previous_view_proj.unwrap_or(view_proj),
+                object_count: self.objects.len() as u32,
+                occlusion: (occlusion && self.previous_view_proj.is_some()) as u32,
+                pyramid_size: UVec2::new(width, height),
+                pyramid_mips: self.pyramid.mip_count(),
+                _padding: [0; 3],
+            },
+        );
+        self.previous_view_proj = Some(view_proj);
+    }
+
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.meshes.is_empty() || self.objects.is_empty() {
+            self.buffers = None;
+            return;
+        }
+
+        // Objects are culled in any order but packed per mesh, so each mesh's survivors
+        // need a run of the visible buffer long enough for all its objects.
+        let mut counts = vec![0u32; self.meshes.len()];
+        for object in self.objects.iter() {
+            counts[object.mesh as usize] += 1;
+        }
+        let mut offset = 0;
+        for (range, count) in self.instance_ranges.iter_mut().zip(counts) {
+            *range = offset..offset + count;
+            offset += count;
+        }
+        let mesh_infos = self
+            .meshes
+            .iter()
+            .zip(&self.instance_ranges)
+            .map(|(mesh, range)| GpuMeshInfo {
+                bounds: mesh.bounds,
+                instance_offset: range.start,
+                _padding: [0; 3],
+            })
+            .collect::<Vec<_>>();
+
+        let buffers = match self.buffers.take() {
+            Some(buffers)
+                if !self.geometry_dirty
+                    && buffers.object_buffer.size()
+                        >= std::mem::size_of_val(self.objects.as_slice()) as u64
+                    && buffers.mesh_buffer.size()
+                        >= std::mem::size_of_val(mesh_infos.as_slice()) as u64 =>
+            {
+                queue.write_buffer(
+                    &buffers.object_buffer,
+                    0,
+                    bytemuck::cast_slice(&self.objects),
+                );
+                queue.write_buffer(&buffers.mesh_buffer, 0, bytemuck::cast_slice(&mesh_infos));
+                buffers
+            }
+            _ => self.create_buffers(device, &mesh_infos),
+        };
+        self.buffers = Some(buffers);
+        self.geometry_dirty = false;
+        self.objects_dirty = false;
+    }
+
+    fn create_buffers(&self, device: &wgpu::Device, mesh_infos: &[GpuMeshInfo]) -> SceneBuffers {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gpu Scene Vertex Buffer"),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gpu Scene Index Buffer"),
+            contents: bytemuck::cast_slice(&self.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        // Room to grow, so adding a few objects doesn't recreate everything.
+        let capacity = self.objects.len().next_power_of_two();
+        let mut objects = self.objects.clone();
+        objects.resize(capacity, bytemuck::Zeroable::zeroed());
+        let object_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gpu Scene Object Buffer"),
+            contents: bytemuck::cast_slice(&objects),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let mut mesh_infos = mesh_infos.to_vec();
+        mesh_infos.resize(
+            self.meshes.len().next_power_of_two(),
+            bytemuck::Zeroable::zeroed(),
+        );
+        let mesh_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gpu Scene Mesh Buffer"),
+            contents: bytemuck::cast_slice(&mesh_infos),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let draw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gpu Scene Draw Buffer"),
+            size: (mesh_infos.len() * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let visible_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gpu Scene Visible Buffer"),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.scene_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: object_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: mesh_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: draw_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: visible_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull_scene_bind_group"),
+        });
+        let pyramid_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.pyramid_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(self.pyramid.view()),
+            }],
+            label: Some("cull_pyramid_bind_group"),
+        });
+
+        SceneBuffers {
+            vertex_buffer,
+            index_buffer,
+            object_buffer,
+            mesh_buffer,
+            draw_buffer,
+            visible_buffer,
+            bind_group,
+            pyramid_bind_group,
+        }
+    }
+
+    /// Whether `prepare` has uploaded anything for `cull` and `draw` to work on.
+    pub fn is_prepared(&self) -> bool {
+        self.buffers.is_some()
+    }
+
+    /// Whether `build_pyramid` records a pass this frame.
+    pub fn builds_pyramid(&self) -> bool {
+        self.occlusion && self.is_prepared()
+    }
+
+    /// Records the culling compute pass. Needs a `prepare` first.
+    pub fn cull(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites<'_>>,
+    ) {
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+            timestamp_writes,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, self.uniform.bind_group(), &[]);
+        compute_pass.set_bind_group(1, &buffers.bind_group, &[]);
+        compute_pass.set_bind_group(2, &buffers.pyramid_bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            (self.objects.len() as u32).div_ceil(WORKGROUP_SIZE),
+            1,
+            1,
+        );
+    }
+
+    /// Builds the depth pyramid next frame's occlusion culling tests against. Call once
+    /// the frame's depth is complete; does nothing while occlusion culling is off.
+    pub fn build_pyramid(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites<'_>>,
+    ) {
+        if self.builds_pyramid() {
+            self.pyramid.build(encoder, timestamp_writes);
+        }
+    }
+
+    /// Draws the culled instances with whatever pipeline and bind groups are set. The
+    /// pipeline reads `types::Vertex` from slot 0 and `types::InstanceRaw` from slot 1.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+        render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+        let stride = std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64;
+        if self.multi_draw {
+            render_pass.set_vertex_buffer(1, buffers.visible_buffer.slice(..));
+            render_pass.multi_draw_indexed_indirect(
+                &buffers.draw_buffer,
+                0,
+                self.meshes.len() as u32,
+            );
+            return;
+        }
+
+        // Without `INDIRECT_FIRST_INSTANCE` every draw starts at instance 0, so each
+        // mesh's run of the visible buffer is bound on its own.
+        let instance_size = std::mem::size_of::<InstanceRaw>() as u64;
+        for (index, range) in self.instance_ranges.iter().enumerate() {
+            if range.is_empty() {
+                continue;
+            }
+            render_pass.set_vertex_buffer(
+                1,
+                buffers
+                    .visible_buffer
+                    .slice(range.start as u64 * instance_size..range.end as u64 * instance_size),
+            );
+            render_pass.draw_indexed_indirect(&buffers.draw_buffer, index as u64 * stride);
+        }
+    }
+}
+
+/// Planes bounding what `view_proj` sees, normals pointing inwards. Depth is 0 to 1.
+fn frustum_planes(view_proj: Mat4) -> [Vec4; 6] {
+    let (x, y, z, w) = (
+        view_proj.row(0),
+        view_proj.row(1),
+        view_proj.row(2),
+        view_proj.row(3),
+    );
+    [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.truncate().length())
+}
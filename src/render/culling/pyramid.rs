@@ -0,0 +1,252 @@
+use crate::Result;
+
+use super::super::{reflect, texture};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Creates the layout of `@group(group)` with float textures marked unfilterable, which
+/// WGSL can't express and `R32Float` needs.
+pub(super) fn unfilterable_layout(
+    device: &wgpu::Device,
+    reflection: &reflect::ShaderReflection,
+    group: u32,
+    label: &str,
+) -> Result<wgpu::BindGroupLayout> {
+    let mut entries = reflection.bind_group_layout_entries(group)?;
+    for entry in entries.iter_mut() {
+        if let wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable },
+            ..
+        } = &mut entry.ty
+        {
+            *filterable = false;
+        }
+    }
+    Ok(
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &entries,
+        }),
+    )
+}
+
+/// Mip chain where every texel holds the farthest depth under it, for testing whether
+/// bounds are hidden behind what was drawn (Hi-Z occlusion culling).
+#[derive(Debug)]
+pub struct DepthPyramid {
+    copy_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    copy_layout: wgpu::BindGroupLayout,
+    downsample_layout: wgpu::BindGroupLayout,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    copy_bind_group: wgpu::BindGroup,
+    downsample_bind_groups: Vec<wgpu::BindGroup>,
+    /// Whether `build` ran since the last resize.
+    valid: bool,
+}
+
+impl DepthPyramid {
+    pub fn new(device: &wgpu::Device, depth_texture: &texture::Texture) -> Result<Self> {
+        let copy_source = include_str!("../../depth_pyramid_copy.wgsl");
+        let copy_reflection = reflect::ShaderReflection::new(copy_source)?;
+        let copy_layout = copy_reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("depth_pyramid_copy_bind_group_layout"),
+        )?;
+
+        let downsample_source = include_str!("../../depth_pyramid.wgsl");
+        let downsample_reflection = reflect::ShaderReflection::new(downsample_source)?;
+        let downsample_layout = unfilterable_layout(
+            device,
+            &downsample_reflection,
+            0,
+            "depth_pyramid_bind_group_layout",
+        )?;
+
+        let create_pipeline = |label, source: &str, layout, entry_point| {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let copy_pipeline = create_pipeline(
+            "Depth Pyramid Copy",
+            copy_source,
+            &copy_layout,
+            "copy_depth",
+        );
+        let downsample_pipeline = create_pipeline(
+            "Depth Pyramid Downsample",
+            downsample_source,
+            &downsample_layout,
+            "downsample",
+        );
+
+        let (texture, view, copy_bind_group, downsample_bind_groups) =
+            Self::create_texture(device, &copy_layout, &downsample_layout, depth_texture);
+
+        Ok(Self {
+            copy_pipeline,
+            downsample_pipeline,
+            copy_layout,
+            downsample_layout,
+            texture,
+            view,
+            copy_bind_group,
+            downsample_bind_groups,
+            valid: false,
+        })
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        copy_layout: &wgpu::BindGroupLayout,
+        downsample_layout: &wgpu::BindGroupLayout,
+        depth_texture: &texture::Texture,
+    ) -> (
+        wgpu::Texture,
+        wgpu::TextureView,
+        wgpu::BindGroup,
+        Vec<wgpu::BindGroup>,
+    ) {
+        let size = wgpu::Extent3d {
+            width: depth_texture.texture.width(),
+            height: depth_texture.texture.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth_pyramid"),
+            size,
+            mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mip_view = |mip| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let copy_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: copy_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&mip_view(0)),
+                },
+            ],
+            label: Some("depth_pyramid_copy_bind_group"),
+        });
+        let downsample_bind_groups = (1..texture.mip_level_count())
+            .map(|mip| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: downsample_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&mip_view(mip - 1)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&mip_view(mip)),
+                        },
+                    ],
+                    label: Some("depth_pyramid_bind_group"),
+                })
+            })
+            .collect();
+
+        (texture, view, copy_bind_group, downsample_bind_groups)
+    }
+
+    /// Matches the pyramid to a new depth texture. It's invalid until the next `build`.
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &texture::Texture) {
+        (
+            self.texture,
+            self.view,
+            self.copy_bind_group,
+            self.downsample_bind_groups,
+        ) = Self::create_texture(
+            device,
+            &self.copy_layout,
+            &self.downsample_layout,
+            depth_texture,
+        );
+        self.valid = false;
+    }
+
+    /// Records the reduction of the depth texture given at creation or the last resize.
+    /// Call once depth for the frame is complete.
+    pub fn build(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites<'_>>,
+    ) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Depth Pyramid Pass"),
+            timestamp_writes,
+        });
+        let dispatch = |compute_pass: &mut wgpu::ComputePass, mip: u32| {
+            let width = (self.texture.width() >> mip).max(1);
+            let height = (self.texture.height() >> mip).max(1);
+            compute_pass.dispatch_workgroups(
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        };
+
+        compute_pass.set_pipeline(&self.copy_pipeline);
+        compute_pass.set_bind_group(0, &self.copy_bind_group, &[]);
+        dispatch(&mut compute_pass, 0);
+
+        compute_pass.set_pipeline(&self.downsample_pipeline);
+        for (mip, bind_group) in self.downsample_bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            dispatch(&mut compute_pass, mip as u32 + 1);
+        }
+        drop(compute_pass);
+        self.valid = true;
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+
+    pub fn mip_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    /// Every mip, for binding to the culling shader.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    f32::consts::TAU,
+    sync::{Arc, Mutex},
+};
+
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+use crate::{lock, Result};
+
+use super::{
+    reflect, texture,
+    types::{self, VertexDescription},
+};
+
+const SPHERE_SEGMENTS: u32 = 24;
+const INITIAL_CAPACITY: u64 = 4096;
+
+pub const RED: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+pub const GREEN: Vec4 = Vec4::new(0.0, 1.0, 0.0, 1.0);
+pub const BLUE: Vec4 = Vec4::new(0.0, 0.0, 1.0, 1.0);
+pub const YELLOW: Vec4 = Vec4::new(1.0, 1.0, 0.0, 1.0);
+pub const WHITE: Vec4 = Vec4::new(1.0, 1.0, 1.0, 1.0);
+pub const GREY: Vec4 = Vec4::new(0.5, 0.5, 0.5, 1.0);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, types::VertexDescription)]
+pub struct DebugVertex {
+    position: Vec3,
+    color: [f32; 4],
+}
+
+/// Immediate-mode world-space line drawing for debugging.
+///
+/// Cheap to clone; every clone queues into the same batch, so a handle can be handed to
+/// whatever needs to draw. Shapes queued during a simulation step stay on screen until
+/// the next step starts, however many frames get rendered in between.
+///
+/// Lines are depth tested against the scene by default; `on_top` returns a handle whose
+/// lines are drawn over everything instead.
+#[derive(Debug, Clone)]
+pub struct DebugDraw {
+    lines: Arc<Mutex<DebugLines>>,
+    depth_test: bool,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Default)]
+struct DebugLines {
+    depth_tested: Vec<DebugVertex>,
+    on_top: Vec<DebugVertex>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {
+            lines: Arc::new(Mutex::new(DebugLines::default())),
+            depth_test: true,
+        }
+    }
+
+    /// A handle to the same batch whose lines ignore the depth buffer.
+    pub fn on_top(&self) -> Self {
+        Self {
+            lines: self.lines.clone(),
+            depth_test: false,
+        }
+    }
+
+    /// A handle to the same batch whose lines are hidden behind scene geometry.
+    pub fn depth_tested(&self) -> Self {
+        Self {
+            lines: self.lines.clone(),
+            depth_test: true,
+        }
+    }
+
+    /// Drops everything queued so far. `Render::update` calls this at the start of
+    /// every simulation step.
+    pub fn clear(&self) {
+        let mut lines = lock!(self.lines);
+        lines.depth_tested.clear();
+        lines.on_top.clear();
+    }
+
+    pub fn line(&self, start: Vec3, end: Vec3, color: Vec4) {
+        self.lines_from_points(&[start, end], color);
+    }
+
+    /// An axis-aligned box between `min` and `max`.
+    pub fn aabb(&self, min: Vec3, max: Vec3, color: Vec4) {
+        let center = (min + max) * 0.5;
+        self.wire_box(
+            Mat4::from_scale_rotation_translation(max - min, Quat::IDENTITY, center),
+            color,
+        );
+    }
+
+    /// The unit cube centred on the origin, transformed by `transform`.
+    pub fn wire_box(&self, transform: Mat4, color: Vec4) {
+        let corners: [Vec3; 8] = std::array::from_fn(|i| {
+            let corner = Vec3::new(
+                if i & 1 == 0 { -0.5 } else { 0.5 },
+                if i & 2 == 0 { -0.5 } else { 0.5 },
+                if i & 4 == 0 { -0.5 } else { 0.5 },
+            );
+            transform.transform_point3(corner)
+        });
+
+        #[rustfmt::skip]
+        const EDGES: [(usize, usize); 12] = [
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+        ];
+
+        let points = EDGES
+            .iter()
+            .flat_map(|&(a, b)| [corners[a], corners[b]])
+            .collect::<Vec<_>>();
+        self.lines_from_points(&points, color);
+    }
+
+    /// A circle of `radius` around `center`, in the plane perpendicular to `normal`.
+    pub fn circle(&self, center: Vec3, normal: Vec3, radius: f32, color: Vec4) {
+        let (u, v) = normal.normalize_or_zero().any_orthonormal_pair();
+        let point = |i: u32| {
+            let angle = i as f32 / SPHERE_SEGMENTS as f32 * TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+
+        let points = (0..SPHERE_SEGMENTS)
+            .flat_map(|i| [point(i), point(i + 1)])
+            .collect::<Vec<_>>();
+        self.lines_from_points(&points, color);
+    }
+
+    /// Three great circles, one around each axis.
+    pub fn sphere(&self, center: Vec3, radius: f32, color: Vec4) {
+        self.circle(center, Vec3::X, radius, color);
+        self.circle(center, Vec3::Y, radius, color);
+        self.circle(center, Vec3::Z, radius, color);
+    }
+
+    /// A line from `start` to `end` with a four-pronged head at `end`.
+    pub fn arrow(&self, start: Vec3, end: Vec3, color: Vec4) {
+        let shaft = end - start;
+        let length = shaft.length();
+        if length == 0.0 {
+            return;
+        }
+
+        let direction = shaft / length;
+        let (u, v) = direction.any_orthonormal_pair();
+        let head = length * 0.2;
+        let base = end - direction * head;
+        let spread = head * 0.5;
+
+        self.lines_from_points(
+            &[
+                start,
+                end,
+                end,
+                base + u * spread,
+                end,
+                base - u * spread,
+                end,
+                base + v * spread,
+                end,
+                base - v * spread,
+            ],
+            color,
+        );
+    }
+
+    /// The X (red), Y (green) and Z (blue) axes of `transform`, `size` units long.
+    pub fn axes(&self, transform: Mat4, size: f32) {
+        let origin = transform.transform_point3(Vec3::ZERO);
+        self.arrow(origin, transform.transform_point3(Vec3::X * size), RED);
+        self.arrow(origin, transform.transform_point3(Vec3::Y * size), GREEN);
+        self.arrow(origin, transform.transform_point3(Vec3::Z * size), BLUE);
+    }
+
+    /// A square grid on the XZ plane around `center`, `cells` cells of `spacing` units
+    /// across.
+    pub fn grid(&self, center: Vec3, cells: u32, spacing: f32, color: Vec4) {
+        let half = cells as f32 * spacing * 0.5;
+        let points = (0..=cells)
+            .flat_map(|i| {
+                let offset = i as f32 * spacing - half;
+                [
+                    center + Vec3::new(offset, 0.0, -half),
+                    center + Vec3::new(offset, 0.0, half),
+                    center + Vec3::new(-half, 0.0, offset),
+                    center + Vec3::new(half, 0.0, offset),
+                ]
+            })
+            .collect::<Vec<_>>();
+        self.lines_from_points(&points, color);
+    }
+
+    /// Queues `points` as a line list: every pair is one segment.
+    fn lines_from_points(&self, points: &[Vec3], color: Vec4) {
+        let color = color.to_array();
+        let mut lines = lock!(self.lines);
+        let target = if self.depth_test {
+            &mut lines.depth_tested
+        } else {
+            &mut lines.on_top
+        };
+        target.extend(
+            points
+                .iter()
+                .map(|&position| DebugVertex { position, color }),
+        );
+    }
+}
+
+/// Draws everything queued on a `DebugDraw` as one line list at the end of a pass that
+/// has the scene's depth buffer attached.
+#[derive(Debug)]
+pub struct DebugRenderer {
+    depth_tested_pipeline: wgpu::RenderPipeline,
+    on_top_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    capacity: u64,
+    depth_tested_count: u32,
+    on_top_count: u32,
+}
+
+impl DebugRenderer {
+    /// `camera_bind_group_layout` must match the layout of `@group(0)` in
+    /// `debug_draw.wgsl`.
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader_source = include_str!("../debug_draw.wgsl");
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers("vs_main", &[DebugVertex::desc()])?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Debug Draw Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Draw Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label: &str, depth_compare: wgpu::CompareFunction| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[DebugVertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                // Debug lines never write depth, so they can't hide each other or the
+                // scene.
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        Ok(Self {
+            depth_tested_pipeline: create_pipeline(
+                "Debug Draw Pipeline",
+                wgpu::CompareFunction::LessEqual,
+            ),
+            on_top_pipeline: create_pipeline(
+                "Debug Draw On Top Pipeline",
+                wgpu::CompareFunction::Always,
+            ),
+            vertex_buffer: create_vertex_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            depth_tested_count: 0,
+            on_top_count: 0,
+        })
+    }
+
+    /// Uploads the lines currently queued on `debug_draw`, growing the vertex buffer if
+    /// needed. Call before the pass that draws them is recorded.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, debug_draw: &DebugDraw) {
+        let lines = lock!(debug_draw.lines);
+        let count = (lines.depth_tested.len() + lines.on_top.len()) as u64;
+
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(device, self.capacity);
+        }
+
+        let stride = std::mem::size_of::<DebugVertex>() as u64;
+        queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&lines.depth_tested),
+        );
+        queue.write_buffer(
+            &self.vertex_buffer,
+            lines.depth_tested.len() as u64 * stride,
+            bytemuck::cast_slice(&lines.on_top),
+        );
+
+        self.depth_tested_count = lines.depth_tested.len() as u32;
+        self.on_top_count = lines.on_top.len() as u32;
+    }
+
+    /// Records the draws. Depth-tested lines go first so lines drawn on top always are.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.depth_tested_count + self.on_top_count == 0 {
+            return;
+        }
+
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        let on_top_start = self.depth_tested_count;
+        if self.depth_tested_count > 0 {
+            render_pass.set_pipeline(&self.depth_tested_pipeline);
+            render_pass.draw(0..on_top_start, 0..1);
+        }
+        if self.on_top_count > 0 {
+            render_pass.set_pipeline(&self.on_top_pipeline);
+            render_pass.draw(on_top_start..on_top_start + self.on_top_count, 0..1);
+        }
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Debug Draw Vertex Buffer"),
+        size: capacity * std::mem::size_of::<DebugVertex>() as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,454 @@
+use strum::{EnumIter, IntoEnumIterator};
+use wgpu::util::DeviceExt;
+
+use crate::Result;
+
+use super::{
+    reflect, texture,
+    types::{self, VertexDescription},
+    uniform::{self, ShaderType},
+};
+
+/// Lets wireframe use `PolygonMode::Line` instead of the barycentric fallback.
+pub const FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
+const OVERDRAW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter)]
+pub enum ViewMode {
+    #[default]
+    Shaded,
+    Wireframe,
+    /// Linearized scene depth, white near and black far.
+    Depth,
+    /// Flat face normals mapped to RGB.
+    Normals,
+    /// Texture coordinates as a checkerboard tinted by U and V.
+    UvChecker,
+    /// How many fragments each pixel shades, from black through blue, green and yellow
+    /// to red at 16 or more.
+    Overdraw,
+}
+
+impl ViewMode {
+    pub fn next(self) -> Self {
+        Self::iter()
+            .cycle()
+            .skip_while(|&mode| mode != self)
+            .nth(1)
+            .unwrap_or_default()
+    }
+
+    /// Whether `DebugViews::resolve` draws anything in this mode.
+    pub fn needs_resolve(self) -> bool {
+        matches!(self, Self::Depth | Self::Overdraw)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct DebugViewUniform {
+    znear: f32,
+    zfar: f32,
+}
+
+/// How the scene mesh should be drawn in the main pass for the current view mode.
+#[derive(Debug)]
+pub enum SceneDraw<'a> {
+    Indexed(&'a wgpu::RenderPipeline),
+    /// Draw `vertex_buffer`, a de-indexed copy of the mesh, without the index buffer.
+    Barycentric {
+        pipeline: &'a wgpu::RenderPipeline,
+        vertex_buffer: &'a wgpu::Buffer,
+        vertex_count: u32,
+    },
+}
+
+#[derive(Debug)]
+struct Barycentric {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+}
+
+/// Pipelines and targets for the debug view modes.
+///
+/// Wireframe, normals and UV checker replace the scene pipeline in the main pass. Depth
+/// and overdraw are resolved onto the frame by a full-screen pass afterwards; overdraw
+/// also needs its own additive pass into a count target first.
+#[derive(Debug)]
+pub struct DebugViews {
+    line_pipeline: Option<wgpu::RenderPipeline>,
+    barycentric: Option<Barycentric>,
+    normals_pipeline: wgpu::RenderPipeline,
+    uv_checker_pipeline: wgpu::RenderPipeline,
+    overdraw_pipeline: wgpu::RenderPipeline,
+    overdraw_texture: wgpu::Texture,
+    overdraw_view: wgpu::TextureView,
+    depth_resolve_pipeline: wgpu::RenderPipeline,
+    overdraw_resolve_pipeline: wgpu::RenderPipeline,
+    resolve_layout: wgpu::BindGroupLayout,
+    resolve_bind_group: wgpu::BindGroup,
+    params: uniform::UniformBuffer<DebugViewUniform>,
+}
+
+impl DebugViews {
+    /// `scene_layout` is the main pipeline's layout, and `vertices`/`indices` the mesh
+    /// it draws. `depth_range` is the camera's near and far plane.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        scene_layout: &wgpu::PipelineLayout,
+        depth_texture: &texture::Texture,
+        depth_range: (f32, f32),
+        vertices: &[types::Vertex],
+        indices: &[u16],
+    ) -> Result<Self> {
+        let scene_source = include_str!("../debug_view.wgsl");
+        let scene_reflection = reflect::ShaderReflection::new(scene_source)?;
+        let scene_buffers = [types::Vertex::desc(), types::InstanceRaw::desc()];
+        scene_reflection.validate_vertex_buffers("vs_main", &scene_buffers)?;
+        scene_reflection.validate_vertex_buffers("vs_barycentric", &scene_buffers)?;
+
+        let scene_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Debug View Shader"),
+            source: wgpu::ShaderSource::Wgsl(scene_source.into()),
+        });
+
+        let scene_depth = Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+
+        let scene_pipeline =
+            |label: &str,
+             vs_entry: &str,
+             fs_entry: &str,
+             primitive: wgpu::PrimitiveState,
+             target: wgpu::ColorTargetState,
+             depth_stencil: Option<wgpu::DepthStencilState>| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(scene_layout),
+                    vertex: wgpu::VertexState {
+                        module: &scene_shader,
+                        entry_point: vs_entry,
+                        buffers: &scene_buffers,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &scene_shader,
+                        entry_point: fs_entry,
+                        targets: &[Some(target)],
+                    }),
+                    primitive,
+                    depth_stencil,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+            };
+
+        let culled = wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        };
+        let opaque = wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+
+        let (line_pipeline, barycentric) = if device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            let pipeline = scene_pipeline(
+                "Wireframe Pipeline",
+                "vs_main",
+                "fs_wireframe",
+                wgpu::PrimitiveState {
+                    polygon_mode: wgpu::PolygonMode::Line,
+                    ..Default::default()
+                },
+                opaque.clone(),
+                scene_depth.clone(),
+            );
+            (Some(pipeline), None)
+        } else {
+            let pipeline = scene_pipeline(
+                "Barycentric Wireframe Pipeline",
+                "vs_barycentric",
+                "fs_barycentric",
+                wgpu::PrimitiveState::default(),
+                wgpu::ColorTargetState {
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    ..opaque.clone()
+                },
+                scene_depth.clone(),
+            );
+            let deindexed = indices
+                .iter()
+                .map(|&index| vertices[index as usize])
+                .collect::<Vec<_>>();
+            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Barycentric Wireframe Vertex Buffer"),
+                contents: bytemuck::cast_slice(&deindexed),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            let barycentric = Barycentric {
+                pipeline,
+                vertex_buffer,
+                vertex_count: deindexed.len() as u32,
+            };
+            (None, Some(barycentric))
+        };
+
+        let normals_pipeline = scene_pipeline(
+            "Normals Pipeline",
+            "vs_main",
+            "fs_normals",
+            culled,
+            opaque.clone(),
+            scene_depth.clone(),
+        );
+        let uv_checker_pipeline = scene_pipeline(
+            "UV Checker Pipeline",
+            "vs_main",
+            "fs_uv_checker",
+            culled,
+            opaque.clone(),
+            scene_depth.clone(),
+        );
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        // No depth test, so every fragment the rasterizer produces is counted.
+        let overdraw_pipeline = scene_pipeline(
+            "Overdraw Pipeline",
+            "vs_main",
+            "fs_overdraw",
+            culled,
+            wgpu::ColorTargetState {
+                format: OVERDRAW_FORMAT,
+                blend: Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+            None,
+        );
+
+        let resolve_source = include_str!("../debug_view_resolve.wgsl");
+        let resolve_reflection = reflect::ShaderReflection::new(resolve_source)?;
+        let resolve_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Debug View Resolve Shader"),
+            source: wgpu::ShaderSource::Wgsl(resolve_source.into()),
+        });
+
+        let resolve_layout = resolve_reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("debug_view_resolve_bind_group_layout"),
+        )?;
+        let params_layout = resolve_reflection.create_bind_group_layout(
+            device,
+            1,
+            Some("debug_view_params_bind_group_layout"),
+        )?;
+        let params = uniform::UniformBuffer::new(
+            device,
+            &params_layout,
+            DebugViewUniform {
+                znear: depth_range.0,
+                zfar: depth_range.1,
+            },
+            "debug_view_params",
+        );
+
+        let resolve_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug View Resolve Pipeline Layout"),
+                bind_group_layouts: &[&resolve_layout, &params_layout],
+                push_constant_ranges: &[],
+            });
+
+        let resolve_pipeline = |label: &str, fs_entry: &str| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&resolve_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &resolve_shader,
+                    entry_point: "vs_fullscreen",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &resolve_shader,
+                    entry_point: fs_entry,
+                    targets: &[Some(opaque.clone())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let (overdraw_texture, overdraw_view) = create_overdraw_target(device, config);
+        let resolve_bind_group =
+            create_resolve_bind_group(device, &resolve_layout, depth_texture, &overdraw_view);
+
+        Ok(Self {
+            line_pipeline,
+            barycentric,
+            normals_pipeline,
+            uv_checker_pipeline,
+            overdraw_pipeline,
+            overdraw_texture,
+            overdraw_view,
+            depth_resolve_pipeline: resolve_pipeline("Depth Resolve Pipeline", "fs_depth"),
+            overdraw_resolve_pipeline: resolve_pipeline("Overdraw Resolve Pipeline", "fs_overdraw"),
+            resolve_layout,
+            resolve_bind_group,
+            params,
+        })
+    }
+
+    /// Recreates the size-dependent targets. `depth_texture` must be the new one.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &texture::Texture,
+    ) {
+        (self.overdraw_texture, self.overdraw_view) = create_overdraw_target(device, config);
+        self.resolve_bind_group = create_resolve_bind_group(
+            device,
+            &self.resolve_layout,
+            depth_texture,
+            &self.overdraw_view,
+        );
+    }
+
+    /// The main pass pipeline for `mode`, falling back to `shaded` for modes that
+    /// resolve after it.
+    pub fn scene_draw<'a>(
+        &'a self,
+        mode: ViewMode,
+        shaded: &'a wgpu::RenderPipeline,
+    ) -> SceneDraw<'a> {
+        match mode {
+            ViewMode::Wireframe => match (&self.line_pipeline, &self.barycentric) {
+                (Some(pipeline), _) => SceneDraw::Indexed(pipeline),
+                (None, Some(barycentric)) => SceneDraw::Barycentric {
+                    pipeline: &barycentric.pipeline,
+                    vertex_buffer: &barycentric.vertex_buffer,
+                    vertex_count: barycentric.vertex_count,
+                },
+                (None, None) => SceneDraw::Indexed(shaded),
+            },
+            ViewMode::Normals => SceneDraw::Indexed(&self.normals_pipeline),
+            ViewMode::UvChecker => SceneDraw::Indexed(&self.uv_checker_pipeline),
+            ViewMode::Shaded | ViewMode::Depth | ViewMode::Overdraw => SceneDraw::Indexed(shaded),
+        }
+    }
+
+    /// Color attachment for the overdraw pass, cleared to zero.
+    pub fn overdraw_attachment(&self) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: &self.overdraw_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+
+    /// Scene pipeline for the overdraw pass, which must have no depth attachment.
+    pub fn overdraw_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.overdraw_pipeline
+    }
+
+    /// Replaces the frame in `view` with the depth or overdraw visualization. Does
+    /// nothing for the other modes.
+    pub fn resolve(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        mode: ViewMode,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'_>>,
+    ) {
+        let pipeline = match mode {
+            ViewMode::Depth => &self.depth_resolve_pipeline,
+            ViewMode::Overdraw => &self.overdraw_resolve_pipeline,
+            _ => return,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Debug View Resolve Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.resolve_bind_group, &[]);
+        render_pass.set_bind_group(1, self.params.bind_group(), &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_overdraw_target(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("overdraw_texture"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OVERDRAW_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn create_resolve_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    depth_texture: &texture::Texture,
+    overdraw_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(overdraw_view),
+            },
+        ],
+        label: Some("debug_view_resolve_bind_group"),
+    })
+}
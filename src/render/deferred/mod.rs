@@ -0,0 +1,471 @@
+use std::f32::consts::PI;
+
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use crate::Result;
+
+use super::{
+    atmosphere, camera, light, reflect, texture,
+    types::{self, VertexDescription},
+    uniform::{self, ShaderType},
+};
+
+pub const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// World space normals, signed so they don't fit a unorm format.
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Roughness in r, metallic in g.
+pub const MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+const VOLUME_RINGS: u32 = 8;
+const VOLUME_SEGMENTS: u32 = 12;
+
+/// Surface parameters of the scene mesh in the G-buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+pub struct Material {
+    pub roughness: f32,
+    pub metallic: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            roughness: 0.6,
+            metallic: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexDescription)]
+struct VolumeVertex {
+    position: Vec3,
+}
+
+#[derive(Debug)]
+struct GBuffer {
+    albedo: wgpu::TextureView,
+    normal: wgpu::TextureView,
+    material: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Deferred shading. The scene mesh is drawn once into the G-buffer, then the frame is
+/// lit from it: the sun and ambient light in one full-screen pass, and each point light
+/// as an instanced sphere covering its range, so a light only costs the pixels it can
+/// reach. Everything drawn after, transparent surfaces included, is forward shaded on
+/// top using the same depth buffer.
+#[derive(Debug)]
+pub struct DeferredRenderer {
+    gbuffer_pipeline: wgpu::RenderPipeline,
+    material: uniform::UniformBuffer<Material>,
+    directional_pipeline: wgpu::RenderPipeline,
+    point_pipeline: wgpu::RenderPipeline,
+    gbuffer_layout: wgpu::BindGroupLayout,
+    gbuffer: GBuffer,
+    lights: light::LightBuffers,
+    volume_vertex_buffer: wgpu::Buffer,
+    volume_index_buffer: wgpu::Buffer,
+    volume_index_count: u32,
+}
+
+impl DeferredRenderer {
+    /// `texture_bind_group_layout` and `camera_bind_group_layout` are the scene mesh's,
+    /// groups 0 and 1 of its pipeline. `ao` is `ssao::Ssao::view`.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_texture: &texture::Texture,
+        ao: &wgpu::TextureView,
+    ) -> Result<Self> {
+        let gbuffer_source = include_str!("../../gbuffer.wgsl");
+        let reflection = reflect::ShaderReflection::new(gbuffer_source)?;
+        reflection.validate_vertex_buffers(
+            "vs_main",
+            &[types::Vertex::desc(), types::InstanceRaw::desc()],
+        )?;
+        let material_layout =
+            reflection.create_bind_group_layout(device, 2, Some("material_bind_group_layout"))?;
+        let material = uniform::UniformBuffer::new(
+            device,
+            &material_layout,
+            Material::default(),
+            "material_uniform",
+        );
+
+        let gbuffer_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("G-Buffer Shader"),
+            source: wgpu::ShaderSource::Wgsl(gbuffer_source.into()),
+        });
+        let gbuffer_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("G-Buffer Pipeline Layout"),
+                bind_group_layouts: &[
+                    texture_bind_group_layout,
+                    camera_bind_group_layout,
+                    &material_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let gbuffer_target = |format| {
+            Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+        };
+        let gbuffer_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("G-Buffer Pipeline"),
+            layout: Some(&gbuffer_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &gbuffer_shader,
+                entry_point: "vs_main",
+                buffers: &[types::Vertex::desc(), types::InstanceRaw::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &gbuffer_shader,
+                entry_point: "fs_main",
+                targets: &[
+                    gbuffer_target(ALBEDO_FORMAT),
+                    gbuffer_target(NORMAL_FORMAT),
+                    gbuffer_target(MATERIAL_FORMAT),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let lighting_source = concat!(
+            include_str!("../../lighting.wgsl"),
+            include_str!("../../deferred_lighting.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(lighting_source)?;
+        reflection.validate_vertex_buffers(
+            "vs_volume",
+            &[VolumeVertex::desc(), light::GpuPointLight::desc()],
+        )?;
+        let gbuffer_layout =
+            reflection.create_bind_group_layout(device, 0, Some("gbuffer_bind_group_layout"))?;
+        let lighting_layout =
+            reflection.create_bind_group_layout(device, 1, Some("lighting_bind_group_layout"))?;
+
+        let lighting_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Deferred Lighting Shader"),
+            source: wgpu::ShaderSource::Wgsl(lighting_source.into()),
+        });
+        let lighting_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Deferred Lighting Pipeline Layout"),
+                bind_group_layouts: &[&gbuffer_layout, &lighting_layout],
+                push_constant_ranges: &[],
+            });
+        // The depth buffer is read by the shaders while attached, so neither pipeline
+        // may write it.
+        let read_only_depth = |depth_compare| wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+        let directional_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Deferred Directional Light Pipeline"),
+            layout: Some(&lighting_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &lighting_shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &lighting_shader,
+                entry_point: "fs_directional",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(read_only_depth(wgpu::CompareFunction::Always)),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let point_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Deferred Point Light Pipeline"),
+            layout: Some(&lighting_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &lighting_shader,
+                entry_point: "vs_volume",
+                buffers: &[VolumeVertex::desc(), light::GpuPointLight::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &lighting_shader,
+                entry_point: "fs_point",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            // Back faces behind the scene: every surface inside the volume, whether the
+            // camera is inside it or not.
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Front),
+                ..Default::default()
+            },
+            depth_stencil: Some(read_only_depth(wgpu::CompareFunction::GreaterEqual)),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let (vertices, indices) = Self::volume_mesh();
+        let volume_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Volume Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let volume_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Volume Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let gbuffer = Self::create_gbuffer(device, config, &gbuffer_layout, depth_texture, ao);
+        Ok(Self {
+            gbuffer_pipeline,
+            material,
+            directional_pipeline,
+            point_pipeline,
+            gbuffer_layout,
+            gbuffer,
+            lights: light::LightBuffers::new(device, &lighting_layout),
+            volume_vertex_buffer,
+            volume_index_buffer,
+            volume_index_count: indices.len() as u32,
+        })
+    }
+
+    /// A UV sphere around a unit sphere: scaled so the flat faces don't cut into it.
+    fn volume_mesh() -> (Vec<VolumeVertex>, Vec<u16>) {
+        let scale =
+            1.0 / ((PI / VOLUME_SEGMENTS as f32).cos() * (PI / (2 * VOLUME_RINGS) as f32).cos());
+        let mut vertices = Vec::new();
+        for ring in 0..=VOLUME_RINGS {
+            let (sin_theta, cos_theta) = (PI * ring as f32 / VOLUME_RINGS as f32).sin_cos();
+            for segment in 0..=VOLUME_SEGMENTS {
+                let (sin_phi, cos_phi) =
+                    (2.0 * PI * segment as f32 / VOLUME_SEGMENTS as f32).sin_cos();
+                vertices.push(VolumeVertex {
+                    position: Vec3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi)
+                        * scale,
+                });
+            }
+        }
+
+        let stride = VOLUME_SEGMENTS + 1;
+        let mut indices = Vec::new();
+        for ring in 0..VOLUME_RINGS {
+            for segment in 0..VOLUME_SEGMENTS {
+                let top = (ring * stride + segment) as u16;
+                let bottom = top + stride as u16;
+                // Counter-clockwise seen from outside.
+                indices.extend_from_slice(&[top, top + 1, bottom, top + 1, bottom + 1, bottom]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    fn create_gbuffer(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &texture::Texture,
+        ao: &wgpu::TextureView,
+    ) -> GBuffer {
+        let create_view = |label, format| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: config.width,
+                        height: config.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let albedo = create_view("gbuffer_albedo_texture", ALBEDO_FORMAT);
+        let normal = create_view("gbuffer_normal_texture", NORMAL_FORMAT);
+        let material = create_view("gbuffer_material_texture", MATERIAL_FORMAT);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&albedo),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&material),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(ao),
+                },
+            ],
+            label: Some("gbuffer_bind_group"),
+        });
+        GBuffer {
+            albedo,
+            normal,
+            material,
+            bind_group,
+        }
+    }
+
+    /// `depth_texture` is the one the G-buffer pass draws into, `ao` the resized
+    /// `ssao::Ssao::view`.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &texture::Texture,
+        ao: &wgpu::TextureView,
+    ) {
+        self.gbuffer =
+            Self::create_gbuffer(device, config, &self.gbuffer_layout, depth_texture, ao);
+    }
+
+    pub fn set_material(&mut self, queue: &wgpu::Queue, material: Material) {
+        self.material.set(queue, material);
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        lights: &light::Lights,
+        atmosphere: &atmosphere::Atmosphere,
+        camera: &camera::Camera,
+    ) {
+        self.lights
+            .update(device, queue, lights, atmosphere, camera);
+    }
+
+    /// Cleared G-buffer targets, drawn with `gbuffer_pipeline` along with a cleared depth
+    /// attachment.
+    pub fn gbuffer_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 3] {
+        let attachment = |view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        };
+        [
+            attachment(&self.gbuffer.albedo),
+            attachment(&self.gbuffer.normal),
+            attachment(&self.gbuffer.material),
+        ]
+    }
+
+    /// Replaces the scene pipeline in the G-buffer pass, after `bind_material`.
+    pub fn gbuffer_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.gbuffer_pipeline
+    }
+
+    /// Sets group 2, groups 0 and 1 are left to the scene mesh.
+    pub fn bind_material<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(2, self.material.bind_group(), &[]);
+    }
+
+    /// Lights the G-buffer into `view`, clearing it to `clear_color` where nothing was
+    /// drawn. `depth_texture` must be the G-buffer pass's, it stays attached read-only so
+    /// later passes can load it.
+    pub fn light(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        depth_texture: &texture::Texture,
+        clear_color: wgpu::Color,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'_>>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Deferred Lighting Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: None,
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes,
+        });
+        render_pass.set_bind_group(0, &self.gbuffer.bind_group, &[]);
+        render_pass.set_bind_group(1, self.lights.bind_group(), &[]);
+
+        render_pass.set_pipeline(&self.directional_pipeline);
+        render_pass.draw(0..3, 0..1);
+
+        if self.lights.point_count() > 0 {
+            render_pass.set_pipeline(&self.point_pipeline);
+            render_pass.set_vertex_buffer(0, self.volume_vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.lights.points().slice(..));
+            render_pass.set_index_buffer(
+                self.volume_index_buffer.slice(..),
+                wgpu::IndexFormat::Uint16,
+            );
+            render_pass.draw_indexed(0..self.volume_index_count, 0, 0..self.lights.point_count());
+        }
+    }
+}
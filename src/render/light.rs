@@ -0,0 +1,216 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use super::{
+    atmosphere::{self, FogFalloff},
+    camera, types,
+    uniform::{self, ShaderType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance where the light has faded out completely. Lit paths skip the light
+    /// beyond it.
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            range,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// Towards the light.
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::new(0.3, 0.8, 0.5).normalize(),
+            color: Vec3::ONE,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// Every light in the scene. Point lights only reach the `Deferred` and
+/// `ClusteredForward` render paths, see `RenderPath`.
+#[derive(Debug, Clone)]
+pub struct Lights {
+    pub sun: DirectionalLight,
+    pub ambient: Vec3,
+    pub points: Vec<PointLight>,
+}
+
+impl Default for Lights {
+    fn default() -> Self {
+        Self {
+            sun: DirectionalLight::default(),
+            ambient: Vec3::splat(0.1),
+            points: Vec::new(),
+        }
+    }
+}
+
+/// A point light as the shaders read it, from a storage buffer or as instance data.
+#[repr(C)]
+#[derive(
+    Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, ShaderType, types::VertexDescription,
+)]
+#[vertex(step_mode = "instance", location_offset = 1)]
+pub struct GpuPointLight {
+    /// Range in `w`.
+    position_range: Vec4,
+    /// Color times intensity.
+    color: Vec4,
+}
+
+impl From<&PointLight> for GpuPointLight {
+    fn from(light: &PointLight) -> Self {
+        Self {
+            position_range: light.position.extend(light.range),
+            color: (light.color * light.intensity).extend(0.0),
+        }
+    }
+}
+
+/// The camera and everything lighting and fog need besides the point lights.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+pub struct LightingUniform {
+    view_proj: Mat4,
+    inverse_view_proj: Mat4,
+    eye: Vec4,
+    /// Towards the sun.
+    sun_direction: Vec4,
+    /// Color times intensity.
+    sun_color: Vec4,
+    ambient: Vec4,
+    fog_color: Vec4,
+    /// Linear start and end in `x` and `y`, exponential density in `z`.
+    fog_distance: Vec4,
+    /// Density, falloff and base height.
+    fog_height: Vec4,
+    point_count: u32,
+    /// 0 for no distance fog, then linear, exponential and exponential squared.
+    fog_mode: u32,
+    #[shader(padding)]
+    _padding: [u32; 2],
+}
+
+/// `Lights` on the GPU: a `LightingUniform` and a buffer of every `GpuPointLight`,
+/// usable both as a storage buffer and as instance data.
+#[derive(Debug)]
+pub struct LightBuffers {
+    uniform: uniform::UniformBuffer<LightingUniform>,
+    points: wgpu::Buffer,
+    point_count: u32,
+}
+
+impl LightBuffers {
+    /// `layout` must match a group with just the `LightingUniform` at binding 0.
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        Self {
+            uniform: uniform::UniformBuffer::new(
+                device,
+                layout,
+                bytemuck::Zeroable::zeroed(),
+                "lighting_uniform",
+            ),
+            points: Self::create_points(device, 1),
+            point_count: 0,
+        }
+    }
+
+    fn create_points(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Point Light Buffer"),
+            size: (capacity * std::mem::size_of::<GpuPointLight>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Uploads `lights` and fog as seen from `camera`. Returns whether the point light
+    /// buffer was recreated, which invalidates bind groups holding it.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        lights: &Lights,
+        atmosphere: &atmosphere::Atmosphere,
+        camera: &camera::Camera,
+    ) -> bool {
+        let view_proj = camera.build_view_projection_matrix();
+        let mut sun_color = lights.sun.color * lights.sun.intensity;
+        if atmosphere.sky.tint_sun {
+            sun_color *= atmosphere.sky.sun_transmittance(lights.sun.direction);
+        }
+        let fog = &atmosphere.fog;
+        let (fog_mode, fog_distance) = match fog.falloff {
+            FogFalloff::None => (0, Vec4::ZERO),
+            FogFalloff::Linear { start, end } => (1, Vec4::new(start, end, 0.0, 0.0)),
+            FogFalloff::Exponential { density } => (2, Vec4::new(0.0, 0.0, density, 0.0)),
+            FogFalloff::ExponentialSquared { density } => (3, Vec4::new(0.0, 0.0, density, 0.0)),
+        };
+        self.uniform.set(
+            queue,
+            LightingUniform {
+                view_proj,
+                inverse_view_proj: view_proj.inverse(),
+                eye: camera.get_eye().extend(1.0),
+                sun_direction: lights.sun.direction.normalize_or_zero().extend(0.0),
+                sun_color: sun_color.extend(1.0),
+                ambient: lights.ambient.extend(1.0),
+                fog_color: fog.color.extend(1.0),
+                fog_distance,
+                fog_height: Vec4::new(fog.height_density, fog.height_falloff, fog.height_base, 0.0),
+                point_count: lights.points.len() as u32,
+                fog_mode,
+                _padding: [0; 2],
+            },
+        );
+
+        let points = lights
+            .points
+            .iter()
+            .map(GpuPointLight::from)
+            .collect::<Vec<_>>();
+        let size = std::mem::size_of_val(points.as_slice()) as wgpu::BufferAddress;
+        let grown = size > self.points.size();
+        if grown {
+            self.points = Self::create_points(device, points.len().next_power_of_two());
+        }
+        if size > 0 {
+            queue.write_buffer(&self.points, 0, bytemuck::cast_slice(&points));
+        }
+        self.point_count = points.len() as u32;
+        grown
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        self.uniform.bind_group()
+    }
+
+    pub fn points(&self) -> &wgpu::Buffer {
+        &self.points
+    }
+
+    pub fn point_count(&self) -> u32 {
+        self.point_count
+    }
+}
@@ -0,0 +1,546 @@
+pub mod simplify;
+
+use std::ops::Range;
+
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::{GameError, Result};
+
+use super::{
+    camera, reflect, texture,
+    types::{self, VertexDescription},
+};
+
+pub use simplify::{simplify, SimplifyOptions};
+
+/// One level's geometry, indexed with `u32` so it isn't limited to 65536 vertices.
+#[derive(Debug, Clone, Default)]
+pub struct LodMesh {
+    pub vertices: Vec<types::Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl LodMesh {
+    pub fn new(vertices: Vec<types::Vertex>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// A coarser copy for the next level, without the vertices it no longer uses.
+    pub fn simplified(&self, options: SimplifyOptions) -> Self {
+        let positions = self
+            .vertices
+            .iter()
+            .map(types::Vertex::position)
+            .collect::<Vec<_>>();
+        let indices = simplify(&positions, &self.indices, options);
+
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut vertices = Vec::new();
+        let indices = indices
+            .into_iter()
+            .map(|index| {
+                let remapped = &mut remap[index as usize];
+                if *remapped == u32::MAX {
+                    *remapped = vertices.len() as u32;
+                    vertices.push(self.vertices[index as usize]);
+                }
+                *remapped
+            })
+            .collect();
+        Self { vertices, indices }
+    }
+
+    /// Sphere around the middle of the bounding box that holds every vertex.
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        let (min, max) = self.vertices.iter().map(types::Vertex::position).fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), position| (min.min(position), max.max(position)),
+        );
+        let center = (min + max) * 0.5;
+        let radius = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position().distance(center))
+            .fold(0.0, f32::max);
+        (center, radius)
+    }
+}
+
+/// How a level's threshold is compared with each instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LodMetric {
+    /// Thresholds are the fraction of the screen height the bounding sphere covers. A
+    /// level is used while instances are at least that large, so thresholds decrease
+    /// from level to level. Adapts to the field of view and the mesh's size.
+    #[default]
+    ScreenSize,
+    /// Thresholds are distances from the camera in world units. A level is used while
+    /// instances are closer than that, so thresholds increase from level to level.
+    Distance,
+}
+
+#[derive(Debug, Clone)]
+pub struct LodLevel {
+    pub mesh: LodMesh,
+    pub threshold: f32,
+}
+
+/// Levels of one model, from the most detailed. Instances past the last level's
+/// threshold aren't drawn at all.
+#[derive(Debug, Clone, Default)]
+pub struct LodGroupDesc {
+    pub levels: Vec<LodLevel>,
+    pub metric: LodMetric,
+    /// Width of the cross-fade on the detailed side of each threshold as a fraction of
+    /// it, e.g. at 0.2 a `Distance` threshold of 50 fades from 40 to 50. While fading, an
+    /// instance is drawn at both levels with complementary dither patterns instead of
+    /// popping. 0 turns it off.
+    pub fade_band: f32,
+    /// Drawn white without one.
+    pub texture: Option<image::RgbaImage>,
+}
+
+impl LodGroupDesc {
+    /// `mesh` as the first level, followed by one level per remaining threshold, each
+    /// simplified from the one before with `options`.
+    pub fn generate(
+        mesh: LodMesh,
+        metric: LodMetric,
+        thresholds: &[f32],
+        options: SimplifyOptions,
+    ) -> Self {
+        let mut levels = Vec::<LodLevel>::with_capacity(thresholds.len());
+        let mut mesh = Some(mesh);
+        for &threshold in thresholds {
+            let mesh = mesh
+                .take()
+                .unwrap_or_else(|| levels[levels.len() - 1].mesh.simplified(options));
+            levels.push(LodLevel { mesh, threshold });
+        }
+        Self {
+            levels,
+            metric,
+            ..Default::default()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, types::VertexDescription)]
+#[vertex(step_mode = "instance", location_offset = 5)]
+struct LodInstanceRaw {
+    model: Mat4,
+    /// See `fade` in `lod.wgsl`.
+    fade: f32,
+    #[vertex(skip)]
+    _padding: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LodGroupId(usize);
+
+#[derive(Debug)]
+struct GpuLevel {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    /// Where this frame's instances of the level are in the group's instance buffer.
+    instances: Range<u32>,
+}
+
+/// GPU resources of one model and the instances placed with `set_instances`, bucketed by
+/// level every `LodRenderer::prepare`.
+#[derive(Debug)]
+pub struct LodGroup {
+    levels: Vec<GpuLevel>,
+    metric: LodMetric,
+    /// Thresholds where larger is always more detailed, so `Distance` ones are negated.
+    thresholds: Vec<f32>,
+    fade_band: f32,
+    bounds: (Vec3, f32),
+    instances: Vec<Mat4>,
+    instance_buffer: wgpu::Buffer,
+    texture: texture::Texture,
+    texture_bind_group: wgpu::BindGroup,
+    pub visible: bool,
+}
+
+impl LodGroup {
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// How many instances the last `prepare` drew at `level`, counting cross-fading ones
+    /// at both levels.
+    pub fn drawn(&self, level: usize) -> u32 {
+        self.levels[level].instances.len() as u32
+    }
+
+    pub fn texture(&self) -> &texture::Texture {
+        &self.texture
+    }
+
+    /// Places copies of the model, like the scene's instances. Their levels are picked
+    /// in the next `prepare`.
+    pub fn set_instances(&mut self, instances: &[types::Instance]) {
+        self.instances = instances
+            .iter()
+            .map(|instance| instance.to_raw().model())
+            .collect();
+    }
+
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        eye: Vec3,
+        screen_scale: f32,
+    ) {
+        let mut buckets = vec![Vec::new(); self.levels.len()];
+        let (center, radius) = self.bounds;
+        for model in &self.instances {
+            let scale = model
+                .x_axis
+                .length()
+                .max(model.y_axis.length())
+                .max(model.z_axis.length());
+            let distance = model.transform_point3(center).distance(eye);
+            let detail = match self.metric {
+                LodMetric::ScreenSize => radius * scale * screen_scale / distance.max(f32::EPSILON),
+                LodMetric::Distance => -distance,
+            };
+            for (level, fade) in select(&self.thresholds, self.fade_band, detail)
+                .into_iter()
+                .flatten()
+            {
+                buckets[level].push(LodInstanceRaw {
+                    model: *model,
+                    fade,
+                    _padding: [0.0; 3],
+                });
+            }
+        }
+
+        let mut start = 0;
+        for (level, bucket) in self.levels.iter_mut().zip(&buckets) {
+            let end = start + bucket.len() as u32;
+            level.instances = start..end;
+            start = end;
+        }
+        let raw = buckets.concat();
+        let size = std::mem::size_of_val(raw.as_slice()) as wgpu::BufferAddress;
+        if size > self.instance_buffer.size() {
+            self.instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("LOD Instance Buffer"),
+                contents: bytemuck::cast_slice(&raw),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            });
+        } else if size > 0 {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw));
+        }
+    }
+}
+
+/// The levels to draw an instance at and how much of each, given how detailed it should
+/// be in the same units as `thresholds`. A negative amount is the next level's share of a
+/// cross-fade, drawn with the complementary dither pattern.
+fn select(thresholds: &[f32], fade_band: f32, detail: f32) -> [Option<(usize, f32)>; 2] {
+    let Some(level) = thresholds.iter().position(|&threshold| detail >= threshold) else {
+        return [None, None];
+    };
+    let next = (level + 1 < thresholds.len()).then_some(level + 1);
+
+    let band = thresholds[level].abs() * fade_band;
+    let t = if band > 0.0 {
+        (detail - thresholds[level]) / band
+    } else {
+        1.0
+    };
+    if t >= 1.0 {
+        [Some((level, 1.0)), None]
+    } else if t <= 0.0 {
+        [next.map(|next| (next, 1.0)), None]
+    } else {
+        [Some((level, t)), next.map(|next| (next, -t))]
+    }
+}
+
+/// Draws instanced models with levels of detail in the main pass. Every frame, each
+/// instance's level is picked on the CPU from its distance or screen size, and the
+/// instances are bucketed into one range of the group's instance buffer per level, so a
+/// level costs one draw call however its instances are spread out.
+#[derive(Debug)]
+pub struct LodRenderer {
+    pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    groups: Vec<LodGroup>,
+}
+
+impl LodRenderer {
+    /// `camera_bind_group_layout` and `lighting_bind_group_layout` must match the layouts
+    /// of `@group(1)` and `@group(2)` in `lod.wgsl`.
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader_source = concat!(
+            include_str!("../../lighting.wgsl"),
+            include_str!("../../lod.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection
+            .validate_vertex_buffers("vs_main", &[types::Vertex::desc(), LodInstanceRaw::desc()])?;
+
+        let texture_layout = reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("lod_texture_bind_group_layout"),
+        )?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("LOD Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("LOD Pipeline Layout"),
+            bind_group_layouts: &[
+                &texture_layout,
+                camera_bind_group_layout,
+                lighting_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("LOD Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[types::Vertex::desc(), LodInstanceRaw::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Ok(Self {
+            pipeline,
+            texture_layout,
+            groups: Vec::new(),
+        })
+    }
+
+    /// Uploads every level of `desc`, without instances. Fails when there are no levels,
+    /// a level is empty or the thresholds are out of order for the metric.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        desc: &LodGroupDesc,
+    ) -> Result<LodGroupId> {
+        if desc.levels.is_empty() {
+            return Err(GameError::LodError("a LOD group needs a level".into()));
+        }
+        if let Some(index) = desc
+            .levels
+            .iter()
+            .position(|level| level.mesh.indices.is_empty())
+        {
+            return Err(GameError::LodError(format!(
+                "level {index} has no triangles"
+            )));
+        }
+        let thresholds = desc
+            .levels
+            .iter()
+            .map(|level| match desc.metric {
+                LodMetric::ScreenSize => level.threshold,
+                LodMetric::Distance => -level.threshold,
+            })
+            .collect::<Vec<_>>();
+        if thresholds.windows(2).any(|pair| pair[0] < pair[1]) {
+            return Err(GameError::LodError(format!(
+                "{:?} thresholds must go from the most to the least detailed level",
+                desc.metric
+            )));
+        }
+
+        let levels = desc
+            .levels
+            .iter()
+            .map(|level| GpuLevel {
+                vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("LOD Vertex Buffer"),
+                    contents: bytemuck::cast_slice(&level.mesh.vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                }),
+                index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("LOD Index Buffer"),
+                    contents: bytemuck::cast_slice(&level.mesh.indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                }),
+                index_count: level.mesh.indices.len() as u32,
+                instances: 0..0,
+            })
+            .collect();
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("LOD Instance Buffer"),
+            size: std::mem::size_of::<LodInstanceRaw>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let image = match &desc.texture {
+            Some(image) => image::DynamicImage::ImageRgba8(image.clone()),
+            None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255; 4]),
+            )),
+        };
+        let texture = texture::Texture::from_image(device, queue, &image, Some("lod_texture"))?;
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("lod_texture_bind_group"),
+        });
+
+        self.groups.push(LodGroup {
+            levels,
+            metric: desc.metric,
+            thresholds,
+            fade_band: desc.fade_band.max(0.0),
+            bounds: desc.levels[0].mesh.bounding_sphere(),
+            instances: Vec::new(),
+            instance_buffer,
+            texture,
+            texture_bind_group,
+            visible: true,
+        });
+        Ok(LodGroupId(self.groups.len() - 1))
+    }
+
+    pub fn group(&mut self, group: LodGroupId) -> &mut LodGroup {
+        &mut self.groups[group.0]
+    }
+
+    /// Picks every instance's level as seen from `camera` and uploads the buckets.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &camera::Camera) {
+        let eye = camera.get_eye();
+        // Bounding sphere radius over distance to the fraction of the screen height its
+        // diameter covers.
+        let screen_scale = 1.0 / (camera.get_fovy() * 0.5).tan();
+        for group in self.groups.iter_mut().filter(|group| group.visible) {
+            group.prepare(device, queue, eye, screen_scale);
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.groups.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, lighting_bind_group, &[]);
+
+        for group in self.groups.iter().filter(|group| group.visible) {
+            render_pass.set_bind_group(0, &group.texture_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, group.instance_buffer.slice(..));
+            for level in group
+                .levels
+                .iter()
+                .filter(|level| !level.instances.is_empty())
+            {
+                render_pass.set_vertex_buffer(0, level.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(level.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..level.index_count, 0, level.instances.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_distance_levels_and_fades() {
+        // Distance thresholds of 10 and 50, negated as in `LodGroup`.
+        let thresholds = [-10.0, -50.0];
+        let at = |distance: f32| select(&thresholds, 0.2, -distance);
+
+        assert_eq!(at(5.0), [Some((0, 1.0)), None]);
+        // Fading from level 0 to 1 between 8 and 10.
+        assert_eq!(at(9.0), [Some((0, 0.5)), Some((1, -0.5))]);
+        assert_eq!(at(10.0), [Some((1, 1.0)), None]);
+        assert_eq!(at(30.0), [Some((1, 1.0)), None]);
+        // The last level fades out between 40 and 50, with nothing to fade into.
+        assert_eq!(at(45.0), [Some((1, 0.5)), None]);
+        assert_eq!(at(60.0), [None, None]);
+    }
+
+    #[test]
+    fn select_screen_size_levels_and_fades() {
+        let thresholds = [0.5, 0.1];
+        let at = |size: f32| select(&thresholds, 0.2, size);
+
+        assert_eq!(at(1.0), [Some((0, 1.0)), None]);
+        let [Some((0, detailed)), Some((1, coarse))] = at(0.55) else {
+            panic!("expected a cross-fade, got {:?}", at(0.55));
+        };
+        assert!((detailed - 0.5).abs() < 1e-5 && (coarse + 0.5).abs() < 1e-5);
+        assert_eq!(at(0.3), [Some((1, 1.0)), None]);
+        assert_eq!(at(0.05), [None, None]);
+    }
+
+    #[test]
+    fn select_without_fade_band_switches_at_thresholds() {
+        let thresholds = [-10.0, -50.0];
+        assert_eq!(select(&thresholds, 0.0, -9.99), [Some((0, 1.0)), None]);
+        assert_eq!(select(&thresholds, 0.0, -10.01), [Some((1, 1.0)), None]);
+        assert_eq!(select(&thresholds, 0.0, -50.01), [None, None]);
+    }
+}
@@ -0,0 +1,599 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use glam::{DVec3, Vec3};
+
+/// Border edges are kept in place by planes through them, weighted this much more than
+/// the triangles around them.
+const BORDER_WEIGHT: f64 = 10.0;
+/// Collapses that turn a triangle further than about 78° from where it faced are
+/// rejected.
+const MIN_NORMAL_DOT: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SimplifyOptions {
+    /// Fraction of the triangles to keep.
+    pub target_ratio: f32,
+    /// Largest distance a surface may move, as a fraction of the mesh's bounding box
+    /// diagonal. Simplification stops short of `target_ratio` rather than exceed it.
+    pub max_error: f32,
+    /// Keeps vertices on open edges where they are instead of only penalizing moving
+    /// them, for meshes that must line up with their neighbours.
+    pub lock_border: bool,
+}
+
+impl Default for SimplifyOptions {
+    fn default() -> Self {
+        Self {
+            target_ratio: 0.5,
+            max_error: 0.01,
+            lock_border: false,
+        }
+    }
+}
+
+/// Symmetric 4x4 error matrix of the squared distance to a set of planes, see Garland
+/// and Heckbert's "Surface Simplification Using Quadric Error Metrics".
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric {
+    a: [f64; 10],
+    /// Total area of the planes, so the error is a mean rather than a sum.
+    weight: f64,
+}
+
+impl Quadric {
+    fn plane(normal: DVec3, point: DVec3, weight: f64) -> Self {
+        let [x, y, z] = normal.to_array();
+        let w = -normal.dot(point);
+        let a = [
+            x * x,
+            x * y,
+            x * z,
+            x * w,
+            y * y,
+            y * z,
+            y * w,
+            z * z,
+            z * w,
+            w * w,
+        ];
+        Self {
+            a: a.map(|value| value * weight),
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.a.iter_mut().zip(other.a) {
+            *a += b;
+        }
+        self.weight += other.weight;
+    }
+
+    fn error(&self, point: DVec3) -> f64 {
+        let [x, y, z] = point.to_array();
+        let a = &self.a;
+        let error = a[0] * x * x
+            + 2.0 * a[1] * x * y
+            + 2.0 * a[2] * x * z
+            + 2.0 * a[3] * x
+            + a[4] * y * y
+            + 2.0 * a[5] * y * z
+            + 2.0 * a[6] * y
+            + a[7] * z * z
+            + 2.0 * a[8] * z
+            + a[9];
+        if self.weight > 0.0 {
+            error.max(0.0) / self.weight
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Moving every copy of position `from` onto position `to`, queued by cost.
+#[derive(Debug, Clone, Copy)]
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Reversed, so the cheapest collapse is at the top of `BinaryHeap`.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// Reduces a triangle list by collapsing edges onto one of their ends, cheapest first by
+/// quadric error, and returns the triangles that are left as indices into the same
+/// vertices. No vertex is created or moved, so texture coordinates and other attributes
+/// stay valid.
+///
+/// Vertices sharing a position are collapsed together. Where their attributes differ
+/// (a texture seam), they only collapse along the seam so it doesn't tear.
+pub fn simplify(positions: &[Vec3], indices: &[u32], options: SimplifyOptions) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let target = (triangle_count as f32 * options.target_ratio.clamp(0.0, 1.0)) as usize;
+    if target >= triangle_count {
+        return indices.to_vec();
+    }
+
+    let mut welded = Vec::<DVec3>::new();
+    let mut welded_index = HashMap::new();
+    let position_of = positions
+        .iter()
+        .map(|position| {
+            *welded_index
+                // Adding 0 turns -0 into 0, which compare equal but differ in bits.
+                .entry(position.to_array().map(|value| (value + 0.0).to_bits()))
+                .or_insert_with(|| {
+                    welded.push(position.as_dvec3());
+                    welded.len() - 1
+                })
+        })
+        .collect::<Vec<_>>();
+
+    let (min, max) = welded.iter().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    );
+    let max_error = options.max_error as f64 * (max - min).length();
+    let max_cost = max_error * max_error;
+
+    let mut triangles = indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect::<Vec<_>>();
+    let mut alive = vec![true; triangles.len()];
+    let corners = |triangle: &[u32; 3]| triangle.map(|vertex| position_of[vertex as usize]);
+
+    let mut quadrics = vec![Quadric::default(); welded.len()];
+    let mut adjacent = vec![Vec::new(); welded.len()];
+    let mut edge_uses = HashMap::<(usize, usize), u32>::new();
+    for (index, triangle) in triangles.iter().enumerate() {
+        let [a, b, c] = corners(triangle);
+        for p in [a, b, c] {
+            adjacent[p].push(index);
+        }
+        for (p, q) in [(a, b), (b, c), (c, a)] {
+            *edge_uses.entry((p.min(q), p.max(q))).or_default() += 1;
+        }
+        let normal = (welded[b] - welded[a]).cross(welded[c] - welded[a]);
+        let area = normal.length() * 0.5;
+        if area > 0.0 {
+            let quadric = Quadric::plane(normal.normalize(), welded[a], area);
+            for p in [a, b, c] {
+                quadrics[p].add(&quadric);
+            }
+        }
+    }
+
+    let mut locked = vec![false; welded.len()];
+    for triangle in &triangles {
+        let [a, b, c] = corners(triangle);
+        let normal = (welded[b] - welded[a]).cross(welded[c] - welded[a]);
+        for (p, q) in [(a, b), (b, c), (c, a)] {
+            if edge_uses[&(p.min(q), p.max(q))] != 1 {
+                continue;
+            }
+            if options.lock_border {
+                locked[p] = true;
+                locked[q] = true;
+                continue;
+            }
+            let edge = welded[q] - welded[p];
+            let plane = edge.cross(normal).normalize_or_zero();
+            if plane != DVec3::ZERO {
+                let quadric =
+                    Quadric::plane(plane, welded[p], edge.length_squared() * BORDER_WEIGHT);
+                quadrics[p].add(&quadric);
+                quadrics[q].add(&quadric);
+            }
+        }
+    }
+
+    let mut versions = vec![0u32; welded.len()];
+    let mut heap = BinaryHeap::new();
+    let push = |heap: &mut BinaryHeap<Collapse>,
+                quadrics: &[Quadric],
+                versions: &[u32],
+                from: usize,
+                to: usize| {
+        if locked[from] {
+            return;
+        }
+        let mut quadric = quadrics[from];
+        quadric.add(&quadrics[to]);
+        let cost = quadric.error(welded[to]);
+        if cost <= max_cost {
+            heap.push(Collapse {
+                cost,
+                from,
+                to,
+                versions: (versions[from], versions[to]),
+            });
+        }
+    };
+    // Sorted so ties in cost always break the same way.
+    let mut edges = edge_uses.keys().copied().collect::<Vec<_>>();
+    edges.sort_unstable();
+    for (p, q) in edges {
+        push(&mut heap, &quadrics, &versions, p, q);
+        push(&mut heap, &quadrics, &versions, q, p);
+    }
+
+    let mut remaining = triangle_count;
+    while remaining > target {
+        let Some(collapse) = heap.pop() else {
+            break;
+        };
+        let Collapse { from, to, .. } = collapse;
+        if collapse.versions != (versions[from], versions[to]) {
+            continue;
+        }
+        let Some(remap) = collapse_remap(&triangles, &alive, &adjacent, &position_of, from, to)
+        else {
+            continue;
+        };
+        if !keeps_orientation(
+            &triangles,
+            &alive,
+            &adjacent[from],
+            &corners,
+            &welded,
+            from,
+            to,
+        ) {
+            continue;
+        }
+
+        let mut moved = std::mem::take(&mut adjacent[from]);
+        for &index in &moved {
+            if !alive[index] {
+                continue;
+            }
+            if corners(&triangles[index]).contains(&to) {
+                alive[index] = false;
+                remaining -= 1;
+            } else {
+                for vertex in triangles[index].iter_mut() {
+                    if position_of[*vertex as usize] == from {
+                        *vertex = remap[&*vertex];
+                    }
+                }
+            }
+        }
+        moved.retain(|&index| alive[index]);
+        adjacent[to].retain(|&index| alive[index]);
+        adjacent[to].extend(moved);
+
+        let quadric = quadrics[from];
+        quadrics[to].add(&quadric);
+        versions[from] += 1;
+        versions[to] += 1;
+
+        for neighbour in neighbours(&triangles, &adjacent[to], &corners, to) {
+            push(&mut heap, &quadrics, &versions, to, neighbour);
+            push(&mut heap, &quadrics, &versions, neighbour, to);
+        }
+    }
+
+    triangles
+        .iter()
+        .zip(&alive)
+        .filter(|(_, &alive)| alive)
+        .flat_map(|(triangle, _)| *triangle)
+        .collect()
+}
+
+/// Positions sharing a triangle with `position`, other than itself.
+fn neighbours(
+    triangles: &[[u32; 3]],
+    adjacent: &[usize],
+    corners: &impl Fn(&[u32; 3]) -> [usize; 3],
+    position: usize,
+) -> Vec<usize> {
+    let mut neighbours = adjacent
+        .iter()
+        .flat_map(|&index| corners(&triangles[index]))
+        .filter(|&p| p != position)
+        .collect::<Vec<_>>();
+    neighbours.sort_unstable();
+    neighbours.dedup();
+    neighbours
+}
+
+/// Which vertex at `to` each vertex at `from` becomes, or `None` when the collapse would
+/// tear a seam or fold the surface onto itself.
+fn collapse_remap(
+    triangles: &[[u32; 3]],
+    alive: &[bool],
+    adjacent: &[Vec<usize>],
+    position_of: &[usize],
+    from: usize,
+    to: usize,
+) -> Option<HashMap<u32, u32>> {
+    let live = |p: usize| {
+        adjacent[p]
+            .iter()
+            .copied()
+            .filter(|&index| alive[index])
+            .collect::<Vec<_>>()
+    };
+    let from_triangles = live(from);
+    let to_triangles = live(to);
+
+    // Every copy of `from` needs a copy of `to` it shares an edge with to take its
+    // attributes from, and two copies can't merge into one.
+    let mut remap = HashMap::new();
+    for &index in &from_triangles {
+        let triangle = &triangles[index];
+        for &vertex in triangle {
+            if position_of[vertex as usize] != from || remap.contains_key(&vertex) {
+                continue;
+            }
+            let partner = from_triangles
+                .iter()
+                .filter(|&&other| triangles[other].contains(&vertex))
+                .flat_map(|&other| triangles[other])
+                .find(|&other| position_of[other as usize] == to)?;
+            remap.insert(vertex, partner);
+        }
+    }
+    let mut partners = remap.values().collect::<Vec<_>>();
+    partners.sort_unstable();
+    partners.dedup();
+    if partners.len() != remap.len() {
+        return None;
+    }
+
+    // The link condition: the edge's ends may only share the neighbours across the
+    // triangles being removed, otherwise the collapse pinches the surface.
+    let ring = |triangles_of: &[usize], p: usize| {
+        let mut ring = triangles_of
+            .iter()
+            .flat_map(|&index| triangles[index].map(|vertex| position_of[vertex as usize]))
+            .filter(|&q| q != p)
+            .collect::<Vec<_>>();
+        ring.sort_unstable();
+        ring.dedup();
+        ring
+    };
+    let to_ring = ring(&to_triangles, to);
+    let shared_neighbours = ring(&from_triangles, from)
+        .into_iter()
+        .filter(|q| *q != to && to_ring.binary_search(q).is_ok())
+        .count();
+    let shared_triangles = from_triangles
+        .iter()
+        .filter(|&&index| {
+            triangles[index]
+                .iter()
+                .any(|&vertex| position_of[vertex as usize] == to)
+        })
+        .count();
+    (shared_triangles > 0 && shared_neighbours <= shared_triangles).then_some(remap)
+}
+
+/// Whether the triangles around `from` that survive the collapse keep facing roughly the
+/// same way once `from` moves to `to`.
+fn keeps_orientation(
+    triangles: &[[u32; 3]],
+    alive: &[bool],
+    adjacent: &[usize],
+    corners: &impl Fn(&[u32; 3]) -> [usize; 3],
+    welded: &[DVec3],
+    from: usize,
+    to: usize,
+) -> bool {
+    adjacent
+        .iter()
+        .filter(|&&index| alive[index])
+        .map(|&index| corners(&triangles[index]))
+        .filter(|corners| !corners.contains(&to))
+        .all(|corners| {
+            let [a, b, c] = corners.map(|p| welded[p]);
+            let [a2, b2, c2] = corners.map(|p| welded[if p == from { to } else { p }]);
+            let before = (b - a).cross(c - a);
+            let after = (b2 - a2).cross(c2 - a2);
+            before == DVec3::ZERO
+                || after.dot(before) > MIN_NORMAL_DOT * before.length() * after.length()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `n` by `n` quads over the unit square, facing +Z, raised by `height`.
+    fn grid(n: u32, height: impl Fn(f32, f32) -> f32) -> (Vec<Vec3>, Vec<u32>) {
+        let positions = (0..=n)
+            .flat_map(|y| (0..=n).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (x, y) = (x as f32 / n as f32, y as f32 / n as f32);
+                Vec3::new(x, y, height(x, y))
+            })
+            .collect();
+        let index = |x: u32, y: u32| y * (n + 1) + x;
+        let indices = (0..n)
+            .flat_map(|y| (0..n).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                [
+                    index(x, y),
+                    index(x + 1, y),
+                    index(x + 1, y + 1),
+                    index(x, y),
+                    index(x + 1, y + 1),
+                    index(x, y + 1),
+                ]
+            })
+            .collect();
+        (positions, indices)
+    }
+
+    fn normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+        indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+                (b - a).cross(c - a)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flat_grid_reaches_target_without_flipping() {
+        let (positions, indices) = grid(8, |_, _| 0.0);
+        let options = SimplifyOptions {
+            target_ratio: 0.25,
+            ..Default::default()
+        };
+        let simplified = simplify(&positions, &indices, options);
+
+        let count = simplified.len() / 3;
+        assert!(count > 0 && count <= 128 / 4, "{count} triangles left");
+        for normal in normals(&positions, &simplified) {
+            assert!(normal.z > 0.0, "triangle flipped or degenerate: {normal}");
+        }
+    }
+
+    #[test]
+    fn stops_at_max_error() {
+        let bumpy = |x: f32, y: f32| 0.2 * (x * 12.0).sin() * (y * 12.0).cos();
+        let (positions, indices) = grid(8, bumpy);
+        let target_ratio = 0.1;
+
+        let strict = simplify(
+            &positions,
+            &indices,
+            SimplifyOptions {
+                target_ratio,
+                max_error: 1e-4,
+                lock_border: false,
+            },
+        );
+        assert!(strict.len() / 3 > 12, "{} triangles left", strict.len() / 3);
+
+        let loose = simplify(
+            &positions,
+            &indices,
+            SimplifyOptions {
+                target_ratio,
+                max_error: 1.0,
+                lock_border: false,
+            },
+        );
+        assert!(loose.len() / 3 <= 12, "{} triangles left", loose.len() / 3);
+    }
+
+    #[test]
+    fn lock_border_keeps_border_vertices() {
+        let n = 8;
+        let (positions, indices) = grid(n, |_, _| 0.0);
+        let simplified = simplify(
+            &positions,
+            &indices,
+            SimplifyOptions {
+                target_ratio: 0.1,
+                max_error: 1.0,
+                lock_border: true,
+            },
+        );
+        assert!(simplified.len() < indices.len());
+
+        for y in 0..=n {
+            for x in 0..=n {
+                if x == 0 || y == 0 || x == n || y == n {
+                    let vertex = y * (n + 1) + x;
+                    assert!(simplified.contains(&vertex), "border vertex {vertex} moved");
+                }
+            }
+        }
+        for normal in normals(&positions, &simplified) {
+            assert!(normal.z > 0.0, "triangle flipped or degenerate: {normal}");
+        }
+    }
+
+    #[test]
+    fn cube_with_seams_stays_closed_and_outward() {
+        // Every face has its own vertices, so each cube edge is a seam between two faces.
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for axis in 0..3 {
+            for sign in [-1.0f32, 1.0] {
+                let (face, face_indices) = grid(4, |_, _| 0.0);
+                let base = positions.len() as u32;
+                positions.extend(face.into_iter().map(|p| {
+                    let local = Vec3::new(p.x * 2.0 - 1.0, (p.y * 2.0 - 1.0) * sign, sign);
+                    match axis {
+                        0 => local,
+                        1 => Vec3::new(local.z, local.x, local.y),
+                        _ => Vec3::new(local.y, local.z, local.x),
+                    }
+                }));
+                indices.extend(face_indices.into_iter().map(|index| base + index));
+            }
+        }
+        for (normal, triangle) in normals(&positions, &indices)
+            .into_iter()
+            .zip(indices.chunks_exact(3))
+        {
+            assert!(normal.dot(positions[triangle[0] as usize]) > 0.0);
+        }
+
+        let simplified = simplify(
+            &positions,
+            &indices,
+            SimplifyOptions {
+                target_ratio: 0.1,
+                max_error: 0.01,
+                lock_border: false,
+            },
+        );
+        assert!(simplified.len() < indices.len() / 2);
+
+        let triangles = simplified.chunks_exact(3).collect::<Vec<_>>();
+        for (normal, triangle) in normals(&positions, &simplified).into_iter().zip(&triangles) {
+            let centroid = triangle
+                .iter()
+                .map(|&index| positions[index as usize])
+                .sum::<Vec3>()
+                / 3.0;
+            assert!(normal.dot(centroid) > 0.0, "triangle faces inwards");
+        }
+
+        // Closed: every edge, by position, is used exactly twice, in opposite directions.
+        let key = |index: u32| positions[index as usize].to_array().map(f32::to_bits);
+        let mut edges = HashMap::new();
+        for triangle in &triangles {
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                *edges
+                    .entry((key(triangle[a]), key(triangle[b])))
+                    .or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &uses) in &edges {
+            assert_eq!(uses, 1);
+            assert_eq!(edges.get(&(b, a)), Some(&1), "open or torn edge");
+        }
+    }
+}
@@ -1,6 +1,29 @@
+pub mod atlas;
+pub mod atmosphere;
 pub mod camera;
+pub mod capture;
+pub mod clustered;
+pub mod culling;
+pub mod debug_draw;
+pub mod debug_view;
+pub mod deferred;
+pub mod light;
+pub mod lod;
+pub mod morph;
+pub mod particles;
+pub mod profiler;
+pub mod reflect;
+pub mod ring;
+pub mod skinning;
+pub mod sprite;
+pub mod ssao;
+pub mod text;
 pub mod texture;
+pub mod transparent;
 pub mod types;
+pub mod ui;
+pub mod uniform;
+pub mod upload;
 use cgmath::Zero;
 
 use std::{f32::consts::PI, sync::Arc};
@@ -23,6 +46,24 @@ const INSTANCE_DISPLACEMENT: glam::Vec3 = glam::Vec3::new(
     NUM_INSTANCES_PER_ROW as f32 * 0.5,
 );
 
+/// How the opaque scene mesh is shaded, picked when `Render` is created. Parses from
+/// snake case names, e.g. `"deferred"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum RenderPath {
+    /// Lit by the sun and ambient light of `Lights` in the main pass, with
+    /// `Atmosphere::fog` over it. Point lights are ignored, and without a depth prepass
+    /// there's no `ssao::Ssao` to occlude the ambient light.
+    #[default]
+    Forward,
+    /// Lit by `Lights` from a G-buffer, see `deferred::DeferredRenderer`.
+    Deferred,
+    /// Lit by `Lights` in the main pass, each fragment only by the point lights binned
+    /// into its cluster, see `clustered::ClusteredRenderer`. Falls back to `Forward`
+    /// without compute shaders.
+    ClusteredForward,
+}
+
 #[derive(Debug)]
 pub struct Render<'a> {
     surface: wgpu::Surface<'a>,
@@ -37,14 +78,40 @@ pub struct Render<'a> {
     diffuse_texture: texture::Texture,
     depth_texture: texture::Texture,
     camera: camera::Camera,
-    camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
+    camera_buffer: uniform::UniformBuffer<types::CameraUniform>,
     instances: Vec<types::Instance>,
     instance_buffer: wgpu::Buffer,
+    gpu_scene: Option<culling::GpuScene>,
+    gpu_driven: bool,
+    uploader: upload::Uploader,
+    capture: capture::Capture,
+    profiler: profiler::GpuProfiler,
+    debug_draw: debug_draw::DebugDraw,
+    debug_renderer: debug_draw::DebugRenderer,
+    view_mode: debug_view::ViewMode,
+    debug_views: debug_view::DebugViews,
+    render_path: RenderPath,
+    lights: light::Lights,
+    atmosphere: atmosphere::Atmosphere,
+    /// The sun, ambient light and fog for the forward lit meshes, see `sunlit` in
+    /// `lighting.wgsl`.
+    lighting: light::LightBuffers,
+    sky: atmosphere::SkyRenderer,
+    deferred: Option<deferred::DeferredRenderer>,
+    clustered: Option<clustered::ClusteredRenderer>,
+    ssao: Option<ssao::Ssao>,
+    skinning: Option<skinning::SkinnedRenderer>,
+    morph: Option<morph::MorphRenderer>,
+    lod: lod::LodRenderer,
+    transparent: transparent::TransparentRenderer,
+    particles: particles::ParticleSystem,
+    sprites: sprite::SpriteBatch,
+    text: text::TextRenderer,
+    ui: ui::Ui,
 }
 
 impl Render<'_> {
-    pub async fn new(window: Arc<Window>) -> Result<Self, GameError> {
+    pub async fn new(window: Arc<Window>, render_path: RenderPath) -> Result<Self, GameError> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -57,7 +124,7 @@ impl Render<'_> {
         // # Safety
         //
         // The surface needs to live as long as the window that created it.
-        let surface = instance.create_surface(window).unwrap();
+        let surface = instance.create_surface(window.clone()).unwrap();
 
         // The adapter is a handle to a physical GPU
         let adapter = instance
@@ -73,7 +140,11 @@ impl Render<'_> {
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
+                    // Query features are only used once the profiler is switched on,
+                    // wireframe has a fallback for adapters without line polygon mode, and
+                    // GPU-driven drawing falls back to one indirect draw per mesh.
+                    required_features: adapter.features()
+                        & (profiler::FEATURES | debug_view::FEATURES | culling::FEATURES),
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web, we'll have to disable some.
                     required_limits: if cfg!(target_arch = "wasm32") {
@@ -102,7 +173,9 @@ impl Render<'_> {
             );
 
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // COPY_SRC lets `capture::Capture` read frames back where the surface allows it.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC),
             format: *surface_format,
             width: size.width,
             height: size.height,
@@ -121,30 +194,20 @@ impl Render<'_> {
             "diffuse_texture",
         )?;
 
+        // Bind group layouts and vertex inputs come from the shader itself, so the
+        // Rust side can't silently drift from the `@group/@binding` declarations.
+        let shader_source = concat!(
+            include_str!("../lighting.wgsl"),
+            include_str!("../shader.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers(
+            "vs_main",
+            &[types::Vertex::desc(), types::InstanceRaw::desc()],
+        )?;
+
         let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
+            reflection.create_bind_group_layout(&device, 0, Some("texture_bind_group_layout"))?;
 
         let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &texture_bind_group_layout,
@@ -163,7 +226,7 @@ impl Render<'_> {
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shader.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
         });
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -180,35 +243,19 @@ impl Render<'_> {
 
         let camera = camera::Camera::new(size.into());
 
-        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera Buffer"),
-            contents: bytemuck::cast_slice(&[camera.get_uniform()]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
         let camera_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("camera_bind_group_layout"),
-            });
+            reflection.create_bind_group_layout(&device, 1, Some("camera_bind_group_layout"))?;
 
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
+        let camera_buffer = uniform::UniformBuffer::new(
+            &device,
+            &camera_bind_group_layout,
+            camera.get_uniform(),
+            "camera_buffer",
+        );
+
+        let lighting_bind_group_layout =
+            reflection.create_bind_group_layout(&device, 2, Some("lighting_bind_group_layout"))?;
+        let lighting = light::LightBuffers::new(&device, &lighting_bind_group_layout);
 
         let instances = (0..NUM_INSTANCES_PER_ROW)
             .flat_map(|z| {
@@ -239,19 +286,29 @@ impl Render<'_> {
         let depth_texture =
             texture::Texture::create_depth_texture(&device, &config, "depth_texture");
 
+        // The debug views draw the scene unlit, so they leave out the lighting group.
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
             bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
             push_constant_ranges: &[],
         });
+        let lit_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lit Render Pipeline Layout"),
+            bind_group_layouts: &[
+                &texture_bind_group_layout,
+                &camera_bind_group_layout,
+                &lighting_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
 
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
-            layout: Some(&pipeline_layout),
+            layout: Some(&lit_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: "vs_main", // 1.
-                buffers: &[types::Vertex::desc(), types::Instance::desc()], // 2.
+                buffers: &[types::Vertex::desc(), types::InstanceRaw::desc()], // 2.
             },
             fragment: Some(wgpu::FragmentState {
                 // 3.
@@ -288,6 +345,116 @@ impl Render<'_> {
             multiview: None,
         });
 
+        let debug_views = debug_view::DebugViews::new(
+            &device,
+            &config,
+            &pipeline_layout,
+            &depth_texture,
+            camera.depth_range(),
+            types::VERTICES,
+            types::INDICES,
+        )?;
+        let debug_renderer =
+            debug_draw::DebugRenderer::new(&device, config.format, &camera_bind_group_layout)?;
+        // Mirrors the instanced scene mesh so `set_gpu_driven` can switch between the two.
+        let gpu_scene = if culling::GpuScene::is_supported(&adapter, &device) {
+            let mut gpu_scene = culling::GpuScene::new(&device, &depth_texture)?;
+            let indices = types::INDICES
+                .iter()
+                .map(|&index| index as u32)
+                .collect::<Vec<_>>();
+            let mesh = gpu_scene.add_mesh(types::VERTICES, &indices);
+            gpu_scene.add_instances(mesh, &instances);
+            Some(gpu_scene)
+        } else {
+            None
+        };
+        let (skinning, morph) = if skinning::SkinnedRenderer::is_supported(&adapter, &device) {
+            (
+                Some(skinning::SkinnedRenderer::new(
+                    &device,
+                    config.format,
+                    &camera_bind_group_layout,
+                    &lighting_bind_group_layout,
+                )?),
+                Some(morph::MorphRenderer::new(
+                    &device,
+                    config.format,
+                    &camera_bind_group_layout,
+                    &lighting_bind_group_layout,
+                )?),
+            )
+        } else {
+            log::warn!(
+                "Vertex storage buffers unsupported, skinned and morphed meshes won't be drawn"
+            );
+            (None, None)
+        };
+        let mut render_path = render_path;
+        if render_path == RenderPath::ClusteredForward
+            && !clustered::ClusteredRenderer::is_supported(&adapter, &device)
+        {
+            log::warn!("Compute shaders unsupported, falling back to forward rendering");
+            render_path = RenderPath::Forward;
+        }
+        let ssao = match render_path {
+            RenderPath::Forward => None,
+            _ => Some(ssao::Ssao::new(&device, &config, &depth_texture)?),
+        };
+        let (deferred, clustered) = match (render_path, &ssao) {
+            (RenderPath::Deferred, Some(ssao)) => (
+                Some(deferred::DeferredRenderer::new(
+                    &device,
+                    &config,
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &depth_texture,
+                    ssao.view(),
+                )?),
+                None,
+            ),
+            (RenderPath::ClusteredForward, Some(ssao)) => (
+                None,
+                Some(clustered::ClusteredRenderer::new(
+                    &device,
+                    &config,
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    ssao.view(),
+                )?),
+            ),
+            _ => (None, None),
+        };
+        let sky = atmosphere::SkyRenderer::new(&device, config.format)?;
+        let lod = lod::LodRenderer::new(
+            &device,
+            config.format,
+            &camera_bind_group_layout,
+            &lighting_bind_group_layout,
+        )?;
+        let transparent = transparent::TransparentRenderer::new(
+            &device,
+            &config,
+            &camera_bind_group_layout,
+            &lighting_bind_group_layout,
+        )?;
+        let compute = particles::ParticleSystem::supports_compute(&adapter, &device);
+        if !compute {
+            log::warn!("Compute shaders unsupported, particles are simulated on the CPU");
+        }
+        let particles = particles::ParticleSystem::new(
+            &device,
+            config.format,
+            &camera_bind_group_layout,
+            &lighting_bind_group_layout,
+            compute,
+        )?;
+        let sprites = sprite::SpriteBatch::new(&device, &config, &camera_bind_group_layout)?;
+        let text = text::TextRenderer::new(&device, &config, &camera_bind_group_layout)?;
+        let ui = ui::Ui::new(window, &device, config.format);
+        let capture = capture::Capture::new(&config);
+        let profiler = profiler::GpuProfiler::new(&device, &queue);
+
         Ok(Self {
             surface,
             device,
@@ -302,12 +469,37 @@ impl Render<'_> {
             depth_texture,
             camera,
             camera_buffer,
-            camera_bind_group,
             instances,
             instance_buffer,
+            gpu_scene,
+            gpu_driven: false,
+            uploader: upload::Uploader::new(upload::DEFAULT_FRAME_BUDGET),
+            capture,
+            profiler,
+            debug_draw: debug_draw::DebugDraw::new(),
+            debug_renderer,
+            view_mode: debug_view::ViewMode::default(),
+            debug_views,
+            render_path,
+            lights: light::Lights::default(),
+            atmosphere: atmosphere::Atmosphere::default(),
+            lighting,
+            sky,
+            deferred,
+            clustered,
+            ssao,
+            skinning,
+            morph,
+            lod,
+            transparent,
+            particles,
+            sprites,
+            text,
+            ui,
         })
     }
 
+    #[tracing::instrument(name = "Render::resize", skip(self))]
     pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
         self.size = size;
         self.config.width = size.width;
@@ -319,20 +511,343 @@ impl Render<'_> {
 
         self.depth_texture =
             texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+        self.debug_views
+            .resize(&self.device, &self.config, &self.depth_texture);
+        if let Some(ssao) = &mut self.ssao {
+            ssao.resize(&self.device, &self.config, &self.depth_texture);
+            if let Some(deferred) = &mut self.deferred {
+                deferred.resize(&self.device, &self.config, &self.depth_texture, ssao.view());
+            }
+            if let Some(clustered) = &mut self.clustered {
+                clustered.resize(&self.device, &self.queue, &self.config, ssao.view());
+            }
+        }
+        self.transparent.resize(&self.device, &self.config);
+        if let Some(gpu_scene) = &mut self.gpu_scene {
+            gpu_scene.resize(&self.device, &self.depth_texture);
+        }
+        self.sprites
+            .resize(&self.queue, self.config.width, self.config.height);
+        self.text
+            .resize(&self.queue, self.config.width, self.config.height);
+    }
+
+    /// Budgeted upload path for large buffers and textures, see `upload::Uploader`.
+    pub fn uploader(&mut self) -> &mut upload::Uploader {
+        &mut self.uploader
+    }
+
+    /// Screenshot and frame sequence capture, see `capture::Capture`.
+    pub fn capture(&mut self) -> &mut capture::Capture {
+        &mut self.capture
+    }
+
+    /// Opt-in GPU/CPU frame profiler, see `profiler::GpuProfiler`.
+    pub fn profiler(&mut self) -> &mut profiler::GpuProfiler {
+        &mut self.profiler
+    }
+
+    /// GPU-culled instances, `None` where compute shaders or indirect draws are
+    /// unavailable. See `culling::GpuScene`.
+    pub fn gpu_scene(&mut self) -> Option<&mut culling::GpuScene> {
+        self.gpu_scene.as_mut()
+    }
+
+    pub fn is_gpu_driven(&self) -> bool {
+        self.gpu_driven
+    }
+
+    /// Draws the scene from `gpu_scene` instead of the CPU instance buffer, where
+    /// supported. The barycentric debug views keep using the CPU path.
+    pub fn set_gpu_driven(&mut self, enabled: bool) {
+        self.gpu_driven = enabled && self.gpu_scene.is_some();
+    }
+
+    /// Handle for queuing debug lines, see `debug_draw::DebugDraw`.
+    pub fn debug_draw(&self) -> &debug_draw::DebugDraw {
+        &self.debug_draw
+    }
+
+    /// 2D sprites and nine-slice panels, see `sprite::SpriteBatch`.
+    pub fn sprites(&mut self) -> &mut sprite::SpriteBatch {
+        &mut self.sprites
+    }
+
+    /// Skinned mesh renderer, `None` where vertex shaders can't read storage buffers.
+    pub fn skinning(&mut self) -> Option<&mut skinning::SkinnedRenderer> {
+        self.skinning.as_mut()
+    }
+
+    /// Uploads `model` for drawing in the main pass. Returns `None` where skinning isn't
+    /// supported.
+    pub fn load_skinned_model(
+        &mut self,
+        model: &skinning::SkinnedModel,
+    ) -> Result<Option<skinning::SkinnedMeshId>, GameError> {
+        match &mut self.skinning {
+            Some(skinning) => Ok(Some(skinning.add(&self.device, &self.queue, model)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Poses a mesh from `load_skinned_model`, placed in the world by `transform`.
+    pub fn set_skinned_pose(
+        &mut self,
+        mesh: skinning::SkinnedMeshId,
+        pose: &skinning::Pose,
+        transform: glam::Mat4,
+    ) {
+        if let Some(skinning) = &mut self.skinning {
+            skinning.mesh(mesh).set_pose(&self.queue, pose, transform);
+        }
+    }
+
+    /// Morph target renderer, `None` where vertex shaders can't read storage buffers.
+    pub fn morph(&mut self) -> Option<&mut morph::MorphRenderer> {
+        self.morph.as_mut()
+    }
+
+    /// Uploads `model` for drawing in the main pass. Returns `None` where morph targets
+    /// aren't supported.
+    pub fn load_morph_model(
+        &mut self,
+        model: &morph::MorphModel,
+    ) -> Result<Option<morph::MorphMeshId>, GameError> {
+        match &mut self.morph {
+            Some(morph) => Ok(Some(morph.add(&self.device, &self.queue, model)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the target weights of a mesh from `load_morph_model`.
+    pub fn set_morph_weights(&mut self, mesh: morph::MorphMeshId, weights: &[f32]) {
+        if let Some(morph) = &mut self.morph {
+            morph.mesh(mesh).set_weights(&self.queue, weights);
+        }
+    }
+
+    /// Places copies of a mesh from `load_morph_model`, like the scene's instances.
+    pub fn set_morph_instances(&mut self, mesh: morph::MorphMeshId, instances: &[Instance]) {
+        if let Some(morph) = &mut self.morph {
+            morph
+                .mesh(mesh)
+                .set_instances(&self.device, &self.queue, instances);
+        }
+    }
+
+    /// Models with levels of detail, see `lod::LodRenderer`.
+    pub fn lod(&mut self) -> &mut lod::LodRenderer {
+        &mut self.lod
+    }
+
+    /// Uploads the levels of `desc` for drawing in the main pass.
+    pub fn add_lod_group(
+        &mut self,
+        desc: &lod::LodGroupDesc,
+    ) -> Result<lod::LodGroupId, GameError> {
+        self.lod.add(&self.device, &self.queue, desc)
+    }
+
+    /// Places copies of a group from `add_lod_group`, each drawn at the level that suits
+    /// its size on screen or distance this frame.
+    pub fn set_lod_instances(&mut self, group: lod::LodGroupId, instances: &[Instance]) {
+        self.lod.group(group).set_instances(instances);
     }
 
+    /// Alpha-blended meshes, see `transparent::TransparentRenderer`.
+    pub fn transparent(&mut self) -> &mut transparent::TransparentRenderer {
+        &mut self.transparent
+    }
+
+    /// Uploads a mesh for the transparent queue. It's tinted with
+    /// `transparent::TransparentMesh::color`, half transparent until changed.
+    pub fn add_transparent_mesh(
+        &mut self,
+        vertices: &[types::Vertex],
+        indices: &[u32],
+        texture: Option<&image::RgbaImage>,
+    ) -> Result<transparent::TransparentMeshId, GameError> {
+        self.transparent
+            .add(&self.device, &self.queue, vertices, indices, texture)
+    }
+
+    /// Places copies of a mesh from `add_transparent_mesh`, like the scene's instances.
+    pub fn set_transparent_instances(
+        &mut self,
+        mesh: transparent::TransparentMeshId,
+        instances: &[Instance],
+    ) {
+        self.transparent.mesh(mesh).set_instances(instances);
+    }
+
+    pub fn transparency_mode(&self) -> transparent::TransparencyMode {
+        self.transparent.mode()
+    }
+
+    /// How transparent meshes and alpha-blended particles are ordered, see
+    /// `transparent::TransparencyMode`.
+    pub fn set_transparency_mode(&mut self, mode: transparent::TransparencyMode) {
+        self.transparent.set_mode(mode);
+    }
+
+    /// Particle emitters, see `particles::ParticleSystem`.
+    pub fn particles(&mut self) -> &mut particles::ParticleSystem {
+        &mut self.particles
+    }
+
+    /// Starts simulating and drawing an emitter.
+    pub fn add_emitter(&mut self, desc: particles::EmitterDesc) -> particles::EmitterId {
+        self.particles.add_emitter(&self.device, desc)
+    }
+
+    /// Decodes an image and registers it with the sprite batch.
+    pub fn load_sprite_texture(
+        &mut self,
+        bytes: &[u8],
+        label: &str,
+    ) -> Result<sprite::TextureId, GameError> {
+        let texture = texture::Texture::from_bytes(&self.device, &self.queue, bytes, label)?;
+        Ok(self.sprites.add_texture(&self.device, texture))
+    }
+
+    /// Uploads an atlas packed at runtime and registers its pages with the sprite batch.
+    pub fn load_atlas(
+        &mut self,
+        packed: atlas::PackedAtlas,
+    ) -> Result<atlas::TextureAtlas, GameError> {
+        atlas::TextureAtlas::from_packed(&self.device, &self.queue, &mut self.sprites, packed)
+    }
+
+    /// Loads the atlas `build.rs` packed from `src/sprites`.
+    pub fn load_bundled_atlas(&mut self) -> Result<atlas::TextureAtlas, GameError> {
+        atlas::TextureAtlas::bundled(&self.device, &self.queue, &mut self.sprites)
+    }
+
+    /// Screen and world-space text, see `text::TextRenderer`.
+    pub fn text(&mut self) -> &mut text::TextRenderer {
+        &mut self.text
+    }
+
+    /// Immediate-mode UI overlay, see `ui::Ui`.
+    pub fn ui(&mut self) -> &mut ui::Ui {
+        &mut self.ui
+    }
+
+    pub fn view_mode(&self) -> debug_view::ViewMode {
+        self.view_mode
+    }
+
+    pub fn set_view_mode(&mut self, mode: debug_view::ViewMode) {
+        self.view_mode = mode;
+    }
+
+    pub fn render_path(&self) -> RenderPath {
+        self.render_path
+    }
+
+    /// Scene lighting, used by every render path except `RenderPath::Forward`.
+    pub fn lights(&mut self) -> &mut light::Lights {
+        &mut self.lights
+    }
+
+    /// Fog and sky. The sky is drawn in every render path, fog only blends over the lit
+    /// ones.
+    pub fn atmosphere(&mut self) -> &mut atmosphere::Atmosphere {
+        &mut self.atmosphere
+    }
+
+    /// Surface parameters of the scene mesh, used by every render path except
+    /// `RenderPath::Forward`.
+    pub fn set_material(&mut self, material: deferred::Material) {
+        if let Some(deferred) = &mut self.deferred {
+            deferred.set_material(&self.queue, material);
+        }
+        if let Some(clustered) = &mut self.clustered {
+            clustered.set_material(&self.queue, material);
+        }
+    }
+
+    /// Ambient occlusion settings, `None` on `RenderPath::Forward`.
+    pub fn ssao(&mut self) -> Option<&mut ssao::Ssao> {
+        self.ssao.as_mut()
+    }
+
+    pub fn request_screenshot(&mut self, path: Option<std::path::PathBuf>) {
+        self.capture.request_screenshot(path);
+    }
+
+    #[tracing::instrument(name = "Render::update", skip_all)]
     pub fn update(&mut self, input: &input::Input, delta: f64) {
+        self.debug_draw.clear();
         self.camera.update(input, delta);
+        self.capture.update(delta);
+        self.particles.update(delta);
     }
 
+    #[tracing::instrument(name = "Render::render", skip_all)]
     pub fn render(&mut self, _window: &Arc<Window>, input: &input::Input) -> Result<(), GameError> {
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera.get_uniform()]),
+        // Fires completion callbacks for finished frames, which is what lets
+        // `ring::UniformRing` recycle memory on native backends.
+        self.device.poll(wgpu::Maintain::Poll);
+        self.capture.save_ready();
+        self.profiler.begin_frame();
+
+        self.camera_buffer
+            .set(&self.queue, self.camera.get_uniform());
+
+        self.debug_renderer
+            .prepare(&self.device, &self.queue, &self.debug_draw);
+        self.sprites.prepare(&self.device, &self.queue);
+        self.text.prepare(&self.device, &self.queue);
+        self.lighting.update(
+            &self.device,
+            &self.queue,
+            &self.lights,
+            &self.atmosphere,
+            &self.camera,
+        );
+        if let Some(deferred) = &mut self.deferred {
+            deferred.prepare(
+                &self.device,
+                &self.queue,
+                &self.lights,
+                &self.atmosphere,
+                &self.camera,
+            );
+        }
+        if let Some(ssao) = &self.ssao {
+            ssao.prepare(&self.queue, &self.camera);
+            if let Some(clustered) = &mut self.clustered {
+                clustered.prepare(
+                    &self.device,
+                    &self.queue,
+                    &self.lights,
+                    &self.atmosphere,
+                    &self.camera,
+                    ssao,
+                );
+            }
+        }
+        self.sky.prepare(
+            &self.queue,
+            &self.camera,
+            self.lights.sun.direction,
+            &self.atmosphere.sky,
         );
+        self.lod.prepare(&self.device, &self.queue, &self.camera);
+        self.transparent
+            .prepare(&self.device, &self.queue, &self.camera);
+        self.particles.prepare(&self.queue, &self.camera);
+        if let (true, Some(gpu_scene)) = (self.gpu_driven, &mut self.gpu_scene) {
+            gpu_scene.prepare(
+                &self.device,
+                &self.queue,
+                self.camera.build_view_projection_matrix(),
+            );
+        }
 
-        let output = self.surface.get_current_texture()?;
+        let output =
+            tracing::info_span!("acquire_frame").in_scope(|| self.surface.get_current_texture())?;
 
         let view = output
             .texture
@@ -344,53 +859,357 @@ impl Render<'_> {
                 label: Some("Render Encoder"),
             });
 
+        // Streamed uploads are copied first so this frame's passes already see them.
+        self.uploader.record(&self.device, &mut encoder);
+        if self.particles.simulates_on_gpu() {
+            let particles_pass = self.profiler.begin_pass("particles");
+            self.particles.simulate(
+                &mut encoder,
+                self.profiler.compute_timestamp_writes(particles_pass),
+            );
+        }
+        if let Some(clustered) = &self.clustered {
+            let binning_pass = self.profiler.begin_pass("light_binning");
+            clustered.bin_lights(
+                &mut encoder,
+                self.profiler.compute_timestamp_writes(binning_pass),
+            );
+        }
+        let gpu_scene = self.gpu_scene.as_ref().filter(|_| self.gpu_driven);
+        if let Some(gpu_scene) = gpu_scene.filter(|gpu_scene| gpu_scene.is_prepared()) {
+            let cull_pass = self.profiler.begin_pass("culling");
+            gpu_scene.cull(
+                &mut encoder,
+                self.profiler.compute_timestamp_writes(cull_pass),
+            );
+        }
+        self.ui
+            .prepare(&self.device, &self.queue, &mut encoder, self.size);
+
+        let mesh = Mesh {
+            diffuse_bind_group: &self.diffuse_bind_group,
+            camera_bind_group: self.camera_buffer.bind_group(),
+            vertex_buffer: &self.vertex_buffer,
+            index_buffer: &self.index_buffer,
+            instance_buffer: &self.instance_buffer,
+            instance_count: self.instances.len() as u32,
+            gpu_scene,
+        };
+
+        let clear_color = wgpu::Color {
+            r: (input.get_f32(input::MouseAxis::PositionX) / self.config.width as f32) as f64,
+            g: (input.get_f32(input::MouseAxis::PositionY) / self.config.height as f32) as f64,
+            b: 0.3,
+            a: 1.0,
+        };
+
+        // The debug views that replace the scene pipeline draw it forward instead.
+        let lit = matches!(
+            self.view_mode,
+            debug_view::ViewMode::Shaded
+                | debug_view::ViewMode::Depth
+                | debug_view::ViewMode::Overdraw
+        );
+        let deferred = self.deferred.as_ref().filter(|_| lit);
+        let depth_prepass = self.clustered.as_ref().filter(|_| lit);
+        if let Some(deferred) = deferred {
+            let gbuffer_pass = self.profiler.begin_pass("gbuffer");
+            {
+                let _span = tracing::info_span!("gbuffer_pass").entered();
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("G-Buffer Pass"),
+                    color_attachments: &deferred.gbuffer_attachments(),
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: self.profiler.timestamp_writes(gbuffer_pass),
+                });
+                deferred.bind_material(&mut render_pass);
+                mesh.draw(
+                    &mut render_pass,
+                    debug_view::SceneDraw::Indexed(deferred.gbuffer_pipeline()),
+                );
+            }
+        }
+        if let Some(clustered) = depth_prepass {
+            let prepass = self.profiler.begin_pass("depth_prepass");
+            let _span = tracing::info_span!("depth_prepass").entered();
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Prepass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: self.profiler.timestamp_writes(prepass),
+            });
+            mesh.draw(
+                &mut render_pass,
+                debug_view::SceneDraw::Indexed(clustered.depth_prepass_pipeline()),
+            );
+        }
+        if let (true, Some(ssao)) = (lit, &self.ssao) {
+            let ssao_pass = self.profiler.begin_pass("ssao");
+            let _span = tracing::info_span!("ssao").entered();
+            ssao.run(&mut encoder, self.profiler.timestamp_writes(ssao_pass));
+        }
+        if let Some(deferred) = deferred {
+            let lighting_pass = self.profiler.begin_pass("lighting");
+            let _span = tracing::info_span!("lighting_pass").entered();
+            deferred.light(
+                &mut encoder,
+                &view,
+                &self.depth_texture,
+                clear_color,
+                self.profiler.timestamp_writes(lighting_pass),
+            );
+        }
+
+        let main_pass = self.profiler.begin_statistics_pass("main");
         {
+            let _span = tracing::info_span!("main_pass").entered();
+            // After deferred lighting the frame and depth are already there, only the rest
+            // of the scene is drawn on top.
+            let color_load = match deferred {
+                Some(_) => wgpu::LoadOp::Load,
+                None => wgpu::LoadOp::Clear(clear_color),
+            };
+            let depth_load = match deferred.is_some() || depth_prepass.is_some() {
+                true => wgpu::LoadOp::Load,
+                false => wgpu::LoadOp::Clear(1.0),
+            };
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: (input.get_f32(input::MouseAxis::PositionX)
-                                / self.config.width as f32) as f64,
-                            g: (input.get_f32(input::MouseAxis::PositionY)
-                                / self.config.height as f32) as f64,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        load: color_load,
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                     view: &self.depth_texture.view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: depth_load,
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
                 }),
                 occlusion_query_set: None,
-                timestamp_writes: None,
+                timestamp_writes: self.profiler.timestamp_writes(main_pass),
             });
+            self.profiler.begin_statistics(&mut render_pass, main_pass);
 
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(
-                0..types::INDICES.len() as _,
-                0,
-                0..self.instances.len() as _,
+            if deferred.is_none() {
+                let shaded = match &self.clustered {
+                    Some(clustered) => {
+                        clustered.bind(&mut render_pass);
+                        clustered.pipeline()
+                    }
+                    None => {
+                        render_pass.set_bind_group(2, self.lighting.bind_group(), &[]);
+                        &self.pipeline
+                    }
+                };
+                mesh.draw(
+                    &mut render_pass,
+                    self.debug_views.scene_draw(self.view_mode, shaded),
+                );
+            }
+            let lighting = self.lighting.bind_group();
+            if let Some(skinning) = &self.skinning {
+                skinning.draw(&mut render_pass, self.camera_buffer.bind_group(), lighting);
+            }
+            if let Some(morph) = &self.morph {
+                morph.draw(&mut render_pass, self.camera_buffer.bind_group(), lighting);
+            }
+            self.lod
+                .draw(&mut render_pass, self.camera_buffer.bind_group(), lighting);
+            self.sky.draw(&mut render_pass);
+            self.transparent
+                .draw(&mut render_pass, self.camera_buffer.bind_group(), lighting);
+            self.particles.draw(
+                &mut render_pass,
+                self.camera_buffer.bind_group(),
+                lighting,
+                self.transparent.mode(),
             );
+            self.debug_renderer
+                .draw(&mut render_pass, self.camera_buffer.bind_group());
+            self.profiler.end_statistics(&mut render_pass, main_pass);
+        }
+
+        if self.transparent.mode() == transparent::TransparencyMode::WeightedBlended {
+            let transparent_pass = self.profiler.begin_pass("transparent");
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Weighted Blended Transparency Pass"),
+                color_attachments: &self.transparent.weighted_blended().attachments(),
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: self.profiler.timestamp_writes(transparent_pass),
+            });
+            let lighting = self.lighting.bind_group();
+            self.transparent.draw_weighted_blended(
+                &mut render_pass,
+                self.camera_buffer.bind_group(),
+                lighting,
+            );
+            self.particles.draw_weighted_blended(
+                &mut render_pass,
+                self.camera_buffer.bind_group(),
+                lighting,
+            );
+            drop(render_pass);
+            let composite_pass = self.profiler.begin_pass("oit_composite");
+            self.transparent.weighted_blended().composite(
+                &mut encoder,
+                &view,
+                self.profiler.timestamp_writes(composite_pass),
+            );
+        }
+
+        if self.view_mode == debug_view::ViewMode::Overdraw {
+            let overdraw_pass = self.profiler.begin_pass("overdraw");
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overdraw Pass"),
+                color_attachments: &[Some(self.debug_views.overdraw_attachment())],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: self.profiler.timestamp_writes(overdraw_pass),
+            });
+            mesh.draw(
+                &mut render_pass,
+                debug_view::SceneDraw::Indexed(self.debug_views.overdraw_pipeline()),
+            );
+        }
+        if let (true, Some(gpu_scene)) = (self.gpu_driven, &mut self.gpu_scene) {
+            if gpu_scene.builds_pyramid() {
+                let pyramid_pass = self.profiler.begin_pass("hi_z");
+                gpu_scene.build_pyramid(
+                    &mut encoder,
+                    self.profiler.compute_timestamp_writes(pyramid_pass),
+                );
+            }
+        }
+        if self.view_mode.needs_resolve() {
+            let resolve_pass = self.profiler.begin_pass("debug_view_resolve");
+            self.debug_views.resolve(
+                &mut encoder,
+                &view,
+                self.view_mode,
+                self.profiler.timestamp_writes(resolve_pass),
+            );
+        }
+
+        // Drawn after the debug view resolve so sprites, text and UI stay readable in every
+        // mode.
+        {
+            let overlay_pass = self.profiler.begin_pass("overlay");
+            let _span = tracing::info_span!("overlay_pass").entered();
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: self.profiler.timestamp_writes(overlay_pass),
+            });
+            self.sprites.draw(&mut render_pass);
+            self.text
+                .draw(&mut render_pass, self.camera_buffer.bind_group());
+            self.ui.draw(&mut render_pass, self.size);
         }
+        self.profiler.end_frame(&mut encoder);
+
+        self.capture
+            .copy_frame(&self.device, &mut encoder, &output.texture);
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+        self.uploader.finish();
+        tracing::info_span!("submit")
+            .in_scope(|| self.queue.submit(std::iter::once(encoder.finish())));
+        self.uploader.recall();
+        self.ui.end_frame();
+        self.capture.map_pending();
+        self.profiler.after_submit();
 
-        output.present();
+        tracing::info_span!("present").in_scope(|| output.present());
 
         Ok(())
     }
 }
+
+/// The buffers and bind groups needed to draw the instanced scene mesh, so passes other
+/// than the main one can draw it with a different pipeline.
+struct Mesh<'a> {
+    diffuse_bind_group: &'a wgpu::BindGroup,
+    camera_bind_group: &'a wgpu::BindGroup,
+    vertex_buffer: &'a wgpu::Buffer,
+    index_buffer: &'a wgpu::Buffer,
+    instance_buffer: &'a wgpu::Buffer,
+    instance_count: u32,
+    /// Set when the scene is GPU-driven, replacing the instance buffer for indexed draws.
+    gpu_scene: Option<&'a culling::GpuScene>,
+}
+
+impl<'a> Mesh<'a> {
+    fn draw(&self, render_pass: &mut wgpu::RenderPass<'a>, draw: debug_view::SceneDraw<'a>) {
+        render_pass.set_bind_group(0, self.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, self.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+        match draw {
+            debug_view::SceneDraw::Indexed(pipeline) => {
+                render_pass.set_pipeline(pipeline);
+                if let Some(gpu_scene) = self.gpu_scene {
+                    gpu_scene.draw(render_pass);
+                    return;
+                }
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..types::INDICES.len() as _, 0, 0..self.instance_count);
+            }
+            debug_view::SceneDraw::Barycentric {
+                pipeline,
+                vertex_buffer,
+                vertex_count,
+            } => {
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                render_pass.draw(0..vertex_count, 0..self.instance_count);
+            }
+        }
+    }
+}
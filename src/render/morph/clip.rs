@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use crate::render::skinning::clip::{sample, Interpolation};
+
+/// Animates the weights of every morph target of a mesh.
+#[derive(Debug, Clone)]
+pub struct MorphClip {
+    pub name: String,
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds, ascending.
+    pub times: Vec<f32>,
+    /// Keyframes of each target's weight. Cubic splines store an in-tangent, value and
+    /// out-tangent per keyframe.
+    pub weights: Vec<Vec<f32>>,
+}
+
+impl MorphClip {
+    /// Seconds, the time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or_default()
+    }
+
+    /// Writes the weight of every animated target at `time` into `weights`.
+    pub fn sample(&self, time: f32, weights: &mut [f32]) {
+        for (weight, keyframes) in weights.iter_mut().zip(&self.weights) {
+            if !keyframes.is_empty() {
+                *weight = sample(self.interpolation, &self.times, keyframes, time);
+            }
+        }
+    }
+}
+
+/// Plays a `MorphClip` over a mesh's default weights.
+#[derive(Debug, Clone)]
+pub struct MorphAnimator {
+    clip: Arc<MorphClip>,
+    /// Seconds.
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+}
+
+impl MorphAnimator {
+    pub fn new(clip: Arc<MorphClip>, looping: bool) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping,
+        }
+    }
+
+    pub fn clip(&self) -> &Arc<MorphClip> {
+        &self.clip
+    }
+
+    pub fn update(&mut self, delta: f64) {
+        self.time += delta as f32 * self.speed;
+        let duration = self.clip.duration();
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0.0, duration);
+        }
+    }
+
+    /// `defaults` with the clip's animated targets replaced by their current weights.
+    pub fn weights(&self, defaults: &[f32]) -> Vec<f32> {
+        let mut weights = defaults.to_vec();
+        self.clip.sample(self.time, &mut weights);
+        weights
+    }
+}
@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::{
+    render::skinning::{
+        import::{check_keyframes, glb_buffers, gltf_error, load_base_color},
+        Interpolation,
+    },
+    GameError, Result,
+};
+
+use super::{clip::MorphClip, MorphVertex};
+
+/// Per-vertex displacements of one morph target, each as long as the mesh's vertices.
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub tangents: Vec<Vec3>,
+}
+
+/// A mesh with morph targets and the clips animating their weights, ready for
+/// `MorphRenderer::add`.
+#[derive(Debug, Clone)]
+pub struct MorphModel {
+    pub vertices: Vec<MorphVertex>,
+    pub indices: Vec<u32>,
+    pub targets: Vec<MorphTarget>,
+    /// Weights when no clip is playing.
+    pub default_weights: Vec<f32>,
+    pub clips: Vec<Arc<MorphClip>>,
+    /// Base color texture of the first primitive's material, if it has an embedded one.
+    pub base_color: Option<image::RgbaImage>,
+}
+
+impl MorphModel {
+    pub fn clip(&self, name: &str) -> Option<&Arc<MorphClip>> {
+        self.clips.iter().find(|clip| clip.name == name)
+    }
+}
+
+/// Imports the first mesh with morph targets from a binary glTF (`.glb`), with every
+/// animation of its weights. Buffers and images must be embedded.
+pub fn load_glb(bytes: &[u8]) -> Result<MorphModel> {
+    let gltf = gltf::Gltf::from_slice(bytes).map_err(gltf_error)?;
+    let buffers = glb_buffers(&gltf)?;
+    let get_buffer = |buffer: gltf::Buffer| buffers.get(buffer.index()).copied();
+
+    let node = gltf
+        .nodes()
+        .find(|node| {
+            node.mesh().is_some_and(|mesh| {
+                mesh.primitives()
+                    .any(|primitive| primitive.morph_targets().len() > 0)
+            })
+        })
+        .ok_or_else(|| GameError::GltfError("no mesh with morph targets".to_string()))?;
+    let mesh = node.mesh().unwrap();
+    let target_count = mesh
+        .primitives()
+        .map(|primitive| primitive.morph_targets().len())
+        .max()
+        .unwrap_or_default();
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut targets = vec![MorphTarget::default(); target_count];
+    for primitive in mesh.primitives() {
+        let reader = primitive.reader(get_buffer);
+        let base = vertices.len() as u32;
+
+        let positions = reader
+            .read_positions()
+            .ok_or_else(|| GameError::GltfError("primitive without positions".to_string()))?;
+        let mut normals = reader.read_normals();
+        let mut tex_coords = reader.read_tex_coords(0).map(|coords| coords.into_f32());
+        let mut tangents = reader.read_tangents();
+        for position in positions {
+            vertices.push(MorphVertex {
+                position: Vec3::from(position),
+                normal: normals
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map_or(Vec3::Z, Vec3::from),
+                tex_coords: tex_coords
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map_or(Vec2::ZERO, Vec2::from),
+                tangent: tangents
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map_or(Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::from),
+            });
+        }
+        let count = vertices.len() - base as usize;
+
+        // Primitives with fewer targets, or targets missing an attribute, don't move.
+        let mut read = reader.read_morph_targets();
+        for target in targets.iter_mut() {
+            let (positions, normals, tangents) = read.next().unwrap_or((None, None, None));
+            let extend = |deltas: &mut Vec<Vec3>, read: Option<_>| {
+                let start = deltas.len();
+                if let Some(read) = read {
+                    deltas.extend(Iterator::map(read, Vec3::from).take(count));
+                }
+                deltas.resize(start + count, Vec3::ZERO);
+            };
+            extend(&mut target.positions, positions);
+            extend(&mut target.normals, normals);
+            extend(&mut target.tangents, tangents);
+        }
+
+        match reader.read_indices() {
+            Some(read) => indices.extend(read.into_u32().map(|index| base + index)),
+            None => indices.extend(base..vertices.len() as u32),
+        }
+    }
+
+    let mut default_weights = node
+        .weights()
+        .or(mesh.weights())
+        .map(<[f32]>::to_vec)
+        .unwrap_or_default();
+    default_weights.resize(target_count, 0.0);
+
+    let mut clips = Vec::new();
+    for (index, animation) in gltf.animations().enumerate() {
+        for channel in animation.channels() {
+            if channel.target().node().index() != node.index() {
+                continue;
+            }
+            let reader = channel.reader(get_buffer);
+            let Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(values)) =
+                reader.read_outputs()
+            else {
+                continue;
+            };
+            let times = reader
+                .read_inputs()
+                .ok_or_else(|| GameError::GltfError("channel without keyframe times".to_string()))?
+                .collect::<Vec<_>>();
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+
+            let values = values.into_f32().collect::<Vec<_>>();
+            check_keyframes(
+                &times,
+                values.len(),
+                target_count * interpolation.values_per_keyframe(),
+            )?;
+            let weights = target_weights(&values, target_count);
+
+            clips.push(Arc::new(MorphClip {
+                name: animation
+                    .name()
+                    .map_or_else(|| format!("animation_{index}"), str::to_string),
+                interpolation,
+                times,
+                weights,
+            }));
+        }
+    }
+
+    let base_color = match mesh.primitives().next() {
+        Some(primitive) => load_base_color(&primitive, &buffers)?,
+        None => None,
+    };
+
+    Ok(MorphModel {
+        vertices,
+        indices,
+        targets,
+        default_weights,
+        clips,
+        base_color,
+    })
+}
+
+/// Splits weight outputs into each target's keyframes. Outputs are keyframe-major: every
+/// target's weight for the first keyframe, then the second, and so on. Cubic splines have
+/// all in-tangents, then all values, then all out-tangents per keyframe, which leaves
+/// each target with the in-tangent, value, out-tangent order `MorphClip` expects.
+fn target_weights(values: &[f32], target_count: usize) -> Vec<Vec<f32>> {
+    (0..target_count)
+        .map(|target| {
+            values
+                .iter()
+                .skip(target)
+                .step_by(target_count)
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_keyframe_major_weights() {
+        // Two keyframes of three targets.
+        let values = [0.0, 0.1, 0.2, 1.0, 1.1, 1.2];
+        assert_eq!(
+            target_weights(&values, 3),
+            vec![vec![0.0, 1.0], vec![0.1, 1.1], vec![0.2, 1.2]]
+        );
+    }
+
+    #[test]
+    fn splits_cubic_tangents_per_target() {
+        // Two keyframes of two targets, each keyframe holding both in-tangents, both
+        // values and both out-tangents.
+        let values = [
+            0.0, 0.1, 0.2, 0.3, 0.4, 0.5, //
+            1.0, 1.1, 1.2, 1.3, 1.4, 1.5,
+        ];
+        assert_eq!(
+            target_weights(&values, 2),
+            vec![
+                vec![0.0, 0.2, 0.4, 1.0, 1.2, 1.4],
+                vec![0.1, 0.3, 0.5, 1.1, 1.3, 1.5],
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_output_counts() {
+        // Two keyframes of three targets.
+        let times = [0.0, 1.0];
+        let linear = 3 * Interpolation::Linear.values_per_keyframe();
+        let cubic = 3 * Interpolation::CubicSpline.values_per_keyframe();
+        assert!(check_keyframes(&times, 6, linear).is_ok());
+        assert!(check_keyframes(&times, 5, linear).is_err());
+        assert!(check_keyframes(&times, 6, cubic).is_err());
+        assert!(check_keyframes(&times, 18, cubic).is_ok());
+        assert!(check_keyframes(&[], 0, linear).is_err());
+    }
+}
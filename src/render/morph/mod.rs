@@ -0,0 +1,338 @@
+pub mod clip;
+pub mod import;
+
+use glam::{Vec2, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::Result;
+
+use super::{
+    reflect, texture,
+    types::{self, RawInstanceVector, VertexDescription},
+    uniform::ShaderType,
+};
+
+pub use clip::{MorphAnimator, MorphClip};
+pub use import::{load_glb, MorphModel, MorphTarget};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, types::VertexDescription)]
+pub struct MorphVertex {
+    position: Vec3,
+    normal: Vec3,
+    tex_coords: Vec2,
+    /// `w` is the bitangent sign.
+    tangent: Vec4,
+}
+
+/// One vertex's displacement for one target, as read by `morph.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct MorphDelta {
+    position: Vec4,
+    normal: Vec4,
+    tangent: Vec4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MorphMeshId(usize);
+
+/// GPU resources of one morphed mesh. Its weights and instances stay as they are until
+/// the next `set_weights` or `set_instances`.
+#[derive(Debug)]
+pub struct MorphMesh {
+    target_count: usize,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    weight_buffer: wgpu::Buffer,
+    morph_bind_group: wgpu::BindGroup,
+    instance_buffer: wgpu::Buffer,
+    instance_count: u32,
+    texture: texture::Texture,
+    texture_bind_group: wgpu::BindGroup,
+    pub visible: bool,
+}
+
+impl MorphMesh {
+    pub fn target_count(&self) -> usize {
+        self.target_count
+    }
+
+    pub fn texture(&self) -> &texture::Texture {
+        &self.texture
+    }
+
+    /// Uploads one weight per target. Missing weights are left as they were, extra ones
+    /// are ignored.
+    pub fn set_weights(&self, queue: &wgpu::Queue, weights: &[f32]) {
+        let weights = &weights[..weights.len().min(self.target_count)];
+        if !weights.is_empty() {
+            queue.write_buffer(&self.weight_buffer, 0, bytemuck::cast_slice(weights));
+        }
+    }
+
+    /// Draws the mesh once per instance, like the instanced scene mesh. The buffer grows
+    /// when there are more instances than before.
+    pub fn set_instances(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instances: &[types::Instance],
+    ) {
+        let raw = instances.iter().collect::<Vec<types::InstanceRaw>>();
+        let size = std::mem::size_of_val(raw.as_slice()) as wgpu::BufferAddress;
+        if size > self.instance_buffer.size() {
+            self.instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Morph Instance Buffer"),
+                contents: bytemuck::cast_slice(&raw),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            });
+        } else if size > 0 {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw));
+        }
+        self.instance_count = raw.len() as u32;
+    }
+}
+
+/// Draws meshes with morph targets in the main pass. The vertex shader blends every
+/// target's position, normal and tangent deltas by its weight, both read from storage
+/// buffers, and places each instance with the same per-instance model matrix as the
+/// scene mesh.
+///
+/// Needs vertex shader storage buffers like `skinning::SkinnedRenderer`, so check
+/// `skinning::SkinnedRenderer::is_supported` first.
+#[derive(Debug)]
+pub struct MorphRenderer {
+    pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    morph_layout: wgpu::BindGroupLayout,
+    meshes: Vec<MorphMesh>,
+}
+
+impl MorphRenderer {
+    /// `camera_bind_group_layout` and `lighting_bind_group_layout` must match the layouts
+    /// of `@group(1)` and `@group(3)` in `morph.wgsl`.
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader_source = concat!(
+            include_str!("../../lighting.wgsl"),
+            include_str!("../../morph.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers(
+            "vs_main",
+            &[MorphVertex::desc(), types::InstanceRaw::desc()],
+        )?;
+
+        let texture_layout = reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("morph_texture_bind_group_layout"),
+        )?;
+        let morph_layout =
+            reflection.create_bind_group_layout(device, 2, Some("morph_bind_group_layout"))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Morph Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Morph Pipeline Layout"),
+            bind_group_layouts: &[
+                &texture_layout,
+                camera_bind_group_layout,
+                &morph_layout,
+                lighting_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Morph Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[MorphVertex::desc(), types::InstanceRaw::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Ok(Self {
+            pipeline,
+            texture_layout,
+            morph_layout,
+            meshes: Vec::new(),
+        })
+    }
+
+    /// Uploads `model` with its default weights, as a single instance at the origin.
+    /// Models without an embedded base color texture are drawn white.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        model: &MorphModel,
+    ) -> Result<MorphMeshId> {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph Vertex Buffer"),
+            contents: bytemuck::cast_slice(&model.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph Index Buffer"),
+            contents: bytemuck::cast_slice(&model.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        // Bindings can't be empty, so a model without targets still gets one zero weight
+        // and a zero delta per vertex.
+        let mut deltas = model
+            .targets
+            .iter()
+            .flat_map(|target| {
+                (0..model.vertices.len()).map(|vertex| MorphDelta {
+                    position: target.positions[vertex].extend(0.0),
+                    normal: target.normals[vertex].extend(0.0),
+                    tangent: target.tangents[vertex].extend(0.0),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut weights = model.default_weights.clone();
+        weights.resize(model.targets.len(), 0.0);
+        if weights.is_empty() {
+            weights.push(0.0);
+            deltas.resize(model.vertices.len().max(1), bytemuck::Zeroable::zeroed());
+        }
+
+        let delta_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph Delta Buffer"),
+            contents: bytemuck::cast_slice(&deltas),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let weight_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph Weight Buffer"),
+            contents: bytemuck::cast_slice(&weights),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let morph_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.morph_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: delta_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: weight_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("morph_bind_group"),
+        });
+
+        let instances = vec![types::Instance::new(glam::Vec3::ZERO, glam::Quat::IDENTITY)];
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph Instance Buffer"),
+            contents: bytemuck::cast_slice(&instances.to_raw()),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let image = match &model.base_color {
+            Some(image) => image::DynamicImage::ImageRgba8(image.clone()),
+            None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255; 4]),
+            )),
+        };
+        let texture =
+            texture::Texture::from_image(device, queue, &image, Some("morph_base_color"))?;
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("morph_texture_bind_group"),
+        });
+
+        self.meshes.push(MorphMesh {
+            target_count: model.targets.len(),
+            vertex_buffer,
+            index_buffer,
+            index_count: model.indices.len() as u32,
+            weight_buffer,
+            morph_bind_group,
+            instance_buffer,
+            instance_count: instances.len() as u32,
+            texture,
+            texture_bind_group,
+            visible: true,
+        });
+        Ok(MorphMeshId(self.meshes.len() - 1))
+    }
+
+    pub fn mesh(&mut self, mesh: MorphMeshId) -> &mut MorphMesh {
+        &mut self.meshes[mesh.0]
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.meshes.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(3, lighting_bind_group, &[]);
+
+        for mesh in self
+            .meshes
+            .iter()
+            .filter(|mesh| mesh.visible && mesh.instance_count > 0)
+        {
+            render_pass.set_bind_group(0, &mesh.texture_bind_group, &[]);
+            render_pass.set_bind_group(2, &mesh.morph_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, mesh.instance_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..mesh.instance_count);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::ops::{Add, Mul};
+
+/// A value over a particle's life, from 0 at birth to 1 at death, linearly interpolated
+/// between keys. Baked into `CURVE_SAMPLES` evenly spaced samples for the simulation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve<T> {
+    /// `(t, value)` pairs with ascending `t`.
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Copy + Add<Output = T> + Mul<f32, Output = T>> Curve<T> {
+    /// Keys are sorted by `t`, which is clamped to `0..=1`. Needs at least one key.
+    pub fn new(keys: impl IntoIterator<Item = (f32, T)>) -> Self {
+        let mut keys = keys
+            .into_iter()
+            .map(|(t, value)| (t.clamp(0.0, 1.0), value))
+            .collect::<Vec<_>>();
+        assert!(!keys.is_empty(), "a curve needs at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self::new([(0.0, value)])
+    }
+
+    /// From `start` at birth to `end` at death.
+    pub fn linear(start: T, end: T) -> Self {
+        Self::new([(0.0, start), (1.0, end)])
+    }
+
+    pub fn keys(&self) -> &[(f32, T)] {
+        &self.keys
+    }
+
+    pub fn sample(&self, t: f32) -> T {
+        let next = self.keys.partition_point(|(key, _)| *key <= t);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+        let (start, from) = self.keys[next - 1];
+        let (end, to) = self.keys[next];
+        let f = (t - start) / (end - start);
+        from * (1.0 - f) + to * f
+    }
+
+    pub(super) fn bake<const N: usize>(&self) -> [T; N] {
+        std::array::from_fn(|index| self.sample(index as f32 / (N - 1) as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_keys_and_holds_the_ends() {
+        let curve = Curve::new([(0.75, 4.0), (0.25, 2.0), (0.5, 0.0)]);
+        assert_eq!(curve.sample(0.25), 2.0);
+        assert_eq!(curve.sample(0.5), 0.0);
+        assert_eq!(curve.sample(0.75), 4.0);
+        assert_eq!(curve.sample(0.625), 2.0);
+        assert_eq!(curve.sample(0.0), 2.0);
+        assert_eq!(curve.sample(1.0), 4.0);
+    }
+
+    #[test]
+    fn bakes_from_birth_to_death() {
+        let samples = Curve::linear(1.0, 0.0).bake::<5>();
+        assert_eq!(samples, [1.0, 0.75, 0.5, 0.25, 0.0]);
+        assert_eq!(Curve::constant(3.0).bake::<3>(), [3.0; 3]);
+    }
+}
@@ -0,0 +1,699 @@
+pub mod curve;
+mod simulate;
+
+use glam::{Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::Result;
+
+use super::{
+    camera, reflect, texture, transparent,
+    types::VertexDescription,
+    uniform::{self, ShaderType},
+};
+
+use simulate::{EmitterUniform, Particle, ParticleInstance, Step};
+
+pub use curve::Curve;
+
+/// Samples each curve is baked into for the simulation.
+pub const CURVE_SAMPLES: usize = 16;
+/// Forces per emitter beyond gravity and drag, further ones are ignored.
+pub const MAX_FORCES: usize = 4;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Brightens what's behind, order independent. Fire, sparks, magic.
+    #[default]
+    Additive,
+    /// Covers what's behind. Particles of one emitter aren't sorted, so keep them soft
+    /// or sparse, or use `transparent::TransparencyMode::WeightedBlended`. Smoke, dust.
+    Alpha,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Force {
+    /// Constant acceleration, e.g. wind.
+    Directional(Vec3),
+    /// Accelerates particles towards `position`, or away from it with a negative
+    /// `strength`.
+    Point { position: Vec3, strength: f32 },
+}
+
+/// How an emitter spawns particles and how they behave over their life.
+#[derive(Debug, Clone)]
+pub struct EmitterDesc {
+    /// World position new particles spawn around. Live particles stay where they are
+    /// when it moves.
+    pub position: Vec3,
+    /// Particles per second while the emitter is active.
+    pub spawn_rate: f32,
+    /// Most particles alive at once, fixed when the emitter is added. The oldest are
+    /// replaced when more spawn.
+    pub capacity: u32,
+    /// Seconds.
+    pub lifetime: f32,
+    /// Lifetimes vary randomly by up to this many seconds either way.
+    pub lifetime_variance: f32,
+    /// Particles spawn inside a sphere of this radius.
+    pub spawn_radius: f32,
+    /// Initial velocity.
+    pub velocity: Vec3,
+    /// Most speed added in a random direction to the initial velocity.
+    pub velocity_spread: f32,
+    /// Multiplies velocity over the particle's life.
+    pub speed_over_life: Curve<f32>,
+    /// World-space quad size over the particle's life.
+    pub size_over_life: Curve<f32>,
+    /// Linear RGBA over the particle's life.
+    pub color_over_life: Curve<Vec4>,
+    pub gravity: Vec3,
+    /// Fraction of velocity lost per second.
+    pub drag: f32,
+    /// At most `MAX_FORCES`.
+    pub forces: Vec<Force>,
+    pub blend: BlendMode,
+}
+
+impl Default for EmitterDesc {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            spawn_rate: 50.0,
+            capacity: 1024,
+            lifetime: 2.0,
+            lifetime_variance: 0.5,
+            spawn_radius: 0.1,
+            velocity: Vec3::new(0.0, 2.0, 0.0),
+            velocity_spread: 0.5,
+            speed_over_life: Curve::constant(1.0),
+            size_over_life: Curve::linear(0.2, 0.0),
+            color_over_life: Curve::linear(Vec4::ONE, Vec4::new(1.0, 1.0, 1.0, 0.0)),
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            drag: 0.0,
+            forces: Vec::new(),
+            blend: BlendMode::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmitterId(usize);
+
+/// A running emitter. Changes to `desc` apply from the next frame, except `capacity`.
+#[derive(Debug)]
+pub struct Emitter {
+    pub desc: EmitterDesc,
+    /// Spawns at `desc.spawn_rate` while set. Live particles finish their lives either
+    /// way.
+    pub active: bool,
+    capacity: u32,
+    spawner: Spawner,
+    instance_buffer: wgpu::Buffer,
+    simulation: Simulation,
+}
+
+/// Where and how many particles an emitter spawns in its next step.
+#[derive(Debug, Default)]
+struct Spawner {
+    accumulator: f32,
+    burst: u32,
+    /// Slot the next particle spawns into.
+    cursor: u32,
+    step: Option<Step>,
+}
+
+impl Spawner {
+    /// Spawns `rate` particles per second, plus any burst, over `delta` seconds into a
+    /// ring of `capacity` slots that replaces the oldest particles once it's full.
+    fn update(&mut self, rate: f32, delta: f32, capacity: u32, seed: u32) {
+        self.accumulator += rate.max(0.0) * delta;
+        let spawned = self.accumulator.floor();
+        self.accumulator -= spawned;
+        let spawn_count = (spawned as u32)
+            .saturating_add(std::mem::take(&mut self.burst))
+            .min(capacity);
+
+        // Updates without a frame in between fold into one step.
+        let step = self.step.get_or_insert(Step {
+            delta: 0.0,
+            seed,
+            spawn_start: self.cursor,
+            spawn_count: 0,
+            capacity,
+        });
+        step.delta += delta;
+        step.spawn_count = (step.spawn_count + spawn_count).min(capacity);
+        self.cursor = (self.cursor + spawn_count) % capacity;
+    }
+}
+
+#[derive(Debug)]
+enum Simulation {
+    Gpu {
+        // Boxed, the uniform's CPU copy dwarfs the other variant.
+        uniform: Box<uniform::UniformBuffer<EmitterUniform>>,
+        bind_group: wgpu::BindGroup,
+        draw_buffer: wgpu::Buffer,
+    },
+    Cpu {
+        particles: Vec<Particle>,
+        instances: Vec<ParticleInstance>,
+        count: u32,
+    },
+}
+
+impl Emitter {
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Spawns `count` particles next frame on top of the spawn rate, even while inactive.
+    pub fn burst(&mut self, count: u32) {
+        self.spawner.burst = self.spawner.burst.saturating_add(count);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct BillboardUniform {
+    right: Vec4,
+    up: Vec4,
+}
+
+#[derive(Debug)]
+struct Compute {
+    pipeline: wgpu::ComputePipeline,
+    emitter_layout: wgpu::BindGroupLayout,
+    particle_layout: wgpu::BindGroupLayout,
+}
+
+/// Particle emitters simulated in compute shaders and drawn as camera-facing billboards
+/// with `draw_indirect`, so particle counts never round-trip through the CPU.
+///
+/// Where compute shaders or indirect draws are unavailable (WebGL2), the same simulation
+/// runs on the CPU and the live particles are uploaded every frame instead.
+#[derive(Debug)]
+pub struct ParticleSystem {
+    compute: Option<Compute>,
+    additive: wgpu::RenderPipeline,
+    alpha: wgpu::RenderPipeline,
+    weighted_blended: wgpu::RenderPipeline,
+    billboard: uniform::UniformBuffer<BillboardUniform>,
+    emitters: Vec<Emitter>,
+    frame: u32,
+}
+
+impl ParticleSystem {
+    pub fn supports_compute(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter.get_downlevel_capabilities().flags.contains(
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION,
+        ) && device.limits().max_storage_buffers_per_shader_stage >= 3
+    }
+
+    /// `camera_bind_group_layout` and `lighting_bind_group_layout` must match the layouts
+    /// of `@group(1)` and `@group(2)` in `particles.wgsl`. Simulates on the GPU when
+    /// `compute` is set, see `supports_compute`.
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_bind_group_layout: &wgpu::BindGroupLayout,
+        compute: bool,
+    ) -> Result<Self> {
+        let shader_source = concat!(
+            include_str!("../../lighting.wgsl"),
+            include_str!("../../particles.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers("vs_main", &[ParticleInstance::desc()])?;
+
+        let billboard_layout = reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("particle_billboard_bind_group_layout"),
+        )?;
+        let billboard = uniform::UniformBuffer::new(
+            device,
+            &billboard_layout,
+            BillboardUniform {
+                right: Vec4::X,
+                up: Vec4::Y,
+            },
+            "particle_billboard",
+        );
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Pipeline Layout"),
+            bind_group_layouts: &[
+                &billboard_layout,
+                camera_bind_group_layout,
+                lighting_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label, entry_point, targets: &[Option<wgpu::ColorTargetState>]| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[ParticleInstance::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets,
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                // Tested against the scene but never written, so particles don't hide
+                // each other.
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let color_target = |blend| {
+            [Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })]
+        };
+        let additive = create_pipeline(
+            "Additive Particle Pipeline",
+            "fs_additive",
+            &color_target(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            }),
+        );
+        let alpha = create_pipeline(
+            "Alpha Particle Pipeline",
+            "fs_main",
+            &color_target(wgpu::BlendState::ALPHA_BLENDING),
+        );
+        let weighted_blended = create_pipeline(
+            "Weighted Blended Particle Pipeline",
+            "fs_weighted_blended",
+            &transparent::oit::color_targets(),
+        );
+
+        let compute = if compute {
+            Some(Self::create_compute(device)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            compute,
+            additive,
+            alpha,
+            weighted_blended,
+            billboard,
+            emitters: Vec::new(),
+            frame: 0,
+        })
+    }
+
+    fn create_compute(device: &wgpu::Device) -> Result<Compute> {
+        let shader_source = include_str!("../../particles_simulate.wgsl");
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        let emitter_layout =
+            reflection.create_bind_group_layout(device, 0, Some("emitter_bind_group_layout"))?;
+        let particle_layout =
+            reflection.create_bind_group_layout(device, 1, Some("particle_bind_group_layout"))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Simulation Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Simulation Pipeline Layout"),
+            bind_group_layouts: &[&emitter_layout, &particle_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Particle Simulation Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "simulate",
+        });
+
+        Ok(Compute {
+            pipeline,
+            emitter_layout,
+            particle_layout,
+        })
+    }
+
+    /// Whether particles are simulated in compute shaders rather than on the CPU.
+    pub fn is_gpu(&self) -> bool {
+        self.compute.is_some()
+    }
+
+    pub fn add_emitter(&mut self, device: &wgpu::Device, desc: EmitterDesc) -> EmitterId {
+        let capacity = desc.capacity.max(1);
+        let instance_size =
+            (capacity as usize * std::mem::size_of::<ParticleInstance>()) as wgpu::BufferAddress;
+
+        let (instance_buffer, simulation) = match &self.compute {
+            Some(compute) => {
+                let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Particle Instance Buffer"),
+                    size: instance_size,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+                    mapped_at_creation: false,
+                });
+                let particle_buffer =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Particle Buffer"),
+                        contents: bytemuck::cast_slice(&vec![
+                            Particle::default();
+                            capacity as usize
+                        ]),
+                        usage: wgpu::BufferUsages::STORAGE,
+                    });
+                let draw_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Particle Draw Buffer"),
+                    contents: wgpu::util::DrawIndirectArgs {
+                        vertex_count: 4,
+                        instance_count: 0,
+                        first_vertex: 0,
+                        first_instance: 0,
+                    }
+                    .as_bytes(),
+                    usage: wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::INDIRECT
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &compute.particle_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: particle_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: instance_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: draw_buffer.as_entire_binding(),
+                        },
+                    ],
+                    label: Some("particle_bind_group"),
+                });
+                let uniform = uniform::UniformBuffer::new(
+                    device,
+                    &compute.emitter_layout,
+                    EmitterUniform::new(
+                        &desc,
+                        Step {
+                            delta: 0.0,
+                            seed: 0,
+                            spawn_start: 0,
+                            spawn_count: 0,
+                            capacity,
+                        },
+                    ),
+                    "particle_emitter",
+                );
+                (
+                    instance_buffer,
+                    Simulation::Gpu {
+                        uniform: Box::new(uniform),
+                        bind_group,
+                        draw_buffer,
+                    },
+                )
+            }
+            None => {
+                let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Particle Instance Buffer"),
+                    size: instance_size,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                (
+                    instance_buffer,
+                    Simulation::Cpu {
+                        particles: vec![Particle::default(); capacity as usize],
+                        instances: vec![bytemuck::Zeroable::zeroed(); capacity as usize],
+                        count: 0,
+                    },
+                )
+            }
+        };
+
+        self.emitters.push(Emitter {
+            desc,
+            active: true,
+            capacity,
+            spawner: Spawner::default(),
+            instance_buffer,
+            simulation,
+        });
+        EmitterId(self.emitters.len() - 1)
+    }
+
+    pub fn emitter(&mut self, emitter: EmitterId) -> &mut Emitter {
+        &mut self.emitters[emitter.0]
+    }
+
+    /// Advances spawning by `delta` seconds. The simulation itself steps in `prepare`.
+    pub fn update(&mut self, delta: f64) {
+        let delta = delta as f32;
+        self.frame = self.frame.wrapping_add(1);
+        for (index, emitter) in self.emitters.iter_mut().enumerate() {
+            let rate = if emitter.active {
+                emitter.desc.spawn_rate
+            } else {
+                0.0
+            };
+            let seed = self.frame ^ (index as u32).wrapping_mul(0x9e37_79b9);
+            emitter.spawner.update(rate, delta, emitter.capacity, seed);
+        }
+    }
+
+    /// Uploads this frame's emitter state and billboard axes for `camera`. On the CPU
+    /// path this also runs the simulation.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, camera: &camera::Camera) {
+        let forward = (camera.get_target() - camera.get_eye()).normalize_or_zero();
+        let right = forward.cross(camera.get_up()).normalize_or_zero();
+        let up = right.cross(forward);
+        self.billboard.set(
+            queue,
+            BillboardUniform {
+                right: right.extend(0.0),
+                up: up.extend(0.0),
+            },
+        );
+
+        for emitter in self.emitters.iter_mut() {
+            let step = emitter.spawner.step.take().unwrap_or(Step {
+                delta: 0.0,
+                seed: 0,
+                spawn_start: 0,
+                spawn_count: 0,
+                capacity: emitter.capacity,
+            });
+            let uniform = EmitterUniform::new(&emitter.desc, step);
+            match &mut emitter.simulation {
+                Simulation::Gpu {
+                    uniform: buffer,
+                    draw_buffer,
+                    ..
+                } => {
+                    buffer.set(queue, uniform);
+                    // `instance_count`, counted up again by the simulation.
+                    queue.write_buffer(draw_buffer, 4, bytemuck::bytes_of(&0u32));
+                }
+                Simulation::Cpu {
+                    particles,
+                    instances,
+                    count,
+                } => {
+                    let live = simulate::simulate(&uniform, particles, instances);
+                    *count = live as u32;
+                    if live > 0 {
+                        queue.write_buffer(
+                            &emitter.instance_buffer,
+                            0,
+                            bytemuck::cast_slice(&instances[..live]),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether `simulate` records a compute pass this frame.
+    pub fn simulates_on_gpu(&self) -> bool {
+        self.compute.is_some() && !self.emitters.is_empty()
+    }
+
+    /// Records the compute pass simulating every emitter. Nothing to do on the CPU path.
+    pub fn simulate(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites<'_>>,
+    ) {
+        let Some(compute) = &self.compute else {
+            return;
+        };
+        if self.emitters.is_empty() {
+            return;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle Simulation Pass"),
+            timestamp_writes,
+        });
+        compute_pass.set_pipeline(&compute.pipeline);
+        for emitter in self.emitters.iter() {
+            if let Simulation::Gpu {
+                uniform,
+                bind_group,
+                ..
+            } = &emitter.simulation
+            {
+                compute_pass.set_bind_group(0, uniform.bind_group(), &[]);
+                compute_pass.set_bind_group(1, bind_group, &[]);
+                compute_pass.dispatch_workgroups(emitter.capacity.div_ceil(WORKGROUP_SIZE), 1, 1);
+            }
+        }
+    }
+
+    /// Draws every emitter's particles. Call after opaque geometry so particles blend
+    /// over it. In `TransparencyMode::WeightedBlended`, `BlendMode::Alpha` emitters are
+    /// left to `draw_weighted_blended`.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+        mode: transparent::TransparencyMode,
+    ) {
+        let weighted_blended = mode == transparent::TransparencyMode::WeightedBlended;
+        self.draw_emitters(
+            render_pass,
+            camera_bind_group,
+            lighting_bind_group,
+            |emitter| match emitter.desc.blend {
+                BlendMode::Additive => Some(&self.additive),
+                BlendMode::Alpha if weighted_blended => None,
+                BlendMode::Alpha => Some(&self.alpha),
+            },
+        );
+    }
+
+    /// Accumulates `BlendMode::Alpha` emitters in a pass with
+    /// `transparent::oit::WeightedBlended::attachments`.
+    pub fn draw_weighted_blended<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.draw_emitters(
+            render_pass,
+            camera_bind_group,
+            lighting_bind_group,
+            |emitter| (emitter.desc.blend == BlendMode::Alpha).then_some(&self.weighted_blended),
+        );
+    }
+
+    /// Draws the emitters `pipeline` picks one for.
+    fn draw_emitters<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+        pipeline: impl Fn(&Emitter) -> Option<&'a wgpu::RenderPipeline>,
+    ) {
+        if self.emitters.is_empty() {
+            return;
+        }
+        render_pass.set_bind_group(0, self.billboard.bind_group(), &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, lighting_bind_group, &[]);
+
+        for emitter in self.emitters.iter() {
+            let Some(pipeline) = pipeline(emitter) else {
+                continue;
+            };
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_vertex_buffer(0, emitter.instance_buffer.slice(..));
+            match &emitter.simulation {
+                Simulation::Gpu { draw_buffer, .. } => render_pass.draw_indirect(draw_buffer, 0),
+                Simulation::Cpu { count, .. } => {
+                    if *count > 0 {
+                        render_pass.draw(0..4, 0..*count);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_cursor_wraps_around_capacity() {
+        let mut spawner = Spawner {
+            cursor: 3,
+            burst: 3,
+            ..Spawner::default()
+        };
+        spawner.update(0.0, 0.1, 4, 0);
+        let step = spawner.step.take().unwrap();
+        assert_eq!((step.spawn_start, step.spawn_count), (3, 3));
+        assert_eq!(spawner.cursor, 2);
+    }
+
+    #[test]
+    fn updates_between_frames_fold_into_one_step() {
+        let mut spawner = Spawner::default();
+        spawner.update(6.0, 0.25, 8, 0);
+        spawner.update(6.0, 0.25, 8, 0);
+        assert_eq!(spawner.cursor, 3);
+        spawner.burst = 20;
+        spawner.update(6.0, 0.0, 8, 0);
+        let step = spawner.step.take().unwrap();
+        assert_eq!((step.spawn_start, step.spawn_count), (0, 8));
+        assert_eq!(step.delta, 0.5);
+        assert_eq!(spawner.cursor, 3);
+    }
+}
@@ -0,0 +1,255 @@
+//! CPU mirror of `particles_simulate.wgsl` for adapters without compute shaders, and the
+//! data layouts both share.
+
+use glam::{UVec4, Vec3, Vec4};
+
+use crate::render::{types, uniform::ShaderType};
+
+use super::{EmitterDesc, Force, CURVE_SAMPLES, MAX_FORCES};
+
+const FORCE_DIRECTIONAL: u32 = 0;
+const FORCE_POINT: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+pub(super) struct EmitterUniform {
+    position: Vec3,
+    spawn_radius: f32,
+    velocity: Vec3,
+    velocity_spread: f32,
+    gravity: Vec3,
+    drag: f32,
+    lifetime: f32,
+    lifetime_variance: f32,
+    delta: f32,
+    seed: u32,
+    spawn_start: u32,
+    spawn_count: u32,
+    capacity: u32,
+    force_count: u32,
+    forces: [Vec4; MAX_FORCES],
+    force_kinds: UVec4,
+    speed: [Vec4; CURVE_SAMPLES / 4],
+    size: [Vec4; CURVE_SAMPLES / 4],
+    color: [Vec4; CURVE_SAMPLES],
+}
+
+/// One frame of simulation for one emitter.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Step {
+    pub delta: f32,
+    pub seed: u32,
+    pub spawn_start: u32,
+    pub spawn_count: u32,
+    pub capacity: u32,
+}
+
+impl EmitterUniform {
+    pub fn new(desc: &EmitterDesc, step: Step) -> Self {
+        let mut forces = [Vec4::ZERO; MAX_FORCES];
+        let mut force_kinds = [0; MAX_FORCES];
+        for (index, force) in desc.forces.iter().take(MAX_FORCES).enumerate() {
+            (forces[index], force_kinds[index]) = match *force {
+                Force::Directional(acceleration) => (acceleration.extend(0.0), FORCE_DIRECTIONAL),
+                Force::Point { position, strength } => (position.extend(strength), FORCE_POINT),
+            };
+        }
+
+        let pack = |samples: [f32; CURVE_SAMPLES]| {
+            std::array::from_fn(|index| Vec4::from_slice(&samples[index * 4..]))
+        };
+
+        Self {
+            position: desc.position,
+            spawn_radius: desc.spawn_radius,
+            velocity: desc.velocity,
+            velocity_spread: desc.velocity_spread,
+            gravity: desc.gravity,
+            drag: desc.drag,
+            lifetime: desc.lifetime,
+            lifetime_variance: desc.lifetime_variance,
+            delta: step.delta,
+            seed: step.seed,
+            spawn_start: step.spawn_start,
+            spawn_count: step.spawn_count,
+            capacity: step.capacity,
+            force_count: desc.forces.len().min(MAX_FORCES) as u32,
+            forces,
+            force_kinds: UVec4::from_array(force_kinds),
+            speed: pack(desc.speed_over_life.bake()),
+            size: pack(desc.size_over_life.bake()),
+            color: desc.color_over_life.bake(),
+        }
+    }
+
+    fn speed_at(&self, t: f32) -> f32 {
+        let (i, f) = curve_position(t);
+        let j = i + 1;
+        lerp(self.speed[i / 4][i % 4], self.speed[j / 4][j % 4], f)
+    }
+
+    fn size_at(&self, t: f32) -> f32 {
+        let (i, f) = curve_position(t);
+        let j = i + 1;
+        lerp(self.size[i / 4][i % 4], self.size[j / 4][j % 4], f)
+    }
+
+    fn color_at(&self, t: f32) -> Vec4 {
+        let (i, f) = curve_position(t);
+        self.color[i].lerp(self.color[i + 1], f)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+pub(super) struct Particle {
+    position: Vec3,
+    age: f32,
+    velocity: Vec3,
+    lifetime: f32,
+}
+
+#[repr(C)]
+#[derive(
+    Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType, types::VertexDescription,
+)]
+#[vertex(step_mode = "instance")]
+pub(super) struct ParticleInstance {
+    position: Vec3,
+    size: f32,
+    color: Vec4,
+}
+
+fn hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn random(state: &mut u32) -> f32 {
+    *state = hash(*state);
+    *state as f32 / 4294967295.0
+}
+
+fn random_direction(state: &mut u32) -> Vec3 {
+    let z = random(state) * 2.0 - 1.0;
+    let angle = random(state) * std::f32::consts::TAU;
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(radius * angle.cos(), radius * angle.sin(), z)
+}
+
+fn curve_position(t: f32) -> (usize, f32) {
+    let x = t.clamp(0.0, 1.0) * (CURVE_SAMPLES - 1) as f32;
+    let index = x.floor().min((CURVE_SAMPLES - 2) as f32);
+    (index as usize, x - index)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Advances every particle one step and writes the live ones to `instances`, packed from
+/// the start. Returns how many were written.
+pub(super) fn simulate(
+    emitter: &EmitterUniform,
+    particles: &mut [Particle],
+    instances: &mut [ParticleInstance],
+) -> usize {
+    let dt = emitter.delta;
+    let capacity = emitter.capacity;
+    let mut count = 0;
+    for (index, particle) in particles.iter_mut().enumerate() {
+        let index = index as u32;
+        if (index + capacity - emitter.spawn_start) % capacity < emitter.spawn_count {
+            let mut state = hash(index ^ hash(emitter.seed));
+            let offset = random_direction(&mut state)
+                * emitter.spawn_radius
+                * random(&mut state).powf(1.0 / 3.0);
+            particle.position = emitter.position + offset;
+            particle.velocity = emitter.velocity
+                + random_direction(&mut state) * emitter.velocity_spread * random(&mut state);
+            particle.lifetime = (emitter.lifetime
+                + (random(&mut state) * 2.0 - 1.0) * emitter.lifetime_variance)
+                .max(0.001);
+            particle.age = 0.0;
+        } else if particle.age < particle.lifetime {
+            let mut acceleration = emitter.gravity;
+            for i in 0..(emitter.force_count as usize).min(MAX_FORCES) {
+                let force = emitter.forces[i];
+                match emitter.force_kinds[i] {
+                    FORCE_DIRECTIONAL => acceleration += force.truncate(),
+                    FORCE_POINT => {
+                        let to_point = force.truncate() - particle.position;
+                        if to_point.length_squared() > 0.0001 {
+                            acceleration += to_point.normalize() * force.w;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            particle.velocity =
+                (particle.velocity + acceleration * dt) * (1.0 - emitter.drag * dt).max(0.0);
+            particle.age += dt;
+            let t = particle.age / particle.lifetime;
+            particle.position += particle.velocity * emitter.speed_at(t) * dt;
+        }
+
+        if particle.age < particle.lifetime {
+            let t = particle.age / particle.lifetime;
+            instances[count] = ParticleInstance {
+                position: particle.position,
+                size: emitter.size_at(t),
+                color: emitter.color_at(t),
+            };
+            count += 1;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(spawn_start: u32, spawn_count: u32, capacity: u32) -> EmitterUniform {
+        let desc = EmitterDesc {
+            lifetime: 1.0,
+            lifetime_variance: 0.0,
+            ..EmitterDesc::default()
+        };
+        EmitterUniform::new(
+            &desc,
+            Step {
+                delta: 0.25,
+                seed: 7,
+                spawn_start,
+                spawn_count,
+                capacity,
+            },
+        )
+    }
+
+    fn run(emitter: &EmitterUniform, particles: &mut [Particle]) -> usize {
+        let mut instances = vec![bytemuck::Zeroable::zeroed(); particles.len()];
+        simulate(emitter, particles, &mut instances)
+    }
+
+    #[test]
+    fn spawns_wrap_around_the_ring() {
+        let mut particles = [Particle::default(); 4];
+        assert_eq!(run(&step(3, 2, 4), &mut particles), 2);
+        let spawned = particles.map(|particle| particle.lifetime > 0.0);
+        assert_eq!(spawned, [true, false, false, true]);
+    }
+
+    #[test]
+    fn particles_expire_after_their_lifetime() {
+        let mut particles = [Particle::default(); 2];
+        assert_eq!(run(&step(0, 1, 2), &mut particles), 1);
+        for _ in 0..3 {
+            assert_eq!(run(&step(0, 0, 2), &mut particles), 1);
+        }
+        assert_eq!(run(&step(0, 0, 2), &mut particles), 0);
+        assert_eq!(particles[0].age, 1.0);
+    }
+}
@@ -0,0 +1,439 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Features the profiler uses when the adapter has them. Nothing is recorded unless the
+/// profiler is also switched on with `GpuProfiler::set_enabled`.
+pub const FEATURES: wgpu::Features =
+    wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::PIPELINE_STATISTICS_QUERY);
+
+const MAX_PASSES: u32 = 32;
+const FRAMES_IN_FLIGHT: usize = 3;
+const HISTORY_LEN: usize = 240;
+const STATISTICS: wgpu::PipelineStatisticsTypes =
+    wgpu::PipelineStatisticsTypes::VERTEX_SHADER_INVOCATIONS
+        .union(wgpu::PipelineStatisticsTypes::CLIPPER_INVOCATIONS)
+        .union(wgpu::PipelineStatisticsTypes::CLIPPER_PRIMITIVES_OUT)
+        .union(wgpu::PipelineStatisticsTypes::FRAGMENT_SHADER_INVOCATIONS);
+const STATISTICS_COUNT: u64 = 4;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipelineStatistics {
+    pub vertex_shader_invocations: u64,
+    pub clipper_invocations: u64,
+    pub clipper_primitives_out: u64,
+    pub fragment_shader_invocations: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    pub name: &'static str,
+    /// `None` when the adapter lacks `Features::TIMESTAMP_QUERY`.
+    pub gpu_ms: Option<f64>,
+    /// `None` unless the pass was begun with `GpuProfiler::begin_statistics_pass` and the
+    /// adapter has `Features::PIPELINE_STATISTICS_QUERY`.
+    pub statistics: Option<PipelineStatistics>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameTiming {
+    /// Wall clock time since the previous profiled frame began.
+    pub cpu_ms: f64,
+    pub passes: Vec<PassTiming>,
+}
+
+impl FrameTiming {
+    pub fn gpu_ms(&self) -> f64 {
+        self.passes.iter().filter_map(|p| p.gpu_ms).sum()
+    }
+}
+
+/// Identifies a pass within the frame being profiled.
+#[derive(Debug, Clone, Copy)]
+pub struct PassScope {
+    index: u32,
+    /// Pipeline statistics query, if the pass has one.
+    statistics: Option<u32>,
+}
+
+/// Opt-in GPU profiler that writes timestamps (and pipeline statistics where available)
+/// around each pass, resolves them into a readback buffer and collects the results a few
+/// frames later without stalling, keeping a rolling history alongside CPU frame times.
+#[derive(Debug)]
+pub struct GpuProfiler {
+    enabled: bool,
+    timestamp_period: f32,
+    slots: Vec<QuerySlot>,
+    current: Option<usize>,
+    cpu_frame_start: Option<instant::Instant>,
+    history: VecDeque<FrameTiming>,
+}
+
+#[derive(Debug)]
+struct QuerySlot {
+    timestamps: Option<wgpu::QuerySet>,
+    statistics: Option<wgpu::QuerySet>,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    statistics_offset: u64,
+    passes: Vec<&'static str>,
+    /// Index of the pass each pipeline statistics query belongs to, in query order.
+    statistics_passes: Vec<u32>,
+    cpu_ms: f64,
+    state: SlotState,
+}
+
+#[derive(Debug)]
+enum SlotState {
+    Free,
+    Recording,
+    Resolved,
+    Mapping(Arc<AtomicBool>),
+}
+
+impl GpuProfiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let features = device.features();
+        let timestamps_size = MAX_PASSES as u64 * 2 * 8;
+        let statistics_offset =
+            wgpu::util::align_to(timestamps_size, wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT);
+        let size = statistics_offset + MAX_PASSES as u64 * STATISTICS_COUNT * 8;
+
+        let slots = (0..FRAMES_IN_FLIGHT)
+            .map(|_| QuerySlot {
+                timestamps: features.contains(wgpu::Features::TIMESTAMP_QUERY).then(|| {
+                    device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("Profiler Timestamps"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: MAX_PASSES * 2,
+                    })
+                }),
+                statistics: features
+                    .contains(wgpu::Features::PIPELINE_STATISTICS_QUERY)
+                    .then(|| {
+                        device.create_query_set(&wgpu::QuerySetDescriptor {
+                            label: Some("Profiler Pipeline Statistics"),
+                            ty: wgpu::QueryType::PipelineStatistics(STATISTICS),
+                            count: MAX_PASSES,
+                        })
+                    }),
+                resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Resolve Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                readback: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Readback Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                statistics_offset,
+                passes: Vec::new(),
+                statistics_passes: Vec::new(),
+                cpu_ms: 0.0,
+                state: SlotState::Free,
+            })
+            .collect();
+
+        Self {
+            enabled: false,
+            timestamp_period: queue.get_timestamp_period(),
+            slots,
+            current: None,
+            cpu_frame_start: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.cpu_frame_start = None;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Profiled frames, oldest first. GPU results lag a couple of frames behind.
+    pub fn history(&self) -> &VecDeque<FrameTiming> {
+        &self.history
+    }
+
+    pub fn latest(&self) -> Option<&FrameTiming> {
+        self.history.back()
+    }
+
+    pub fn average_cpu_ms(&self) -> f64 {
+        average(self.history.iter().map(|f| f.cpu_ms))
+    }
+
+    pub fn average_pass_ms(&self, name: &str) -> f64 {
+        average(
+            self.history
+                .iter()
+                .flat_map(|f| f.passes.iter())
+                .filter(|p| p.name == name)
+                .filter_map(|p| p.gpu_ms),
+        )
+    }
+
+    pub fn log_summary(&self) {
+        let Some(latest) = self.latest() else {
+            return;
+        };
+        log::info!(
+            "Frame: {:.2}ms CPU (avg {:.2}ms), {:.3}ms GPU",
+            latest.cpu_ms,
+            self.average_cpu_ms(),
+            latest.gpu_ms()
+        );
+        for pass in latest.passes.iter() {
+            log::info!(
+                "  {}: {:.3}ms (avg {:.3}ms) {:?}",
+                pass.name,
+                pass.gpu_ms.unwrap_or_default(),
+                self.average_pass_ms(pass.name),
+                pass.statistics
+            );
+        }
+    }
+
+    /// Collects finished results and claims a query slot for this frame. Needs the
+    /// device to have been polled since the last `after_submit`.
+    pub fn begin_frame(&mut self) {
+        self.collect();
+
+        // A frame that bailed out before submitting never released its slot.
+        if let Some(stale) = self.current.take() {
+            self.slots[stale].state = SlotState::Free;
+        }
+
+        if !self.enabled {
+            return;
+        }
+
+        let now = instant::Instant::now();
+        let cpu_ms = self
+            .cpu_frame_start
+            .replace(now)
+            .map(|start| (now - start).as_secs_f64() * 1000.0)
+            .unwrap_or_default();
+
+        // If every slot is still waiting on the GPU this frame just isn't profiled.
+        self.current = self
+            .slots
+            .iter()
+            .position(|slot| matches!(slot.state, SlotState::Free));
+        if let Some(slot) = self.current.map(|index| &mut self.slots[index]) {
+            slot.state = SlotState::Recording;
+            slot.passes.clear();
+            slot.statistics_passes.clear();
+            slot.cpu_ms = cpu_ms;
+        }
+    }
+
+    pub fn begin_pass(&mut self, name: &'static str) -> Option<PassScope> {
+        self.begin_scope(name, false)
+    }
+
+    /// Like `begin_pass`, and also reserves a pipeline statistics query for
+    /// `begin_statistics` and `end_statistics` to record in a render pass.
+    pub fn begin_statistics_pass(&mut self, name: &'static str) -> Option<PassScope> {
+        self.begin_scope(name, true)
+    }
+
+    fn begin_scope(&mut self, name: &'static str, statistics: bool) -> Option<PassScope> {
+        let slot = &mut self.slots[self.current?];
+        if slot.passes.len() as u32 >= MAX_PASSES {
+            log::warn!("Profiler pass limit reached, not timing '{}'", name);
+            return None;
+        }
+        let index = slot.passes.len() as u32;
+        slot.passes.push(name);
+        let statistics = (statistics && slot.statistics.is_some()).then(|| {
+            slot.statistics_passes.push(index);
+            slot.statistics_passes.len() as u32 - 1
+        });
+        Some(PassScope { index, statistics })
+    }
+
+    /// Timestamp writes for a render pass descriptor.
+    pub fn timestamp_writes(
+        &self,
+        scope: Option<PassScope>,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let (query_set, index) = self.timestamp_query(scope)?;
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    /// Timestamp writes for a compute pass descriptor.
+    pub fn compute_timestamp_writes(
+        &self,
+        scope: Option<PassScope>,
+    ) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let (query_set, index) = self.timestamp_query(scope)?;
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    fn timestamp_query(&self, scope: Option<PassScope>) -> Option<(&wgpu::QuerySet, u32)> {
+        let index = scope?.index;
+        let query_set = self.slots[self.current?].timestamps.as_ref()?;
+        Some((query_set, index))
+    }
+
+    /// Does nothing unless `scope` came from `begin_statistics_pass`.
+    pub fn begin_statistics<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        scope: Option<PassScope>,
+    ) {
+        let Some(query) = scope.and_then(|scope| scope.statistics) else {
+            return;
+        };
+        if let Some(query_set) = self
+            .current
+            .and_then(|current| self.slots[current].statistics.as_ref())
+        {
+            pass.begin_pipeline_statistics_query(query_set, query);
+        }
+    }
+
+    pub fn end_statistics(&self, pass: &mut wgpu::RenderPass, scope: Option<PassScope>) {
+        if scope.is_some_and(|scope| scope.statistics.is_some()) {
+            pass.end_pipeline_statistics_query();
+        }
+    }
+
+    /// Resolves this frame's queries. Call once all profiled passes have been recorded.
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(slot) = self.current.map(|index| &mut self.slots[index]) else {
+            return;
+        };
+        let count = slot.passes.len() as u32;
+        let statistics_count = slot.statistics_passes.len() as u32;
+
+        if count > 0 {
+            if let Some(timestamps) = &slot.timestamps {
+                encoder.resolve_query_set(timestamps, 0..count * 2, &slot.resolve, 0);
+            }
+            // Only the queries of passes begun with `begin_statistics_pass` were written.
+            if let Some(statistics) = slot.statistics.as_ref().filter(|_| statistics_count > 0) {
+                encoder.resolve_query_set(
+                    statistics,
+                    0..statistics_count,
+                    &slot.resolve,
+                    slot.statistics_offset,
+                );
+            }
+            encoder.copy_buffer_to_buffer(&slot.resolve, 0, &slot.readback, 0, slot.resolve.size());
+        }
+
+        slot.state = SlotState::Resolved;
+    }
+
+    /// Starts reading back the frame's results. Call after the frame is submitted.
+    pub fn after_submit(&mut self) {
+        let Some(slot) = self.current.take().map(|index| &mut self.slots[index]) else {
+            return;
+        };
+
+        let ready = Arc::new(AtomicBool::new(false));
+        if slot.passes.is_empty() {
+            ready.store(true, Ordering::Release);
+        } else {
+            let signal = ready.clone();
+            slot.readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| match result {
+                    Ok(()) => signal.store(true, Ordering::Release),
+                    Err(err) => log::error!("Failed to map profiler buffer: {}", err),
+                });
+        }
+        slot.state = SlotState::Mapping(ready);
+    }
+
+    fn collect(&mut self) {
+        for slot in self.slots.iter_mut() {
+            match &slot.state {
+                SlotState::Mapping(ready) if ready.load(Ordering::Acquire) => (),
+                _ => continue,
+            }
+
+            let passes = if slot.passes.is_empty() {
+                Vec::new()
+            } else {
+                let passes = slot.read_passes(self.timestamp_period);
+                slot.readback.unmap();
+                passes
+            };
+
+            self.history.push_back(FrameTiming {
+                cpu_ms: slot.cpu_ms,
+                passes,
+            });
+            if self.history.len() > HISTORY_LEN {
+                self.history.pop_front();
+            }
+
+            slot.state = SlotState::Free;
+        }
+    }
+}
+
+impl QuerySlot {
+    fn read_passes(&self, timestamp_period: f32) -> Vec<PassTiming> {
+        let view = self.readback.slice(..).get_mapped_range();
+        let values: &[u64] = bytemuck::cast_slice(&view);
+        let statistics = &values[(self.statistics_offset / 8) as usize..];
+
+        self.passes
+            .iter()
+            .enumerate()
+            .map(|(index, &name)| PassTiming {
+                name,
+                gpu_ms: self.timestamps.as_ref().map(|_| {
+                    let ticks = values[index * 2 + 1].wrapping_sub(values[index * 2]);
+                    ticks as f64 * timestamp_period as f64 / 1_000_000.0
+                }),
+                statistics: self
+                    .statistics_passes
+                    .iter()
+                    .position(|&pass| pass as usize == index)
+                    .map(|query| {
+                        let stats = &statistics[query * STATISTICS_COUNT as usize..];
+                        PipelineStatistics {
+                            vertex_shader_invocations: stats[0],
+                            clipper_invocations: stats[1],
+                            clipper_primitives_out: stats[2],
+                            fragment_shader_invocations: stats[3],
+                        }
+                    }),
+            })
+            .collect()
+    }
+}
+
+fn average<I: Iterator<Item = f64>>(values: I) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
@@ -0,0 +1,485 @@
+use std::{collections::BTreeMap, num::NonZeroU64};
+
+use crate::{GameError, Result};
+
+/// Parsed and validated WGSL module used to derive pipeline state from the
+/// `@group/@binding` and `@location` declarations in the shader itself.
+#[derive(Debug)]
+pub struct ShaderReflection {
+    module: naga::Module,
+    info: naga::valid::ModuleInfo,
+}
+
+impl ShaderReflection {
+    pub fn new(source: &str) -> Result<Self> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|err| GameError::ShaderParseError(err.emit_to_string(source)))?;
+
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|err| GameError::ShaderValidationError(err.emit_to_string(source)))?;
+
+        Ok(Self { module, info })
+    }
+
+    pub fn module(&self) -> &naga::Module {
+        &self.module
+    }
+
+    /// Layout entries for every resource declared in `@group(group)`, sorted by binding.
+    /// Visibility is the set of entry point stages that actually use the resource.
+    pub fn bind_group_layout_entries(&self, group: u32) -> Result<Vec<wgpu::BindGroupLayoutEntry>> {
+        let mut entries = BTreeMap::new();
+
+        for (handle, global) in self.module.global_variables.iter() {
+            let Some(binding) = global.binding.as_ref().filter(|b| b.group == group) else {
+                continue;
+            };
+
+            let entry = wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility: self.visibility(handle),
+                ty: self.binding_type(global)?,
+                count: None,
+            };
+
+            if entries.insert(binding.binding, entry).is_some() {
+                return Err(GameError::ShaderReflectionError(format!(
+                    "@group({}) @binding({}) is declared more than once",
+                    group, binding.binding
+                )));
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(GameError::ShaderReflectionError(format!(
+                "shader declares no bindings in @group({})",
+                group
+            )));
+        }
+
+        Ok(entries.into_values().collect())
+    }
+
+    pub fn create_bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        group: u32,
+        label: Option<&str>,
+    ) -> Result<wgpu::BindGroupLayout> {
+        let entries = self.bind_group_layout_entries(group)?;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label,
+            entries: &entries,
+        });
+        Ok(layout)
+    }
+
+    /// `@location` inputs of a vertex entry point, keyed by location.
+    pub fn vertex_inputs(&self, entry_point: &str) -> Result<BTreeMap<u32, VertexInput>> {
+        let entry = self
+            .module
+            .entry_points
+            .iter()
+            .find(|ep| ep.name == entry_point && ep.stage == naga::ShaderStage::Vertex)
+            .ok_or_else(|| {
+                GameError::ShaderReflectionError(format!(
+                    "no vertex entry point named '{}'",
+                    entry_point
+                ))
+            })?;
+
+        let mut inputs = BTreeMap::new();
+        for argument in entry.function.arguments.iter() {
+            match (&argument.binding, &self.module.types[argument.ty].inner) {
+                (Some(binding), _) => {
+                    self.collect_input(&mut inputs, argument.name.as_deref(), binding, argument.ty)
+                }
+                (None, naga::TypeInner::Struct { members, .. }) => {
+                    for member in members {
+                        if let Some(binding) = &member.binding {
+                            self.collect_input(
+                                &mut inputs,
+                                member.name.as_deref(),
+                                binding,
+                                member.ty,
+                            );
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    /// Checks that the vertex buffer layouts supply every `@location` the entry point
+    /// reads, with a format of the same scalar kind and component count.
+    pub fn validate_vertex_buffers(
+        &self,
+        entry_point: &str,
+        buffers: &[wgpu::VertexBufferLayout],
+    ) -> Result<()> {
+        let inputs = self.vertex_inputs(entry_point)?;
+
+        let mut attributes = BTreeMap::new();
+        for (slot, buffer) in buffers.iter().enumerate() {
+            for attribute in buffer.attributes {
+                if let Some(previous) =
+                    attributes.insert(attribute.shader_location, (slot, attribute))
+                {
+                    return Err(GameError::ShaderReflectionError(format!(
+                        "@location({}) is provided by both vertex buffer {} and {}",
+                        attribute.shader_location, previous.0, slot
+                    )));
+                }
+            }
+        }
+
+        for (location, input) in inputs.iter() {
+            let Some((slot, attribute)) = attributes.get(location) else {
+                return Err(GameError::ShaderReflectionError(format!(
+                    "'{}' expects '{}' at @location({}), but no vertex buffer provides it",
+                    entry_point, input.name, location
+                )));
+            };
+
+            let format = vertex_format_shape(attribute.format);
+            if format != (input.kind, input.components) {
+                return Err(GameError::ShaderReflectionError(format!(
+                    "'{}' expects '{}' at @location({}) as {} x {:?}, but vertex buffer {} provides {:?}",
+                    entry_point,
+                    input.name,
+                    location,
+                    input.components,
+                    input.kind,
+                    slot,
+                    attribute.format
+                )));
+            }
+        }
+
+        for (location, (slot, _)) in attributes.iter() {
+            if !inputs.contains_key(location) {
+                log::warn!(
+                    "Vertex buffer {} provides @location({}) which '{}' never reads",
+                    slot,
+                    location,
+                    entry_point
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn collect_input(
+        &self,
+        inputs: &mut BTreeMap<u32, VertexInput>,
+        name: Option<&str>,
+        binding: &naga::Binding,
+        ty: naga::Handle<naga::Type>,
+    ) {
+        let naga::Binding::Location { location, .. } = *binding else {
+            return;
+        };
+
+        let (kind, components) = match self.module.types[ty].inner {
+            naga::TypeInner::Scalar(scalar) => (scalar.kind, 1),
+            naga::TypeInner::Vector { size, scalar } => (scalar.kind, size as u32),
+            _ => return,
+        };
+
+        inputs.insert(
+            location,
+            VertexInput {
+                name: name.unwrap_or("<unnamed>").to_string(),
+                kind,
+                components,
+            },
+        );
+    }
+
+    fn visibility(&self, handle: naga::Handle<naga::GlobalVariable>) -> wgpu::ShaderStages {
+        self.module
+            .entry_points
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !self.info.get_entry_point(*index)[handle].is_empty())
+            .fold(wgpu::ShaderStages::NONE, |stages, (_, ep)| {
+                stages
+                    | match ep.stage {
+                        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                    }
+            })
+    }
+
+    fn binding_type(&self, global: &naga::GlobalVariable) -> Result<wgpu::BindingType> {
+        let name = global.name.as_deref().unwrap_or("<unnamed>");
+        let inner = &self.module.types[global.ty].inner;
+
+        match global.space {
+            naga::AddressSpace::Uniform => Ok(wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(inner.size(self.module.to_ctx()) as u64),
+            }),
+            naga::AddressSpace::Storage { access } => Ok(wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: !access.contains(naga::StorageAccess::STORE),
+                },
+                has_dynamic_offset: false,
+                // Runtime sized arrays report the size of their fixed prefix.
+                min_binding_size: NonZeroU64::new(inner.size(self.module.to_ctx()) as u64),
+            }),
+            naga::AddressSpace::Handle => match *inner {
+                naga::TypeInner::Sampler { comparison } => {
+                    Ok(wgpu::BindingType::Sampler(if comparison {
+                        wgpu::SamplerBindingType::Comparison
+                    } else {
+                        wgpu::SamplerBindingType::Filtering
+                    }))
+                }
+                naga::TypeInner::Image {
+                    dim,
+                    arrayed,
+                    class,
+                } => {
+                    let view_dimension = match (dim, arrayed) {
+                        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                    };
+
+                    match class {
+                        naga::ImageClass::Sampled { kind, multi } => {
+                            Ok(wgpu::BindingType::Texture {
+                                sample_type: match kind {
+                                    naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                                    naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                                    // Filterability isn't expressed in WGSL, so assume the
+                                    // common case of a filtering sampler alongside it.
+                                    _ => wgpu::TextureSampleType::Float { filterable: !multi },
+                                },
+                                view_dimension,
+                                multisampled: multi,
+                            })
+                        }
+                        naga::ImageClass::Depth { multi } => Ok(wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension,
+                            multisampled: multi,
+                        }),
+                        naga::ImageClass::Storage { format, access } => {
+                            Ok(wgpu::BindingType::StorageTexture {
+                                access: match (
+                                    access.contains(naga::StorageAccess::LOAD),
+                                    access.contains(naga::StorageAccess::STORE),
+                                ) {
+                                    (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                                    (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+                                    _ => wgpu::StorageTextureAccess::WriteOnly,
+                                },
+                                format: storage_format(format),
+                                view_dimension,
+                            })
+                        }
+                    }
+                }
+                _ => Err(GameError::ShaderReflectionError(format!(
+                    "'{}' has a handle type that can't be bound",
+                    name
+                ))),
+            },
+            space => Err(GameError::ShaderReflectionError(format!(
+                "'{}' is in address space {:?} which has no bind group layout equivalent",
+                name, space
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VertexInput {
+    pub name: String,
+    pub kind: naga::ScalarKind,
+    pub components: u32,
+}
+
+fn vertex_format_shape(format: wgpu::VertexFormat) -> (naga::ScalarKind, u32) {
+    use naga::ScalarKind::{Float, Sint, Uint};
+    use wgpu::VertexFormat as F;
+
+    match format {
+        F::Uint32 => (Uint, 1),
+        F::Uint8x2 | F::Uint16x2 | F::Uint32x2 => (Uint, 2),
+        F::Uint32x3 => (Uint, 3),
+        F::Uint8x4 | F::Uint16x4 | F::Uint32x4 => (Uint, 4),
+        F::Sint32 => (Sint, 1),
+        F::Sint8x2 | F::Sint16x2 | F::Sint32x2 => (Sint, 2),
+        F::Sint32x3 => (Sint, 3),
+        F::Sint8x4 | F::Sint16x4 | F::Sint32x4 => (Sint, 4),
+        F::Float32 | F::Float64 => (Float, 1),
+        F::Unorm8x2
+        | F::Snorm8x2
+        | F::Unorm16x2
+        | F::Snorm16x2
+        | F::Float16x2
+        | F::Float32x2
+        | F::Float64x2 => (Float, 2),
+        F::Float32x3 | F::Float64x3 => (Float, 3),
+        F::Unorm8x4
+        | F::Snorm8x4
+        | F::Unorm16x4
+        | F::Snorm16x4
+        | F::Float16x4
+        | F::Float32x4
+        | F::Float64x4 => (Float, 4),
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as S;
+    use wgpu::TextureFormat as T;
+
+    match format {
+        S::R8Unorm => T::R8Unorm,
+        S::R8Snorm => T::R8Snorm,
+        S::R8Uint => T::R8Uint,
+        S::R8Sint => T::R8Sint,
+        S::R16Uint => T::R16Uint,
+        S::R16Sint => T::R16Sint,
+        S::R16Float => T::R16Float,
+        S::Rg8Unorm => T::Rg8Unorm,
+        S::Rg8Snorm => T::Rg8Snorm,
+        S::Rg8Uint => T::Rg8Uint,
+        S::Rg8Sint => T::Rg8Sint,
+        S::R32Uint => T::R32Uint,
+        S::R32Sint => T::R32Sint,
+        S::R32Float => T::R32Float,
+        S::Rg16Uint => T::Rg16Uint,
+        S::Rg16Sint => T::Rg16Sint,
+        S::Rg16Float => T::Rg16Float,
+        S::Rgba8Unorm => T::Rgba8Unorm,
+        S::Rgba8Snorm => T::Rgba8Snorm,
+        S::Rgba8Uint => T::Rgba8Uint,
+        S::Rgba8Sint => T::Rgba8Sint,
+        S::Bgra8Unorm => T::Bgra8Unorm,
+        S::Rgb10a2Uint => T::Rgb10a2Uint,
+        S::Rgb10a2Unorm => T::Rgb10a2Unorm,
+        S::Rg11b10Float => T::Rg11b10Float,
+        S::Rg32Uint => T::Rg32Uint,
+        S::Rg32Sint => T::Rg32Sint,
+        S::Rg32Float => T::Rg32Float,
+        S::Rgba16Uint => T::Rgba16Uint,
+        S::Rgba16Sint => T::Rgba16Sint,
+        S::Rgba16Float => T::Rgba16Float,
+        S::Rgba32Uint => T::Rgba32Uint,
+        S::Rgba32Sint => T::Rgba32Sint,
+        S::Rgba32Float => T::Rgba32Float,
+        S::R16Unorm => T::R16Unorm,
+        S::R16Snorm => T::R16Snorm,
+        S::Rg16Unorm => T::Rg16Unorm,
+        S::Rg16Snorm => T::Rg16Snorm,
+        S::Rgba16Unorm => T::Rgba16Unorm,
+        S::Rgba16Snorm => T::Rgba16Snorm,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERTEX: &str = "
+        struct VertexInput {
+            @location(0) position: vec3<f32>,
+            @location(1) tex_coords: vec2<f32>,
+        };
+
+        @vertex
+        fn vs_main(input: VertexInput) -> @builtin(position) vec4<f32> {
+            return vec4<f32>(input.position + vec3<f32>(input.tex_coords, 0.0), 1.0);
+        }
+    ";
+
+    fn layout(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: 20,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        }
+    }
+
+    #[test]
+    fn accepts_matching_vertex_buffers() {
+        let reflection = ShaderReflection::new(VERTEX).unwrap();
+        let attributes = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+        assert!(reflection
+            .validate_vertex_buffers("vs_main", &[layout(&attributes)])
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_or_missing_vertex_attributes() {
+        let reflection = ShaderReflection::new(VERTEX).unwrap();
+        let wrong_format = wgpu::vertex_attr_array![0 => Float32x3, 1 => Uint32x2];
+        assert!(reflection
+            .validate_vertex_buffers("vs_main", &[layout(&wrong_format)])
+            .is_err());
+        let missing = wgpu::vertex_attr_array![0 => Float32x3];
+        assert!(reflection
+            .validate_vertex_buffers("vs_main", &[layout(&missing)])
+            .is_err());
+    }
+
+    #[test]
+    fn maps_bindings_to_layout_entries() {
+        let reflection = ShaderReflection::new(
+            "
+            @group(0) @binding(0) var color: texture_2d<f32>;
+            @group(0) @binding(1) var color_sampler: sampler;
+            @group(0) @binding(2) var<storage, read> weights: array<f32>;
+            @group(0) @binding(3) var<storage, read_write> output: array<f32>;
+
+            @fragment
+            fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+                output[0] = weights[0];
+                return textureSample(color, color_sampler, position.xy);
+            }
+            ",
+        )
+        .unwrap();
+        let entries = reflection.bind_group_layout_entries(0).unwrap();
+        let types = entries.iter().map(|entry| entry.ty).collect::<Vec<_>>();
+        let storage = |read_only| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(4),
+        };
+        assert_eq!(
+            types,
+            [
+                wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                storage(true),
+                storage(false),
+            ]
+        );
+        assert!(entries
+            .iter()
+            .all(|entry| entry.visibility == wgpu::ShaderStages::FRAGMENT));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::VecDeque,
+    marker::PhantomData,
+    num::NonZeroU64,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{GameError, Result};
+
+use super::uniform::{round_up, AssertUniform, ShaderType};
+
+/// A large uniform buffer shared by many draws through dynamic offsets.
+///
+/// Each frame, `push` a value per draw and bind the returned offset with
+/// `set_bind_group(n, ring.bind_group(), &[offset])`. Call `flush` before submitting the
+/// frame's command buffers and `end_frame` right after; the frame's memory is recycled
+/// once the GPU reports that submission as done, which requires the device to be
+/// polled (`Render::render` does so every frame).
+#[derive(Debug)]
+pub struct UniformRing<T: ShaderType> {
+    buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    stride: u64,
+    capacity: u64,
+    head: u64,
+    used: u64,
+    /// Bytes consumed by the frame currently being recorded, including wrap waste.
+    frame_bytes: u64,
+    in_flight: VecDeque<InFlightFrame>,
+    pending: Vec<PendingWrite>,
+    _data: PhantomData<T>,
+}
+
+#[derive(Debug)]
+struct InFlightFrame {
+    bytes: u64,
+    done: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+struct PendingWrite {
+    offset: u64,
+    data: Vec<u8>,
+}
+
+impl<T: ShaderType> UniformRing<T> {
+    /// Creates a ring with room for `capacity` values, each padded to the device's
+    /// `min_uniform_buffer_offset_alignment`.
+    pub fn new(
+        device: &wgpu::Device,
+        visibility: wgpu::ShaderStages,
+        capacity: u32,
+        label: &str,
+    ) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = AssertUniform::<T>::VALID;
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let stride = round_up(alignment, std::mem::size_of::<T>()) as u64;
+        let size = stride * capacity.max(1) as u64;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+                },
+                count: None,
+            }],
+            label: Some(label),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+                }),
+            }],
+            label: Some(label),
+        });
+
+        Self {
+            buffer,
+            layout,
+            bind_group,
+            stride,
+            capacity: size,
+            head: 0,
+            used: 0,
+            frame_bytes: 0,
+            in_flight: VecDeque::new(),
+            pending: Vec::new(),
+            _data: PhantomData,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Copies `value` into this frame's part of the ring and returns its dynamic offset.
+    pub fn push(&mut self, value: &T) -> Result<wgpu::DynamicOffset> {
+        self.reclaim();
+
+        let wrap = self.head + self.stride > self.capacity;
+        let offset = if wrap { 0 } else { self.head };
+        let needed = self.stride + if wrap { self.capacity - self.head } else { 0 };
+
+        if self.used + needed > self.capacity {
+            return Err(GameError::UniformRingFull(self.capacity));
+        }
+
+        self.used += needed;
+        self.frame_bytes += needed;
+        self.head = offset + self.stride;
+
+        let mut bytes = bytemuck::bytes_of(value).to_vec();
+        bytes.resize(self.stride as usize, 0);
+        match self.pending.last_mut() {
+            Some(write) if write.offset + write.data.len() as u64 == offset => {
+                write.data.extend_from_slice(&bytes)
+            }
+            _ => self.pending.push(PendingWrite {
+                offset,
+                data: bytes,
+            }),
+        }
+
+        Ok(offset as wgpu::DynamicOffset)
+    }
+
+    /// Uploads everything pushed this frame. Must happen before the frame is submitted.
+    pub fn flush(&mut self, queue: &wgpu::Queue) {
+        for write in self.pending.drain(..) {
+            queue.write_buffer(&self.buffer, write.offset, &write.data);
+        }
+    }
+
+    /// Closes the frame. Call right after `queue.submit` so the completion callback
+    /// tracks the submission that reads this frame's values.
+    pub fn end_frame(&mut self, queue: &wgpu::Queue) {
+        if self.frame_bytes == 0 {
+            return;
+        }
+
+        let done = Arc::new(AtomicBool::new(false));
+        let signal = done.clone();
+        queue.on_submitted_work_done(move || signal.store(true, Ordering::Release));
+
+        self.in_flight.push_back(InFlightFrame {
+            bytes: std::mem::take(&mut self.frame_bytes),
+            done,
+        });
+    }
+
+    fn reclaim(&mut self) {
+        while let Some(frame) = self.in_flight.front() {
+            if !frame.done.load(Ordering::Acquire) {
+                break;
+            }
+            self.used -= frame.bytes;
+            self.in_flight.pop_front();
+        }
+    }
+}
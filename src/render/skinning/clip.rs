@@ -0,0 +1,251 @@
+use std::{
+    ops::{Add, Mul},
+    sync::Arc,
+};
+
+use glam::{Quat, Vec3};
+
+use super::skeleton::{Pose, Skeleton};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    /// Holds each keyframe until the next one.
+    Step,
+    /// Hermite spline with per-keyframe tangents, stored as in glTF: in-tangent, value,
+    /// out-tangent for every keyframe.
+    CubicSpline,
+}
+
+impl Interpolation {
+    /// Output values per keyframe time.
+    pub(crate) fn values_per_keyframe(self) -> usize {
+        match self {
+            Interpolation::CubicSpline => 3,
+            Interpolation::Linear | Interpolation::Step => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+/// Animates one property of one joint.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds, ascending.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Seconds, the time of the last keyframe of any channel.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Writes the animated properties at `time` into `pose`. Joints and properties the
+    /// clip doesn't animate are left alone.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in self.channels.iter() {
+            let Some(local) = pose.locals.get_mut(channel.joint) else {
+                continue;
+            };
+            match &channel.keyframes {
+                Keyframes::Translation(values) => {
+                    local.translation = sample(channel.interpolation, &channel.times, values, time);
+                }
+                Keyframes::Rotation(values) => {
+                    local.rotation =
+                        sample(channel.interpolation, &channel.times, values, time).normalize();
+                }
+                Keyframes::Scale(values) => {
+                    local.scale = sample(channel.interpolation, &channel.times, values, time);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) trait Keyframe: Copy + Add<Output = Self> + Mul<f32, Output = Self> {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+}
+
+impl Keyframe for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Keyframe for Vec3 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+impl Keyframe for Quat {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+}
+
+/// Samples keyframes at `time`, clamping to the first and last keyframe. For cubic
+/// splines `values` holds an in-tangent, value and out-tangent per keyframe.
+pub(crate) fn sample<T: Keyframe>(
+    interpolation: Interpolation,
+    times: &[f32],
+    values: &[T],
+    time: f32,
+) -> T {
+    let cubic = interpolation == Interpolation::CubicSpline;
+    let value = |index: usize| {
+        if cubic {
+            values[index * 3 + 1]
+        } else {
+            values[index]
+        }
+    };
+
+    // Index of the first keyframe after `time`.
+    let next = times.partition_point(|&t| t <= time);
+    if next == 0 {
+        return value(0);
+    }
+    if next == times.len() {
+        return value(times.len() - 1);
+    }
+    let previous = next - 1;
+    let span = times[next] - times[previous];
+    let t = (time - times[previous]) / span;
+
+    match interpolation {
+        Interpolation::Step => value(previous),
+        Interpolation::Linear => value(previous).interpolate(value(next), t),
+        Interpolation::CubicSpline => {
+            let out_tangent = values[previous * 3 + 2];
+            let in_tangent = values[next * 3];
+            let (t2, t3) = (t * t, t * t * t);
+            value(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + out_tangent * (span * (t3 - 2.0 * t2 + t))
+                + value(next) * (-2.0 * t3 + 3.0 * t2)
+                + in_tangent * (span * (t3 - t2))
+        }
+    }
+}
+
+/// A clip and how far into it playback is.
+#[derive(Debug, Clone)]
+pub struct ClipState {
+    pub clip: Arc<AnimationClip>,
+    /// Seconds.
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+}
+
+impl ClipState {
+    pub fn new(clip: Arc<AnimationClip>, looping: bool) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping,
+        }
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.time += delta * self.speed;
+        let duration = self.clip.duration;
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0.0, duration);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Fade {
+    from: ClipState,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Plays clips on a skeleton, cross-fading from the previous clip when switching.
+#[derive(Debug, Clone, Default)]
+pub struct Animator {
+    current: Option<ClipState>,
+    fade: Option<Fade>,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switches to `clip`, blending from the current pose over `fade` seconds. Does
+    /// nothing if `clip` is already playing.
+    pub fn play(&mut self, clip: &Arc<AnimationClip>, looping: bool, fade: f32) {
+        if let Some(current) = &self.current {
+            if Arc::ptr_eq(&current.clip, clip) {
+                return;
+            }
+        }
+
+        let next = ClipState::new(clip.clone(), looping);
+        self.fade = match self.current.replace(next) {
+            Some(from) if fade > 0.0 => Some(Fade {
+                from,
+                elapsed: 0.0,
+                duration: fade,
+            }),
+            _ => None,
+        };
+    }
+
+    pub fn current(&mut self) -> Option<&mut ClipState> {
+        self.current.as_mut()
+    }
+
+    /// Advances both clips of a cross-fade by `delta` seconds.
+    pub fn update(&mut self, delta: f64) {
+        let delta = delta as f32;
+        if let Some(current) = &mut self.current {
+            current.advance(delta);
+        }
+        if let Some(fade) = &mut self.fade {
+            fade.from.advance(delta);
+            fade.elapsed += delta;
+            if fade.elapsed >= fade.duration {
+                self.fade = None;
+            }
+        }
+    }
+
+    /// Samples the playing clips over `skeleton`'s rest pose.
+    pub fn pose(&self, skeleton: &Skeleton) -> Pose {
+        let mut pose = skeleton.rest_pose();
+        let Some(current) = &self.current else {
+            return pose;
+        };
+
+        if let Some(fade) = &self.fade {
+            fade.from.clip.sample(fade.from.time, &mut pose);
+            let mut to = skeleton.rest_pose();
+            current.clip.sample(current.time, &mut to);
+            return pose.blend(&to, fade.elapsed / fade.duration);
+        }
+
+        current.clip.sample(current.time, &mut pose);
+        pose
+    }
+}
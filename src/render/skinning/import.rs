@@ -0,0 +1,365 @@
+use std::{collections::HashMap, sync::Arc};
+
+use glam::{Mat4, Quat, UVec4, Vec2, Vec3, Vec4};
+
+use crate::{GameError, Result};
+
+use super::{
+    clip::{AnimationClip, Channel, Interpolation, Keyframes},
+    skeleton::{Joint, Skeleton, Transform},
+    SkinnedVertex,
+};
+
+/// A skinned mesh, its skeleton and animations, ready for `SkinnedRenderer::add`.
+#[derive(Debug, Clone)]
+pub struct SkinnedModel {
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u32>,
+    pub skeleton: Arc<Skeleton>,
+    pub clips: Vec<Arc<AnimationClip>>,
+    /// Base color texture of the first primitive's material, if it has an embedded one.
+    pub base_color: Option<image::RgbaImage>,
+}
+
+impl SkinnedModel {
+    pub fn clip(&self, name: &str) -> Option<&Arc<AnimationClip>> {
+        self.clips.iter().find(|clip| clip.name == name)
+    }
+}
+
+/// Imports the first skinned mesh of a binary glTF (`.glb`) with its skin and every
+/// animation targeting its joints. Buffers and images must be embedded.
+///
+/// Joints are reordered so parents come first; vertex joint indices are remapped to
+/// match.
+pub fn load_glb(bytes: &[u8]) -> Result<SkinnedModel> {
+    let gltf = gltf::Gltf::from_slice(bytes).map_err(gltf_error)?;
+    let buffers = glb_buffers(&gltf)?;
+    let get_buffer = |buffer: gltf::Buffer| buffers.get(buffer.index()).copied();
+
+    let node = gltf
+        .nodes()
+        .find(|node| node.mesh().is_some() && node.skin().is_some())
+        .ok_or_else(|| GameError::GltfError("no skinned mesh".to_string()))?;
+    let (mesh, skin) = (node.mesh().unwrap(), node.skin().unwrap());
+
+    let mut parents = HashMap::new();
+    for node in gltf.nodes() {
+        for child in node.children() {
+            parents.insert(child.index(), node.index());
+        }
+    }
+
+    let (skeleton, joint_remap) = load_skeleton(&gltf, &skin, &parents, &buffers)?;
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for primitive in mesh.primitives() {
+        let reader = primitive.reader(get_buffer);
+        let base = vertices.len() as u32;
+
+        let positions = reader
+            .read_positions()
+            .ok_or_else(|| GameError::GltfError("primitive without positions".to_string()))?;
+        let mut normals = reader.read_normals();
+        let mut tex_coords = reader.read_tex_coords(0).map(|coords| coords.into_f32());
+        let mut joints = reader
+            .read_joints(0)
+            .ok_or_else(|| GameError::GltfError("primitive without JOINTS_0".to_string()))?
+            .into_u16();
+        let mut weights = reader
+            .read_weights(0)
+            .ok_or_else(|| GameError::GltfError("primitive without WEIGHTS_0".to_string()))?
+            .into_f32();
+
+        for position in positions {
+            let joint = joints.next().unwrap_or_default();
+            if let Some(index) = joint
+                .iter()
+                .find(|&&index| index as usize >= joint_remap.len())
+            {
+                return Err(GameError::GltfError(format!(
+                    "vertex uses joint {}, but the skin only has {}",
+                    index,
+                    joint_remap.len()
+                )));
+            }
+            let weight = Vec4::from(weights.next().unwrap_or_default());
+            let sum = weight.element_sum();
+            vertices.push(SkinnedVertex {
+                position: Vec3::from(position),
+                normal: normals
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map_or(Vec3::Z, Vec3::from),
+                tex_coords: tex_coords
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map_or(Vec2::ZERO, Vec2::from),
+                joints: UVec4::from(joint.map(|joint| joint_remap[joint as usize] as u32)),
+                // Exporters don't always normalize; unweighted vertices follow the root.
+                weights: if sum > 0.0 { weight / sum } else { Vec4::X },
+            });
+        }
+
+        match reader.read_indices() {
+            Some(read) => indices.extend(read.into_u32().map(|index| base + index)),
+            None => indices.extend(base..vertices.len() as u32),
+        }
+    }
+
+    let base_color = match mesh.primitives().next() {
+        Some(primitive) => load_base_color(&primitive, &buffers)?,
+        None => None,
+    };
+
+    let joint_of_node = skin
+        .joints()
+        .enumerate()
+        .map(|(index, node)| (node.index(), joint_remap[index]))
+        .collect::<HashMap<_, _>>();
+    let clips = gltf
+        .animations()
+        .enumerate()
+        .map(|(index, animation)| load_clip(index, &animation, &joint_of_node, &buffers))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(SkinnedModel {
+        vertices,
+        indices,
+        skeleton: Arc::new(skeleton),
+        clips,
+        base_color,
+    })
+}
+
+/// Builds the skeleton in parent-first order. Also returns, for every joint in skin
+/// order, its index in the skeleton.
+fn load_skeleton(
+    gltf: &gltf::Gltf,
+    skin: &gltf::Skin,
+    parents: &HashMap<usize, usize>,
+    buffers: &[&[u8]],
+) -> Result<(Skeleton, Vec<usize>)> {
+    let nodes = skin.joints().collect::<Vec<_>>();
+    let skin_index = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (node.index(), index))
+        .collect::<HashMap<_, _>>();
+    let reader = skin.reader(|buffer| buffers.get(buffer.index()).copied());
+    let inverse_binds = match reader.read_inverse_bind_matrices() {
+        Some(matrices) => matrices
+            .map(|matrix| Mat4::from_cols_array_2d(&matrix))
+            .collect(),
+        None => vec![Mat4::IDENTITY; nodes.len()],
+    };
+
+    // Nearest ancestor that is also a joint, in skin order.
+    let joint_parent = |node: usize| {
+        let mut current = node;
+        while let Some(&parent) = parents.get(&current) {
+            if let Some(&index) = skin_index.get(&parent) {
+                return Some(index);
+            }
+            current = parent;
+        }
+        None
+    };
+
+    // Depth-first from the roots lists every parent before its children.
+    let skin_parents = nodes
+        .iter()
+        .map(|node| joint_parent(node.index()))
+        .collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut stack = (0..nodes.len())
+        .filter(|&index| skin_parents[index].is_none())
+        .rev()
+        .collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+        order.push(index);
+        stack.extend(
+            (0..nodes.len())
+                .rev()
+                .filter(|&child| skin_parents[child] == Some(index)),
+        );
+    }
+    if order.len() != nodes.len() {
+        return Err(GameError::GltfError("skin joints form a cycle".to_string()));
+    }
+
+    let mut remap = vec![0; nodes.len()];
+    for (new, &old) in order.iter().enumerate() {
+        remap[old] = new;
+    }
+
+    let joints = order
+        .iter()
+        .map(|&old| {
+            let node = &nodes[old];
+            let (translation, rotation, scale) = node.transform().decomposed();
+            Joint {
+                name: node
+                    .name()
+                    .map_or_else(|| format!("joint_{}", node.index()), str::to_string),
+                parent: skin_parents[old].map(|parent| remap[parent]),
+                rest: Transform {
+                    translation: Vec3::from(translation),
+                    rotation: Quat::from_array(rotation),
+                    scale: Vec3::from(scale),
+                },
+                inverse_bind: inverse_binds.get(old).copied().unwrap_or(Mat4::IDENTITY),
+            }
+        })
+        .collect();
+
+    // Non-joint ancestors of the first root (often an "Armature" node) still move it.
+    let mut root = Mat4::IDENTITY;
+    let mut current = order.first().map(|&index| nodes[index].index());
+    while let Some(parent) = current.and_then(|node| parents.get(&node).copied()) {
+        let parent_node = gltf
+            .nodes()
+            .nth(parent)
+            .ok_or_else(|| GameError::GltfError(format!("missing node {parent}")))?;
+        root = Mat4::from_cols_array_2d(&parent_node.transform().matrix()) * root;
+        current = Some(parent);
+    }
+
+    Ok((Skeleton::new(joints, root), remap))
+}
+
+fn load_clip(
+    index: usize,
+    animation: &gltf::Animation,
+    joint_of_node: &HashMap<usize, usize>,
+    buffers: &[&[u8]],
+) -> Result<Arc<AnimationClip>> {
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let Some(&joint) = joint_of_node.get(&channel.target().node().index()) else {
+            continue;
+        };
+        let reader = channel.reader(|buffer| buffers.get(buffer.index()).copied());
+        let times = reader
+            .read_inputs()
+            .ok_or_else(|| GameError::GltfError("channel without keyframe times".to_string()))?
+            .collect::<Vec<_>>();
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+        let keyframes = match reader.read_outputs() {
+            Some(gltf::animation::util::ReadOutputs::Translations(values)) => {
+                Keyframes::Translation(values.map(Vec3::from).collect())
+            }
+            Some(gltf::animation::util::ReadOutputs::Rotations(values)) => {
+                Keyframes::Rotation(values.into_f32().map(Quat::from_array).collect())
+            }
+            Some(gltf::animation::util::ReadOutputs::Scales(values)) => {
+                Keyframes::Scale(values.map(Vec3::from).collect())
+            }
+            // Morph target weights aren't joint transforms.
+            Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(_)) | None => continue,
+        };
+        let values = match &keyframes {
+            Keyframes::Translation(values) | Keyframes::Scale(values) => values.len(),
+            Keyframes::Rotation(values) => values.len(),
+        };
+        check_keyframes(&times, values, interpolation.values_per_keyframe())?;
+
+        channels.push(Channel {
+            joint,
+            interpolation,
+            times,
+            keyframes,
+        });
+    }
+
+    let duration = channels
+        .iter()
+        .filter_map(|channel| channel.times.last().copied())
+        .fold(0.0, f32::max);
+    Ok(Arc::new(AnimationClip {
+        name: animation
+            .name()
+            .map_or_else(|| format!("animation_{index}"), str::to_string),
+        duration,
+        channels,
+    }))
+}
+
+/// Checks that a channel has keyframes and `values_per_keyframe` output values for each,
+/// which `clip::sample` indexes without checking.
+pub(crate) fn check_keyframes(
+    times: &[f32],
+    values: usize,
+    values_per_keyframe: usize,
+) -> Result<()> {
+    if times.is_empty() {
+        return Err(GameError::GltfError(
+            "channel without keyframes".to_string(),
+        ));
+    }
+    if values != times.len() * values_per_keyframe {
+        return Err(GameError::GltfError(format!(
+            "channel has {} keyframes but {} output values, expected {}",
+            times.len(),
+            values,
+            times.len() * values_per_keyframe
+        )));
+    }
+    Ok(())
+}
+
+/// Contents of every buffer of a `.glb`, which must all live in its binary chunk.
+pub(crate) fn glb_buffers(gltf: &gltf::Gltf) -> Result<Vec<&[u8]>> {
+    gltf.buffers()
+        .map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .as_deref()
+                .ok_or_else(|| GameError::GltfError("missing binary chunk".to_string())),
+            gltf::buffer::Source::Uri(uri) => Err(GameError::GltfError(format!(
+                "external buffer '{uri}' isn't supported, export as .glb"
+            ))),
+        })
+        .collect()
+}
+
+pub(crate) fn load_base_color(
+    primitive: &gltf::Primitive,
+    buffers: &[&[u8]],
+) -> Result<Option<image::RgbaImage>> {
+    let Some(info) = primitive
+        .material()
+        .pbr_metallic_roughness()
+        .base_color_texture()
+    else {
+        return Ok(None);
+    };
+
+    match info.texture().source().source() {
+        gltf::image::Source::View { view, .. } => {
+            let bytes = buffers
+                .get(view.buffer().index())
+                .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                .ok_or_else(|| {
+                    GameError::GltfError(format!(
+                        "base color image runs past the end of buffer {}",
+                        view.buffer().index()
+                    ))
+                })?;
+            Ok(Some(image::load_from_memory(bytes)?.to_rgba8()))
+        }
+        gltf::image::Source::Uri { uri, .. } => {
+            log::warn!("Skipping external base color texture '{}'", uri);
+            Ok(None)
+        }
+    }
+}
+
+pub(crate) fn gltf_error(err: gltf::Error) -> GameError {
+    GameError::GltfError(err.to_string())
+}
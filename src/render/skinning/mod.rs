@@ -0,0 +1,276 @@
+pub mod clip;
+pub mod import;
+pub mod skeleton;
+
+use std::sync::Arc;
+
+use glam::{Mat4, UVec4, Vec2, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::Result;
+
+use super::{
+    reflect, texture,
+    types::{self, VertexDescription},
+};
+
+pub use clip::{AnimationClip, Animator, Channel, ClipState, Interpolation, Keyframes};
+pub use import::{load_glb, SkinnedModel};
+pub use skeleton::{Joint, Pose, Skeleton, Transform};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, types::VertexDescription)]
+pub struct SkinnedVertex {
+    position: Vec3,
+    normal: Vec3,
+    tex_coords: Vec2,
+    /// Indices into the skeleton's joints.
+    joints: UVec4,
+    /// Sum to 1.
+    weights: Vec4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkinnedMeshId(usize);
+
+/// GPU resources of one skinned mesh. Its joint matrices stay as they are until the next
+/// `set_pose`.
+#[derive(Debug)]
+pub struct SkinnedMesh {
+    skeleton: Arc<Skeleton>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    joint_buffer: wgpu::Buffer,
+    joint_bind_group: wgpu::BindGroup,
+    texture: texture::Texture,
+    texture_bind_group: wgpu::BindGroup,
+    pub visible: bool,
+}
+
+impl SkinnedMesh {
+    pub fn skeleton(&self) -> &Arc<Skeleton> {
+        &self.skeleton
+    }
+
+    pub fn texture(&self) -> &texture::Texture {
+        &self.texture
+    }
+
+    /// Uploads the joint matrices for `pose`, with the mesh placed in the world by
+    /// `transform`.
+    pub fn set_pose(&self, queue: &wgpu::Queue, pose: &Pose, transform: Mat4) {
+        let matrices = pose.joint_matrices(&self.skeleton, transform);
+        queue.write_buffer(&self.joint_buffer, 0, bytemuck::cast_slice(&matrices));
+    }
+}
+
+/// Draws skinned meshes in the main pass, deforming them in the vertex shader with joint
+/// matrices read from a storage buffer.
+///
+/// Vertex shader storage buffers aren't available everywhere (WebGL2 in particular), so
+/// check `is_supported` first.
+#[derive(Debug)]
+pub struct SkinnedRenderer {
+    pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    joint_layout: wgpu::BindGroupLayout,
+    meshes: Vec<SkinnedMesh>,
+}
+
+impl SkinnedRenderer {
+    pub fn is_supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+            && device.limits().max_storage_buffers_per_shader_stage > 0
+    }
+
+    /// `camera_bind_group_layout` and `lighting_bind_group_layout` must match the layouts
+    /// of `@group(1)` and `@group(3)` in `skinning.wgsl`.
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader_source = concat!(
+            include_str!("../../lighting.wgsl"),
+            include_str!("../../skinning.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers("vs_main", &[SkinnedVertex::desc()])?;
+
+        let texture_layout = reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("skinned_texture_bind_group_layout"),
+        )?;
+        let joint_layout =
+            reflection.create_bind_group_layout(device, 2, Some("joint_bind_group_layout"))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Skinning Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skinning Pipeline Layout"),
+            bind_group_layouts: &[
+                &texture_layout,
+                camera_bind_group_layout,
+                &joint_layout,
+                lighting_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Skinning Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[SkinnedVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Ok(Self {
+            pipeline,
+            texture_layout,
+            joint_layout,
+            meshes: Vec::new(),
+        })
+    }
+
+    /// Uploads `model`, posed at rest at the origin. Models without an embedded base
+    /// color texture are drawn white.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        model: &SkinnedModel,
+    ) -> Result<SkinnedMeshId> {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinned Vertex Buffer"),
+            contents: bytemuck::cast_slice(&model.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinned Index Buffer"),
+            contents: bytemuck::cast_slice(&model.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let rest = model
+            .skeleton
+            .rest_pose()
+            .joint_matrices(&model.skeleton, Mat4::IDENTITY);
+        // Bindings can't be empty, so a skeleton without joints still gets one matrix.
+        let contents = if rest.is_empty() {
+            vec![Mat4::IDENTITY]
+        } else {
+            rest
+        };
+        let joint_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Joint Buffer"),
+            contents: bytemuck::cast_slice(&contents),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let joint_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.joint_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: joint_buffer.as_entire_binding(),
+            }],
+            label: Some("joint_bind_group"),
+        });
+
+        let image = match &model.base_color {
+            Some(image) => image::DynamicImage::ImageRgba8(image.clone()),
+            None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255; 4]),
+            )),
+        };
+        let texture =
+            texture::Texture::from_image(device, queue, &image, Some("skinned_base_color"))?;
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("skinned_texture_bind_group"),
+        });
+
+        self.meshes.push(SkinnedMesh {
+            skeleton: model.skeleton.clone(),
+            vertex_buffer,
+            index_buffer,
+            index_count: model.indices.len() as u32,
+            joint_buffer,
+            joint_bind_group,
+            texture,
+            texture_bind_group,
+            visible: true,
+        });
+        Ok(SkinnedMeshId(self.meshes.len() - 1))
+    }
+
+    pub fn mesh(&mut self, mesh: SkinnedMeshId) -> &mut SkinnedMesh {
+        &mut self.meshes[mesh.0]
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.meshes.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(3, lighting_bind_group, &[]);
+
+        for mesh in self.meshes.iter().filter(|mesh| mesh.visible) {
+            render_pass.set_bind_group(0, &mesh.texture_bind_group, &[]);
+            render_pass.set_bind_group(2, &mesh.joint_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use glam::{Mat4, Quat, Vec3};
+
+/// A joint's local translation, rotation and scale relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Interpolates towards `other`, spherically for the rotation.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    /// Index of the parent joint, always lower than this joint's own index.
+    pub parent: Option<usize>,
+    /// Local transform when no animation is applied.
+    pub rest: Transform,
+    /// Takes mesh space to this joint's space at bind time.
+    pub inverse_bind: Mat4,
+}
+
+/// A joint hierarchy, ordered so parents come before their children.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    joints: Vec<Joint>,
+    /// Transform of whatever non-joint nodes sit above the root joints.
+    root: Mat4,
+}
+
+impl Skeleton {
+    /// `joints` must list every parent before its children.
+    pub fn new(joints: Vec<Joint>, root: Mat4) -> Self {
+        debug_assert!(joints
+            .iter()
+            .enumerate()
+            .all(|(index, joint)| joint.parent.is_none_or(|parent| parent < index)));
+        Self { joints, root }
+    }
+
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            locals: self.joints.iter().map(|joint| joint.rest).collect(),
+        }
+    }
+}
+
+/// Local transforms for every joint of a skeleton, in the skeleton's joint order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    pub locals: Vec<Transform>,
+}
+
+impl Pose {
+    /// Interpolates every joint towards `other` by `weight`, 0 keeping this pose.
+    pub fn blend(&self, other: &Pose, weight: f32) -> Pose {
+        Pose {
+            locals: self
+                .locals
+                .iter()
+                .zip(&other.locals)
+                .map(|(a, b)| a.lerp(b, weight))
+                .collect(),
+        }
+    }
+
+    /// Mesh-space transforms of every joint.
+    pub fn global_transforms(&self, skeleton: &Skeleton) -> Vec<Mat4> {
+        let mut globals: Vec<Mat4> = Vec::with_capacity(self.locals.len());
+        for (local, joint) in self.locals.iter().zip(skeleton.joints()) {
+            let parent = joint.parent.map_or(skeleton.root, |parent| globals[parent]);
+            globals.push(parent * local.to_matrix());
+        }
+        globals
+    }
+
+    /// The matrices the skinning shader expects: each joint's global transform times its
+    /// inverse bind matrix, premultiplied by `transform` to place the mesh in the world.
+    pub fn joint_matrices(&self, skeleton: &Skeleton, transform: Mat4) -> Vec<Mat4> {
+        self.global_transforms(skeleton)
+            .into_iter()
+            .zip(skeleton.joints())
+            .map(|(global, joint)| transform * global * joint.inverse_bind)
+            .collect()
+    }
+}
@@ -0,0 +1,624 @@
+//! Frame-based sprite animations, loaded from this crate's JSON format or from
+//! Aseprite's JSON export.
+//!
+//! The native format names atlas regions per frame, with durations in seconds:
+//!
+//! ```json
+//! {
+//!   "animations": {
+//!     "walk": {
+//!       "mode": "loop",
+//!       "repeat": 3,
+//!       "frames": [
+//!         { "region": "hero/walk_0", "duration": 0.1, "events": ["footstep"] },
+//!         { "region": "hero/walk_1", "duration": 0.1 }
+//!       ]
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+
+use glam::Vec2;
+use serde::Deserialize;
+
+use crate::{render::atlas::TextureAtlas, GameError, Result};
+
+use super::{Sprite, TextureId};
+
+/// Frames shorter than this are stretched to it, so a zero duration can't stall
+/// `AnimationPlayer::update`.
+const MIN_FRAME_DURATION: f32 = 0.001;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    #[default]
+    Loop,
+    /// Plays forwards then backwards, without repeating the end frames.
+    PingPong,
+    /// Stops on the last frame, ignoring `Animation::repeat`.
+    Once,
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub texture: TextureId,
+    /// Normalized texture region, `[min_u, min_v, max_u, max_v]`.
+    pub uv: [f32; 4],
+    /// Seconds.
+    pub duration: f32,
+    /// Reported by `AnimationPlayer::update` when the frame is shown.
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    pub mode: PlayMode,
+    /// Passes to play before stopping, `None` to play forever. A `PlayMode::PingPong`
+    /// pass is one sweep in either direction, as in Aseprite, so 1 plays forwards once
+    /// and 2 plays there and back.
+    pub repeat: Option<u32>,
+}
+
+impl Animation {
+    /// Seconds one pass through the frames takes.
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+}
+
+/// Named animations, shared between players with `Arc`.
+#[derive(Debug, Clone, Default)]
+pub struct AnimationSet {
+    animations: HashMap<String, Arc<Animation>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetDef {
+    animations: HashMap<String, AnimationDef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnimationDef {
+    frames: Vec<FrameDef>,
+    #[serde(default)]
+    mode: PlayMode,
+    #[serde(default)]
+    repeat: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FrameDef {
+    region: String,
+    duration: f32,
+    #[serde(default)]
+    events: Vec<String>,
+}
+
+impl AnimationSet {
+    /// Parses the native format, looking frame regions up in `atlas`.
+    pub fn from_json(json: &str, atlas: &TextureAtlas) -> Result<Self> {
+        let set: SetDef = serde_json::from_str(json).map_err(animation_error)?;
+
+        let mut animations = HashMap::new();
+        for (name, animation) in set.animations {
+            let frames = animation
+                .frames
+                .into_iter()
+                .map(|frame| {
+                    let region = atlas.region(&frame.region).ok_or_else(|| {
+                        GameError::AnimationError(format!(
+                            "'{}' uses missing atlas region '{}'",
+                            name, frame.region
+                        ))
+                    })?;
+                    Ok(Frame {
+                        texture: atlas.page(region.page),
+                        uv: region.uv,
+                        duration: frame.duration,
+                        events: frame.events,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            animations.insert(
+                name,
+                Arc::new(Animation {
+                    frames,
+                    mode: animation.mode,
+                    repeat: animation.repeat,
+                }),
+            );
+        }
+
+        Ok(Self { animations })
+    }
+
+    /// Parses an Aseprite sheet export (hash or array frames, with frame tags) for the
+    /// sheet image uploaded as `texture`.
+    ///
+    /// Every tag becomes an animation, or the whole sheet becomes `"default"` if there
+    /// are none. A tag's repeat count becomes `Animation::repeat`, so tags without one
+    /// loop forever. Aseprite has no frame events; add them to `Frame::events` after
+    /// loading.
+    pub fn from_aseprite(json: &str, texture: TextureId) -> Result<Self> {
+        let sheet: aseprite::Sheet = serde_json::from_str(json).map_err(animation_error)?;
+        let size = Vec2::new(sheet.meta.size.w as f32, sheet.meta.size.h as f32);
+        let frames = sheet.frames.into_vec();
+
+        let frame = |index: usize| {
+            let aseprite::Frame { frame, duration } = frames.get(index).ok_or_else(|| {
+                GameError::AnimationError(format!("tag uses missing frame {index}"))
+            })?;
+            Ok(Frame {
+                texture,
+                uv: [
+                    frame.x as f32 / size.x,
+                    frame.y as f32 / size.y,
+                    (frame.x + frame.w) as f32 / size.x,
+                    (frame.y + frame.h) as f32 / size.y,
+                ],
+                duration: *duration as f32 / 1000.0,
+                events: Vec::new(),
+            })
+        };
+
+        let mut animations = HashMap::new();
+        if sheet.meta.frame_tags.is_empty() {
+            animations.insert(
+                "default".to_string(),
+                Arc::new(Animation {
+                    frames: (0..frames.len()).map(frame).collect::<Result<_>>()?,
+                    mode: PlayMode::Loop,
+                    repeat: None,
+                }),
+            );
+        }
+        for tag in sheet.meta.frame_tags {
+            let mut tag_frames = (tag.from..=tag.to).map(frame).collect::<Result<Vec<_>>>()?;
+            // Aseprite writes 0 or leaves the count out to repeat forever.
+            let repeat = match tag.repeat.as_deref().map(str::parse::<u32>) {
+                None | Some(Ok(0)) => None,
+                Some(Ok(repeat)) => Some(repeat),
+                Some(Err(_)) => {
+                    return Err(GameError::AnimationError(format!(
+                        "tag '{}' has invalid repeat count '{}'",
+                        tag.name,
+                        tag.repeat.unwrap_or_default()
+                    )))
+                }
+            };
+            let mode = match tag.direction.as_str() {
+                "forward" | "reverse" => PlayMode::Loop,
+                "pingpong" | "pingpong_reverse" => PlayMode::PingPong,
+                direction => {
+                    return Err(GameError::AnimationError(format!(
+                        "tag '{}' has unknown direction '{}'",
+                        tag.name, direction
+                    )))
+                }
+            };
+            if tag.direction.ends_with("reverse") {
+                tag_frames.reverse();
+            }
+            animations.insert(
+                tag.name,
+                Arc::new(Animation {
+                    frames: tag_frames,
+                    mode,
+                    repeat,
+                }),
+            );
+        }
+
+        Ok(Self { animations })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Animation>> {
+        self.animations.get(name).cloned()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, animation: Animation) {
+        self.animations.insert(name.into(), Arc::new(animation));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.animations.keys().map(String::as_str)
+    }
+}
+
+/// Plays one animation at a time. Advance it with `update` once per game update, using
+/// the same `delta`.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    animation: Arc<Animation>,
+    frame: usize,
+    /// Seconds spent on the current frame.
+    elapsed: f32,
+    forward: bool,
+    /// Passes completed, see `Animation::repeat`.
+    passes: u32,
+    finished: bool,
+    /// Whether the current frame's events still have to be reported.
+    entered: bool,
+    events: Vec<String>,
+    /// Playback rate, 1 is normal speed.
+    pub speed: f32,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation: Arc<Animation>) -> Self {
+        Self {
+            animation,
+            frame: 0,
+            elapsed: 0.0,
+            forward: true,
+            passes: 0,
+            finished: false,
+            entered: true,
+            events: Vec::new(),
+            speed: 1.0,
+        }
+    }
+
+    /// Switches to `animation` from its first frame. Does nothing if it's already
+    /// playing, so it can be called every update.
+    pub fn play(&mut self, animation: &Arc<Animation>) {
+        if !Arc::ptr_eq(&self.animation, animation) {
+            self.animation = animation.clone();
+            self.restart();
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.forward = true;
+        self.passes = 0;
+        self.finished = false;
+        self.entered = true;
+    }
+
+    pub fn animation(&self) -> &Arc<Animation> {
+        &self.animation
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.frame
+    }
+
+    pub fn frame(&self) -> Option<&Frame> {
+        self.animation.frames.get(self.frame)
+    }
+
+    /// Whether a `PlayMode::Once` animation, or one with `Animation::repeat` set, reached
+    /// the end of its last pass.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Advances by `delta` seconds and returns the events of every frame shown since the
+    /// last update, in order.
+    pub fn update(&mut self, delta: f64) -> &[String] {
+        self.events.clear();
+        let count = self.animation.frames.len();
+        if count == 0 {
+            return &self.events;
+        }
+
+        if self.entered {
+            self.enter_frame();
+        }
+        if self.finished {
+            return &self.events;
+        }
+
+        self.elapsed += delta as f32 * self.speed;
+        loop {
+            let duration = self.animation.frames[self.frame]
+                .duration
+                .max(MIN_FRAME_DURATION);
+            if self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+
+            let pass_end = match self.animation.mode {
+                PlayMode::Loop | PlayMode::Once => self.frame + 1 == count,
+                PlayMode::PingPong if self.forward => self.frame + 1 == count,
+                PlayMode::PingPong => self.frame == 0,
+            };
+            if pass_end {
+                self.passes += 1;
+                let repeat = match self.animation.mode {
+                    PlayMode::Once => Some(1),
+                    _ => self.animation.repeat,
+                };
+                if repeat.is_some_and(|repeat| self.passes >= repeat) {
+                    self.finished = true;
+                    self.elapsed = duration;
+                    break;
+                }
+            }
+
+            match self.animation.mode {
+                PlayMode::Loop | PlayMode::Once => self.frame = (self.frame + 1) % count,
+                PlayMode::PingPong if count == 1 => (),
+                PlayMode::PingPong => {
+                    if pass_end {
+                        self.forward = !self.forward;
+                    }
+                    if self.forward {
+                        self.frame += 1;
+                    } else {
+                        self.frame -= 1;
+                    }
+                }
+            }
+            self.enter_frame();
+        }
+
+        &self.events
+    }
+
+    /// Points `sprite` at the current frame.
+    pub fn apply(&self, sprite: &mut Sprite) {
+        if let Some(frame) = self.frame() {
+            sprite.texture = frame.texture;
+            sprite.uv = frame.uv;
+        }
+    }
+
+    fn enter_frame(&mut self) {
+        self.entered = false;
+        self.events
+            .extend(self.animation.frames[self.frame].events.iter().cloned());
+    }
+}
+
+fn animation_error(err: serde_json::Error) -> GameError {
+    GameError::AnimationError(err.to_string())
+}
+
+/// The parts of Aseprite's "Export Sprite Sheet" JSON that animations need.
+mod aseprite {
+    use std::fmt;
+
+    use serde::{
+        de::{MapAccess, Visitor},
+        Deserialize, Deserializer,
+    };
+
+    #[derive(Debug, Deserialize)]
+    pub struct Sheet {
+        pub frames: Frames,
+        pub meta: Meta,
+    }
+
+    /// "Array" exports list frames in order; "Hash" exports key them by filename in
+    /// document order, which a map would lose.
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    pub enum Frames {
+        Array(Vec<Frame>),
+        Hash(OrderedFrames),
+    }
+
+    impl Frames {
+        pub fn into_vec(self) -> Vec<Frame> {
+            match self {
+                Frames::Array(frames) => frames,
+                Frames::Hash(OrderedFrames(frames)) => frames,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct OrderedFrames(Vec<Frame>);
+
+    impl<'de> Deserialize<'de> for OrderedFrames {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct FramesVisitor;
+
+            impl<'de> Visitor<'de> for FramesVisitor {
+                type Value = OrderedFrames;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("a map of frame names to frames")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                    let mut frames = Vec::new();
+                    while let Some((_, frame)) = map.next_entry::<String, Frame>()? {
+                        frames.push(frame);
+                    }
+                    Ok(OrderedFrames(frames))
+                }
+            }
+
+            deserializer.deserialize_map(FramesVisitor)
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Frame {
+        pub frame: Rect,
+        /// Milliseconds.
+        pub duration: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Rect {
+        pub x: u32,
+        pub y: u32,
+        pub w: u32,
+        pub h: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Size {
+        pub w: u32,
+        pub h: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Meta {
+        pub size: Size,
+        #[serde(default)]
+        pub frame_tags: Vec<Tag>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Tag {
+        pub name: String,
+        pub from: usize,
+        pub to: usize,
+        #[serde(default = "forward")]
+        pub direction: String,
+        /// A count as a string, missing for infinite.
+        pub repeat: Option<String>,
+    }
+
+    fn forward() -> String {
+        "forward".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::atlas::{AtlasManifest, AtlasRegion};
+
+    fn sheet(tags: &str) -> String {
+        format!(
+            r#"{{
+                "frames": [
+                    {{ "frame": {{ "x": 0, "y": 0, "w": 8, "h": 8 }}, "duration": 100 }},
+                    {{ "frame": {{ "x": 8, "y": 0, "w": 8, "h": 8 }}, "duration": 100 }},
+                    {{ "frame": {{ "x": 16, "y": 0, "w": 8, "h": 8 }}, "duration": 100 }}
+                ],
+                "meta": {{ "size": {{ "w": 24, "h": 8 }}, "frameTags": [{tags}] }}
+            }}"#
+        )
+    }
+
+    /// Frame indices shown over `steps` updates of 0.1 seconds.
+    fn play(animation: Arc<Animation>, steps: usize) -> (Vec<usize>, bool) {
+        let mut player = AnimationPlayer::new(animation);
+        let frames = (0..steps)
+            .map(|_| {
+                player.update(0.1);
+                player.frame_index()
+            })
+            .collect();
+        (frames, player.finished())
+    }
+
+    #[test]
+    fn native_format() {
+        let region = |page, uv| AtlasRegion {
+            page,
+            uv,
+            size: [8, 8],
+        };
+        let manifest = AtlasManifest {
+            pages: 2,
+            regions: [
+                ("hero/walk_0".to_string(), region(0, [0.0, 0.0, 0.5, 1.0])),
+                ("hero/walk_1".to_string(), region(1, [0.5, 0.0, 1.0, 1.0])),
+            ]
+            .into(),
+        };
+        let atlas = TextureAtlas::from_pages(vec![TextureId(3), TextureId(4)], manifest);
+        let json = r#"{
+            "animations": {
+                "walk": {
+                    "mode": "once",
+                    "frames": [
+                        { "region": "hero/walk_0", "duration": 0.1, "events": ["footstep"] },
+                        { "region": "hero/walk_1", "duration": 0.2 }
+                    ]
+                },
+                "idle": { "frames": [{ "region": "hero/walk_0", "duration": 1 }] }
+            }
+        }"#;
+        let set = AnimationSet::from_json(json, &atlas).unwrap();
+
+        let walk = set.get("walk").unwrap();
+        assert_eq!((walk.mode, walk.repeat), (PlayMode::Once, None));
+        assert_eq!(walk.frames[1].texture, TextureId(4));
+        assert_eq!(walk.frames[1].uv, [0.5, 0.0, 1.0, 1.0]);
+        assert_eq!(walk.frames[0].events, ["footstep"]);
+        assert!((walk.duration() - 0.3).abs() < 1e-6);
+        assert_eq!(set.get("idle").unwrap().mode, PlayMode::Loop);
+
+        let missing = json.replace("hero/walk_1", "hero/run_0");
+        assert!(AnimationSet::from_json(&missing, &atlas).is_err());
+    }
+
+    #[test]
+    fn frame_events() {
+        let frame = |events: &[&str]| Frame {
+            texture: TextureId(0),
+            uv: [0.0; 4],
+            duration: 0.1,
+            events: events.iter().map(|event| event.to_string()).collect(),
+        };
+        let mut player = AnimationPlayer::new(Arc::new(Animation {
+            frames: vec![frame(&["step"]), frame(&[]), frame(&["land", "dust"])],
+            mode: PlayMode::Loop,
+            repeat: None,
+        }));
+
+        // The first frame's events come with the first update.
+        assert_eq!(player.update(0.05), ["step"]);
+        assert!(player.update(0.0).is_empty());
+        // Frames skipped within one update still report theirs, in order.
+        assert_eq!(player.update(0.2), ["land", "dust"]);
+        assert_eq!(player.update(0.1), ["step"]);
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let set = AnimationSet::from_aseprite(&sheet(""), TextureId(0)).unwrap();
+        let mut animation = (*set.get("default").unwrap()).clone();
+        animation.mode = PlayMode::Once;
+        // `repeat` doesn't apply to `Once`.
+        animation.repeat = Some(3);
+        assert_eq!(play(Arc::new(animation), 5), (vec![1, 2, 2, 2, 2], true));
+    }
+
+    #[test]
+    fn aseprite_repeat_counts() {
+        let set = AnimationSet::from_aseprite(
+            &sheet(
+                r#"{ "name": "spin", "from": 0, "to": 2, "direction": "forward", "repeat": "2" },
+                   { "name": "bounce", "from": 0, "to": 2, "direction": "pingpong", "repeat": "1" },
+                   { "name": "idle", "from": 0, "to": 2, "direction": "pingpong" }"#,
+            ),
+            TextureId(0),
+        )
+        .unwrap();
+
+        let spin = set.get("spin").unwrap();
+        assert_eq!((spin.mode, spin.repeat), (PlayMode::Loop, Some(2)));
+        assert_eq!(play(spin, 8), (vec![1, 2, 0, 1, 2, 2, 2, 2], true));
+
+        // One ping-pong pass only goes forwards.
+        let bounce = set.get("bounce").unwrap();
+        assert_eq!(play(bounce, 4), (vec![1, 2, 2, 2], true));
+
+        let idle = set.get("idle").unwrap();
+        assert_eq!(idle.repeat, None);
+        assert_eq!(play(idle, 6), (vec![1, 2, 1, 0, 1, 2], false));
+    }
+
+    #[test]
+    fn aseprite_rejects_bad_repeat() {
+        let json = sheet(r#"{ "name": "a", "from": 0, "to": 1, "repeat": "twice" }"#);
+        assert!(AnimationSet::from_aseprite(&json, TextureId(0)).is_err());
+    }
+}
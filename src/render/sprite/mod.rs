@@ -0,0 +1,492 @@
+pub mod animation;
+
+use std::ops::Range;
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::Result;
+
+use super::{
+    camera::OrthoCamera,
+    reflect,
+    text::screen_projection,
+    texture,
+    types::{self, CameraUniform, VertexDescription},
+    uniform,
+};
+
+const INITIAL_CAPACITY: u64 = 6 * 1024;
+/// The whole texture, `[min_u, min_v, max_u, max_v]`.
+pub const FULL_UV: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureId(usize);
+
+/// Coordinate space a sprite is positioned in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Space {
+    /// World units of the batch's `OrthoCamera`, +Y up.
+    #[default]
+    World,
+    /// Pixels from the top-left of the window, +Y down. Drawn over all world sprites.
+    Screen,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, types::VertexDescription)]
+pub struct SpriteVertex {
+    position: Vec3,
+    tex_coords: Vec2,
+    color: [f32; 4],
+}
+
+/// A textured quad. Build one with `Sprite::new` and struct update syntax for the rest.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    pub texture: TextureId,
+    pub position: Vec2,
+    /// Radians, turning +X towards +Y of the sprite's space.
+    pub rotation: f32,
+    /// Multiplies `size`. A negative component mirrors the sprite on that axis.
+    pub scale: Vec2,
+    /// Size before scaling. `None` uses the size of `uv` in texels.
+    pub size: Option<Vec2>,
+    /// Point placed at `position` and rotated around, from (0, 0) at the top-left of the
+    /// image to (1, 1) at the bottom-right.
+    pub anchor: Vec2,
+    /// Multiplied with the texture color.
+    pub color: Vec4,
+    /// Normalized texture region, `[min_u, min_v, max_u, max_v]`.
+    pub uv: [f32; 4],
+    /// Draw order within a space: higher values are drawn over lower ones.
+    pub z: f32,
+    pub space: Space,
+}
+
+impl Sprite {
+    pub fn new(texture: TextureId, position: Vec2) -> Self {
+        Self {
+            texture,
+            position,
+            rotation: 0.0,
+            scale: Vec2::ONE,
+            size: None,
+            anchor: Vec2::splat(0.5),
+            color: Vec4::ONE,
+            uv: FULL_UV,
+            z: 0.0,
+            space: Space::World,
+        }
+    }
+}
+
+/// A texture region split into a 3x3 grid for resizable panels: corners keep their
+/// size, edges stretch along one axis and the centre along both.
+#[derive(Debug, Clone, Copy)]
+pub struct NineSlice {
+    pub texture: TextureId,
+    /// Normalized texture region, `[min_u, min_v, max_u, max_v]`.
+    pub uv: [f32; 4],
+    /// Border widths in texels of the region: left, top, right, bottom.
+    pub borders: [f32; 4],
+    /// Size of one border texel when drawn.
+    pub border_scale: f32,
+}
+
+impl NineSlice {
+    pub fn new(texture: TextureId, borders: [f32; 4]) -> Self {
+        Self {
+            texture,
+            uv: FULL_UV,
+            borders,
+            border_scale: 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Quad {
+    texture: TextureId,
+    space: Space,
+    z: f32,
+    /// Corners in the sprite's space: top-left, top-right, bottom-left, bottom-right of
+    /// the image.
+    corners: [Vec2; 4],
+    uv: [f32; 4],
+    color: [f32; 4],
+}
+
+#[derive(Debug)]
+struct Batch {
+    texture: TextureId,
+    space: Space,
+    vertices: Range<u32>,
+}
+
+#[derive(Debug)]
+struct TextureEntry {
+    texture: texture::Texture,
+    bind_group: wgpu::BindGroup,
+    size: Vec2,
+}
+
+/// Draws sprites queued with `push`/`push_nine_slice` since the last frame.
+///
+/// Sprites are sorted by space and `z`, then by texture, and consecutive sprites sharing
+/// a texture go out in one draw call. Sprites with equal `z` overlap in no particular
+/// order.
+#[derive(Debug)]
+pub struct SpriteBatch {
+    textures: Vec<TextureEntry>,
+    texture_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    camera: OrthoCamera,
+    world_camera: uniform::UniformBuffer<CameraUniform>,
+    screen_camera: uniform::UniformBuffer<CameraUniform>,
+    size: (u32, u32),
+    quads: Vec<Quad>,
+    vertices: Vec<SpriteVertex>,
+    drawn: Vec<Batch>,
+    vertex_buffer: wgpu::Buffer,
+    capacity: u64,
+}
+
+impl SpriteBatch {
+    /// `camera_bind_group_layout` must match the layout of `@group(1)` in `sprite.wgsl`.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader_source = include_str!("../../sprite.wgsl");
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers("vs_main", &[SpriteVertex::desc()])?;
+
+        let texture_layout = reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("sprite_texture_bind_group_layout"),
+        )?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[&texture_layout, camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Ordering comes from sorting, so depth is neither tested nor written. The depth
+        // state only has to match the pass the batch is drawn in.
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sprite Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[SpriteVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let camera = OrthoCamera::default();
+        let mut world_uniform = CameraUniform::new();
+        world_uniform
+            .update_view_proj(camera.build_view_projection_matrix(config.width, config.height));
+        let world_camera = uniform::UniformBuffer::new(
+            device,
+            camera_bind_group_layout,
+            world_uniform,
+            "sprite_world_camera",
+        );
+        let mut screen_uniform = CameraUniform::new();
+        screen_uniform.update_view_proj(screen_projection(config.width, config.height));
+        let screen_camera = uniform::UniformBuffer::new(
+            device,
+            camera_bind_group_layout,
+            screen_uniform,
+            "sprite_screen_camera",
+        );
+
+        Ok(Self {
+            textures: Vec::new(),
+            texture_layout,
+            pipeline,
+            camera,
+            world_camera,
+            screen_camera,
+            size: (config.width, config.height),
+            quads: Vec::new(),
+            vertices: Vec::new(),
+            drawn: Vec::new(),
+            vertex_buffer: create_vertex_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+        })
+    }
+
+    /// Takes ownership of `texture` so sprites can reference it by id.
+    pub fn add_texture(&mut self, device: &wgpu::Device, texture: texture::Texture) -> TextureId {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("sprite_texture_bind_group"),
+        });
+        let size = texture.texture.size();
+        self.textures.push(TextureEntry {
+            texture,
+            bind_group,
+            size: Vec2::new(size.width as f32, size.height as f32),
+        });
+        TextureId(self.textures.len() - 1)
+    }
+
+    pub fn texture(&self, texture: TextureId) -> &texture::Texture {
+        &self.textures[texture.0].texture
+    }
+
+    /// Size of `texture` in texels.
+    pub fn texture_size(&self, texture: TextureId) -> Vec2 {
+        self.textures[texture.0].size
+    }
+
+    /// Camera for `Space::World` sprites.
+    pub fn camera(&mut self) -> &mut OrthoCamera {
+        &mut self.camera
+    }
+
+    /// Draw calls the last prepared frame took.
+    pub fn draw_calls(&self) -> usize {
+        self.drawn.len()
+    }
+
+    pub fn push(&mut self, sprite: &Sprite) {
+        let [min_u, min_v, max_u, max_v] = sprite.uv;
+        let size = sprite.size.unwrap_or_else(|| {
+            self.texture_size(sprite.texture) * Vec2::new(max_u - min_u, max_v - min_v).abs()
+        }) * sprite.scale;
+        let rotation = Vec2::from_angle(sprite.rotation);
+
+        let corner = |x: f32, y: f32| {
+            let local = to_space(sprite.space, (Vec2::new(x, y) - sprite.anchor) * size);
+            sprite.position + rotation.rotate(local)
+        };
+        self.quads.push(Quad {
+            texture: sprite.texture,
+            space: sprite.space,
+            z: sprite.z,
+            corners: [
+                corner(0.0, 0.0),
+                corner(1.0, 0.0),
+                corner(0.0, 1.0),
+                corner(1.0, 1.0),
+            ],
+            uv: sprite.uv,
+            color: sprite.color.to_array(),
+        });
+    }
+
+    /// Draws `slice` stretched over a `size` rectangle with its top-left corner at
+    /// `position`. Borders shrink evenly when `size` is too small to fit them.
+    pub fn push_nine_slice(
+        &mut self,
+        slice: &NineSlice,
+        position: Vec2,
+        size: Vec2,
+        color: Vec4,
+        z: f32,
+        space: Space,
+    ) {
+        let [min_u, min_v, max_u, max_v] = slice.uv;
+        let region = self.texture_size(slice.texture) * Vec2::new(max_u - min_u, max_v - min_v);
+        let [left, top, right, bottom] = slice.borders;
+
+        let fit = |start: f32, end: f32, available: f32| {
+            let total = (start + end) * slice.border_scale;
+            let shrink = if total > available {
+                available / total
+            } else {
+                1.0
+            };
+            (
+                start * slice.border_scale * shrink,
+                end * slice.border_scale * shrink,
+            )
+        };
+        let (left_size, right_size) = fit(left, right, size.x);
+        let (top_size, bottom_size) = fit(top, bottom, size.y);
+
+        // Cut lines across the drawn rectangle and the texture region.
+        let xs = [0.0, left_size, size.x - right_size, size.x];
+        let ys = [0.0, top_size, size.y - bottom_size, size.y];
+        let us = [
+            min_u,
+            min_u + (max_u - min_u) * left / region.x,
+            max_u - (max_u - min_u) * right / region.x,
+            max_u,
+        ];
+        let vs = [
+            min_v,
+            min_v + (max_v - min_v) * top / region.y,
+            max_v - (max_v - min_v) * bottom / region.y,
+            max_v,
+        ];
+
+        for row in 0..3 {
+            for column in 0..3 {
+                if xs[column + 1] <= xs[column] || ys[row + 1] <= ys[row] {
+                    continue;
+                }
+                let corner = |x: f32, y: f32| position + to_space(space, Vec2::new(x, y));
+                self.quads.push(Quad {
+                    texture: slice.texture,
+                    space,
+                    z,
+                    corners: [
+                        corner(xs[column], ys[row]),
+                        corner(xs[column + 1], ys[row]),
+                        corner(xs[column], ys[row + 1]),
+                        corner(xs[column + 1], ys[row + 1]),
+                    ],
+                    uv: [us[column], vs[row], us[column + 1], vs[row + 1]],
+                    color: color.to_array(),
+                });
+            }
+        }
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.size = (width, height);
+        self.screen_camera.update(queue, |uniform| {
+            uniform.update_view_proj(screen_projection(width, height))
+        });
+    }
+
+    /// Sorts and batches this frame's sprites, uploads them and the camera, and clears
+    /// the queue for the next frame. Call before recording the pass `draw` is used in.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let view_proj = self
+            .camera
+            .build_view_projection_matrix(self.size.0, self.size.1);
+        self.world_camera
+            .update(queue, |uniform| uniform.update_view_proj(view_proj));
+
+        // Stable, so sprites that tie on every key keep their submission order.
+        self.quads.sort_by(|a, b| {
+            a.space
+                .cmp(&b.space)
+                .then(a.z.total_cmp(&b.z))
+                .then(a.texture.cmp(&b.texture))
+        });
+
+        self.drawn.clear();
+        for quad in self.quads.drain(..) {
+            let [min_u, min_v, max_u, max_v] = quad.uv;
+            let vertex = |corner: usize, u: f32, v: f32| SpriteVertex {
+                position: quad.corners[corner].extend(0.0),
+                tex_coords: Vec2::new(u, v),
+                color: quad.color,
+            };
+            let top_left = vertex(0, min_u, min_v);
+            let top_right = vertex(1, max_u, min_v);
+            let bottom_left = vertex(2, min_u, max_v);
+            let bottom_right = vertex(3, max_u, max_v);
+
+            let start = self.vertices.len() as u32;
+            self.vertices.extend([
+                top_left,
+                bottom_left,
+                top_right,
+                top_right,
+                bottom_left,
+                bottom_right,
+            ]);
+            let end = self.vertices.len() as u32;
+
+            match self.drawn.last_mut() {
+                Some(batch) if batch.texture == quad.texture && batch.space == quad.space => {
+                    batch.vertices.end = end
+                }
+                _ => self.drawn.push(Batch {
+                    texture: quad.texture,
+                    space: quad.space,
+                    vertices: start..end,
+                }),
+            }
+        }
+
+        let count = self.vertices.len() as u64;
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.vertices.clear();
+    }
+
+    /// Records the sprites prepared this frame into a pass with the scene's depth buffer
+    /// attached.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.drawn.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        for batch in self.drawn.iter() {
+            let camera = match batch.space {
+                Space::World => self.world_camera.bind_group(),
+                Space::Screen => self.screen_camera.bind_group(),
+            };
+            render_pass.set_bind_group(0, &self.textures[batch.texture.0].bind_group, &[]);
+            render_pass.set_bind_group(1, camera, &[]);
+            render_pass.draw(batch.vertices.clone(), 0..1);
+        }
+    }
+}
+
+/// Turns an offset in image orientation (+Y down) into `space`.
+fn to_space(space: Space, offset: Vec2) -> Vec2 {
+    match space {
+        Space::World => Vec2::new(offset.x, -offset.y),
+        Space::Screen => offset,
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Sprite Vertex Buffer"),
+        size: capacity * std::mem::size_of::<SpriteVertex>() as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
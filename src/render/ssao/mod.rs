@@ -0,0 +1,279 @@
+use super::{camera, reflect, texture, uniform::ShaderType};
+
+use crate::Result;
+
+pub const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct SsaoUniform {
+    projection: glam::Mat4,
+    inverse_projection: glam::Mat4,
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    #[shader(padding)]
+    _padding: u32,
+}
+
+#[derive(Debug)]
+struct Targets {
+    /// Occlusion straight from `fs_ssao`, and again after both blur passes.
+    ao: wgpu::TextureView,
+    /// Between the horizontal and vertical blur.
+    blurred: wgpu::TextureView,
+    /// Binds `blurred` as the input, for `fs_ssao` (which ignores it) and the vertical blur.
+    from_blurred: wgpu::BindGroup,
+    from_ao: wgpu::BindGroup,
+}
+
+/// Screen-space ambient occlusion. Occlusion is estimated per pixel from the depth buffer
+/// alone, sampling a hemisphere around the normal reconstructed from neighboring depths,
+/// then blurred with weights that respect depth edges. The result, 1 where nothing
+/// occludes, scales the ambient light on the scene mesh in `RenderPath::Deferred` and
+/// `RenderPath::ClusteredForward`. The other meshes aren't in the depth it reads, so they
+/// stay unoccluded, as does everything on `RenderPath::Forward`.
+#[derive(Debug)]
+pub struct Ssao {
+    pub enabled: bool,
+    /// Of the sampled hemisphere, in world units.
+    pub radius: f32,
+    /// Depth difference a sample has to be behind the scene by to count, so surfaces
+    /// don't occlude themselves through depth imprecision.
+    pub bias: f32,
+    /// Exponent on the unoccluded fraction, higher darkens creases more.
+    pub intensity: f32,
+    ssao_pipeline: wgpu::RenderPipeline,
+    horizontal_pipeline: wgpu::RenderPipeline,
+    vertical_pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+    targets: Targets,
+}
+
+impl Ssao {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &texture::Texture,
+    ) -> Result<Self> {
+        let shader_source = include_str!("../../ssao.wgsl");
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        let layout =
+            reflection.create_bind_group_layout(device, 0, Some("ssao_bind_group_layout"))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_fullscreen",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: AO_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let ssao_pipeline = create_pipeline("SSAO Pipeline", "fs_ssao");
+        let horizontal_pipeline =
+            create_pipeline("SSAO Horizontal Blur Pipeline", "fs_blur_horizontal");
+        let vertical_pipeline = create_pipeline("SSAO Vertical Blur Pipeline", "fs_blur_vertical");
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO Uniform Buffer"),
+            size: std::mem::size_of::<SsaoUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let targets = Self::create_targets(device, config, &layout, &uniform, depth_texture);
+        Ok(Self {
+            enabled: true,
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+            ssao_pipeline,
+            horizontal_pipeline,
+            vertical_pipeline,
+            layout,
+            uniform,
+            targets,
+        })
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+        uniform: &wgpu::Buffer,
+        depth_texture: &texture::Texture,
+    ) -> Targets {
+        let create_view = |label| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: config.width,
+                        height: config.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: AO_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let ao = create_view("ssao_texture");
+        let blurred = create_view("ssao_blur_texture");
+        let create_bind_group = |label, input| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(input),
+                    },
+                ],
+                label: Some(label),
+            })
+        };
+        let from_blurred = create_bind_group("ssao_from_blurred_bind_group", &blurred);
+        let from_ao = create_bind_group("ssao_from_ao_bind_group", &ao);
+        Targets {
+            ao,
+            blurred,
+            from_blurred,
+            from_ao,
+        }
+    }
+
+    /// `view` changes, so bind groups holding it have to be recreated too.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_texture: &texture::Texture,
+    ) {
+        self.targets =
+            Self::create_targets(device, config, &self.layout, &self.uniform, depth_texture);
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &camera::Camera) {
+        let projection = camera.build_projection_matrix();
+        let uniform = SsaoUniform {
+            projection,
+            inverse_projection: projection.inverse(),
+            radius: self.radius,
+            bias: self.bias,
+            intensity: self.intensity,
+            _padding: 0,
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Fills `view` from the depth buffer, which must hold the opaque scene by now. Just
+    /// clears it to 1 when disabled. `timestamp_writes` spans all of its passes.
+    pub fn run(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'_>>,
+    ) {
+        let mut pass = |label,
+                        view,
+                        pipeline: Option<(&wgpu::RenderPipeline, &wgpu::BindGroup)>,
+                        timestamp_writes| {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes,
+            });
+            if let Some((pipeline, bind_group)) = pipeline {
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+        };
+
+        if !self.enabled {
+            pass("SSAO Pass", &self.targets.ao, None, timestamp_writes);
+            return;
+        }
+
+        // The first pass writes the start time and the last the end time.
+        let first = timestamp_writes
+            .clone()
+            .map(|writes| wgpu::RenderPassTimestampWrites {
+                end_of_pass_write_index: None,
+                ..writes
+            });
+        let last = timestamp_writes.map(|writes| wgpu::RenderPassTimestampWrites {
+            beginning_of_pass_write_index: None,
+            ..writes
+        });
+        let targets = &self.targets;
+        pass(
+            "SSAO Pass",
+            &targets.ao,
+            Some((&self.ssao_pipeline, &targets.from_blurred)),
+            first,
+        );
+        pass(
+            "SSAO Horizontal Blur Pass",
+            &targets.blurred,
+            Some((&self.horizontal_pipeline, &targets.from_ao)),
+            None,
+        );
+        pass(
+            "SSAO Vertical Blur Pass",
+            &targets.ao,
+            Some((&self.vertical_pipeline, &targets.from_blurred)),
+            last,
+        );
+    }
+
+    /// Ambient visibility after `run`, one texel per pixel.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.targets.ao
+    }
+}
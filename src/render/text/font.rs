@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use crate::{render::atlas::pack::ShelfPacker, GameError, Result};
+
+/// Side length of a font's glyph atlas in texels.
+pub const ATLAS_SIZE: u32 = 1024;
+/// Empty texels around every glyph so linear filtering never picks up a neighbour.
+const GLYPH_PADDING: u32 = 1;
+
+/// How glyphs are stored in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlyphMode {
+    /// Plain coverage. Sharpest at the raster size, blurry when scaled up.
+    Bitmap,
+    /// Signed distance to the outline, `spread` texels either side of it. Stays crisp at
+    /// any size, at the cost of rounded corners on very large text.
+    Sdf { spread: u32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FontOptions {
+    /// Pixel size glyphs are rasterized at.
+    pub raster_size: f32,
+    pub mode: GlyphMode,
+}
+
+impl Default for FontOptions {
+    fn default() -> Self {
+        Self {
+            raster_size: 48.0,
+            mode: GlyphMode::Sdf { spread: 6 },
+        }
+    }
+}
+
+/// A glyph's placement in the atlas and its metrics at the raster size, in pixels with
+/// y pointing up from the baseline. `width`/`height` include the SDF spread, if any.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph {
+    pub xmin: f32,
+    pub ymin: f32,
+    pub width: f32,
+    pub height: f32,
+    pub advance: f32,
+    /// Normalized atlas coordinates, `[min_u, min_v, max_u, max_v]`.
+    pub uv: [f32; 4],
+}
+
+/// A parsed font and its glyph atlas. Glyphs are rasterized into the atlas the first
+/// time they're laid out; ASCII is done up front.
+#[derive(Debug)]
+pub struct Font {
+    font: fontdue::Font,
+    options: FontOptions,
+    glyphs: HashMap<char, Option<Glyph>>,
+    atlas: Vec<u8>,
+    packer: ShelfPacker,
+    /// Set whenever the atlas changed since the renderer last uploaded it.
+    pub(super) dirty: bool,
+}
+
+impl Font {
+    pub fn from_bytes(bytes: &[u8], options: FontOptions) -> Result<Self> {
+        let font = fontdue::Font::from_bytes(
+            bytes,
+            fontdue::FontSettings {
+                scale: options.raster_size,
+                ..Default::default()
+            },
+        )
+        .map_err(|err| GameError::FontError(err.to_string()))?;
+
+        let mut font = Self {
+            font,
+            options,
+            glyphs: HashMap::new(),
+            atlas: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+            packer: ShelfPacker::new(ATLAS_SIZE, ATLAS_SIZE),
+            dirty: true,
+        };
+        for c in ' '..='~' {
+            font.glyph(c);
+        }
+        Ok(font)
+    }
+
+    pub fn options(&self) -> FontOptions {
+        self.options
+    }
+
+    /// Single channel atlas texels, `ATLAS_SIZE` squared.
+    pub fn atlas(&self) -> &[u8] {
+        &self.atlas
+    }
+
+    /// Ascent and line advance at the raster size.
+    pub fn line_metrics(&self) -> (f32, f32) {
+        self.font
+            .horizontal_line_metrics(self.options.raster_size)
+            .map(|metrics| (metrics.ascent, metrics.new_line_size))
+            .unwrap_or((self.options.raster_size, self.options.raster_size * 1.2))
+    }
+
+    /// Kerning adjustment between two characters at the raster size.
+    pub fn kern(&self, left: char, right: char) -> f32 {
+        self.font
+            .horizontal_kern(left, right, self.options.raster_size)
+            .unwrap_or_default()
+    }
+
+    /// Advance width of `c` at the raster size, without touching the atlas.
+    pub fn advance(&self, c: char) -> f32 {
+        self.font.metrics(c, self.options.raster_size).advance_width
+    }
+
+    /// Returns the atlas entry for `c`, rasterizing it if needed. `None` for glyphs
+    /// without an outline (such as spaces) or when the atlas is full.
+    pub fn glyph(&mut self, c: char) -> Option<Glyph> {
+        if let Some(glyph) = self.glyphs.get(&c) {
+            return *glyph;
+        }
+
+        let glyph = self.rasterize(c);
+        self.glyphs.insert(c, glyph);
+        glyph
+    }
+
+    fn rasterize(&mut self, c: char) -> Option<Glyph> {
+        let (metrics, coverage) = self.font.rasterize(c, self.options.raster_size);
+        if metrics.width == 0 || metrics.height == 0 {
+            return None;
+        }
+
+        let (spread, width, height, texels) = match self.options.mode {
+            GlyphMode::Bitmap => (0, metrics.width, metrics.height, coverage),
+            GlyphMode::Sdf { spread } => {
+                let (width, height, texels) =
+                    signed_distance_field(&coverage, metrics.width, metrics.height, spread);
+                (spread, width, height, texels)
+            }
+        };
+
+        let Some((x, y)) = self.packer.allocate(
+            width as u32 + GLYPH_PADDING * 2,
+            height as u32 + GLYPH_PADDING * 2,
+        ) else {
+            log::warn!("Glyph atlas is full, '{}' won't be drawn", c);
+            return None;
+        };
+        let (x, y) = (x + GLYPH_PADDING, y + GLYPH_PADDING);
+
+        for row in 0..height {
+            let dst = ((y as usize + row) * ATLAS_SIZE as usize) + x as usize;
+            self.atlas[dst..dst + width].copy_from_slice(&texels[row * width..(row + 1) * width]);
+        }
+        self.dirty = true;
+
+        let size = ATLAS_SIZE as f32;
+        Some(Glyph {
+            xmin: metrics.xmin as f32 - spread as f32,
+            ymin: metrics.ymin as f32 - spread as f32,
+            width: width as f32,
+            height: height as f32,
+            advance: metrics.advance_width,
+            uv: [
+                x as f32 / size,
+                y as f32 / size,
+                (x as f32 + width as f32) / size,
+                (y as f32 + height as f32) / size,
+            ],
+        })
+    }
+}
+
+/// Converts a coverage bitmap into a distance field padded by `spread` texels on every
+/// side. 0.5 is the outline, larger values are inside.
+fn signed_distance_field(
+    coverage: &[u8],
+    width: usize,
+    height: usize,
+    spread: u32,
+) -> (usize, usize, Vec<u8>) {
+    let pad = spread as usize;
+    let (out_width, out_height) = (width + pad * 2, height + pad * 2);
+
+    let inside = |x: usize, y: usize| {
+        x >= pad
+            && y >= pad
+            && x - pad < width
+            && y - pad < height
+            && coverage[(y - pad) * width + (x - pad)] >= 128
+    };
+
+    let mut to_inside = vec![0.0; out_width * out_height];
+    let mut to_outside = vec![0.0; out_width * out_height];
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let i = y * out_width + x;
+            if inside(x, y) {
+                to_outside[i] = f32::INFINITY;
+            } else {
+                to_inside[i] = f32::INFINITY;
+            }
+        }
+    }
+    distance_transform(&mut to_inside, out_width, out_height);
+    distance_transform(&mut to_outside, out_width, out_height);
+
+    let texels = to_inside
+        .iter()
+        .zip(&to_outside)
+        .map(|(&to_inside, &to_outside)| {
+            let distance = to_outside.sqrt() - to_inside.sqrt();
+            let value = 0.5 + distance / (spread.max(1) as f32 * 2.0);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect();
+
+    (out_width, out_height, texels)
+}
+
+/// Felzenszwalb & Huttenlocher's exact squared Euclidean distance transform: replaces
+/// every value with the squared distance to the nearest zero.
+fn distance_transform(grid: &mut [f32], width: usize, height: usize) {
+    let mut column = vec![0.0; height];
+    for x in 0..width {
+        for y in 0..height {
+            column[y] = grid[y * width + x];
+        }
+        let column = distance_transform_1d(&column);
+        for y in 0..height {
+            grid[y * width + x] = column[y];
+        }
+    }
+    for row in grid.chunks_exact_mut(width) {
+        let transformed = distance_transform_1d(row);
+        row.copy_from_slice(&transformed);
+    }
+}
+
+fn distance_transform_1d(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut distances = vec![f32::INFINITY; n];
+    // Parabola vertices and the boundaries between them.
+    let mut vertices = vec![0usize; n];
+    let mut bounds = vec![0.0f32; n + 1];
+    let mut k = 0;
+
+    let Some(first) = f.iter().position(|v| v.is_finite()) else {
+        return distances;
+    };
+    vertices[0] = first;
+    bounds[0] = f32::NEG_INFINITY;
+    bounds[1] = f32::INFINITY;
+
+    for q in first + 1..n {
+        if !f[q].is_finite() {
+            continue;
+        }
+        // `bounds[0]` is -inf, so this always stops before `k` underflows.
+        let s = loop {
+            let v = vertices[k];
+            let s = ((f[q] + (q * q) as f32) - (f[v] + (v * v) as f32)) / (2.0 * (q - v) as f32);
+            if s > bounds[k] {
+                break s;
+            }
+            k -= 1;
+        };
+        k += 1;
+        vertices[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, distance) in distances.iter_mut().enumerate() {
+        while bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+        let v = vertices[k];
+        let offset = q as f32 - v as f32;
+        *distance = offset * offset + f[v];
+    }
+    distances
+}
@@ -0,0 +1,166 @@
+use super::font::Font;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+    /// Font size in pixels (screen space) or layout units (world space).
+    pub size: f32,
+    pub color: glam::Vec4,
+    pub align: Align,
+    /// Lines longer than this wrap at the last space, or mid-word if there isn't one.
+    /// Alignment is relative to this width when set.
+    pub max_width: Option<f32>,
+    /// Multiplier on the font's line height.
+    pub line_spacing: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 24.0,
+            color: glam::Vec4::ONE,
+            align: Align::Left,
+            max_width: None,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+/// A glyph quad in layout space: pixels from the top-left of the text block, y down.
+#[derive(Debug, Clone, Copy)]
+pub struct PositionedGlyph {
+    pub min: glam::Vec2,
+    pub max: glam::Vec2,
+    /// Normalized atlas coordinates, `[min_u, min_v, max_u, max_v]`.
+    pub uv: [f32; 4],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    /// Size of the whole block, the widest line by the total line height.
+    pub size: glam::Vec2,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Placed {
+    c: char,
+    x: f32,
+    advance: f32,
+}
+
+/// Lays out a UTF-8 string: applies kerning, wraps lines to `style.max_width`, breaks on
+/// `\n` and aligns each line. Rasterizes any glyphs not yet in `font`'s atlas.
+pub fn layout(font: &mut Font, text: &str, style: &TextStyle) -> TextLayout {
+    let scale = style.size / font.options().raster_size;
+    let (ascent, line_height) = font.line_metrics();
+    let (ascent, line_height) = (ascent * scale, line_height * scale * style.line_spacing);
+
+    let lines = text
+        .split('\n')
+        .flat_map(|paragraph| wrap(font, paragraph, scale, style.max_width))
+        .collect::<Vec<_>>();
+
+    let widths = lines
+        .iter()
+        .map(|line| line_width(line))
+        .collect::<Vec<_>>();
+    let block_width = style
+        .max_width
+        .unwrap_or_else(|| widths.iter().copied().fold(0.0, f32::max));
+
+    let mut glyphs = Vec::new();
+    for (index, (line, width)) in lines.iter().zip(&widths).enumerate() {
+        let offset = match style.align {
+            Align::Left => 0.0,
+            Align::Center => (block_width - width) * 0.5,
+            Align::Right => block_width - width,
+        };
+        let baseline = ascent + index as f32 * line_height;
+
+        for placed in line {
+            let Some(glyph) = font.glyph(placed.c) else {
+                continue;
+            };
+            let min = glam::Vec2::new(
+                offset + placed.x + glyph.xmin * scale,
+                baseline - (glyph.ymin + glyph.height) * scale,
+            );
+            glyphs.push(PositionedGlyph {
+                min,
+                max: min + glam::Vec2::new(glyph.width, glyph.height) * scale,
+                uv: glyph.uv,
+            });
+        }
+    }
+
+    TextLayout {
+        glyphs,
+        size: glam::Vec2::new(
+            widths.iter().copied().fold(0.0, f32::max),
+            lines.len() as f32 * line_height,
+        ),
+    }
+}
+
+/// Splits one paragraph into lines no wider than `max_width`.
+fn wrap(font: &Font, paragraph: &str, scale: f32, max_width: Option<f32>) -> Vec<Vec<Placed>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<Placed> = Vec::new();
+    let mut x = 0.0;
+    // Index in `line` just past the last space, where the line can be broken.
+    let mut last_break = None;
+    let mut previous = None;
+
+    for c in paragraph.chars() {
+        if let Some(previous) = previous {
+            x += font.kern(previous, c) * scale;
+        }
+        let advance = font.advance(c) * scale;
+
+        let overflows = max_width.is_some_and(|max| x + advance > max);
+        if overflows && !c.is_whitespace() && !line.is_empty() {
+            let rest = last_break
+                .take()
+                .map(|index| line.split_off(index))
+                .unwrap_or_default();
+            let shift = rest.first().map(|placed| placed.x).unwrap_or(x);
+            lines.push(std::mem::take(&mut line));
+            line = rest
+                .into_iter()
+                .map(|placed| Placed {
+                    x: placed.x - shift,
+                    ..placed
+                })
+                .collect();
+            x -= shift;
+        }
+
+        line.push(Placed { c, x, advance });
+        x += advance;
+        if c.is_whitespace() {
+            last_break = Some(line.len());
+        }
+        previous = Some(c);
+    }
+
+    lines.push(line);
+    lines
+}
+
+/// Width up to the end of the last visible character, so trailing spaces don't affect
+/// alignment.
+fn line_width(line: &[Placed]) -> f32 {
+    line.iter()
+        .rev()
+        .find(|placed| !placed.c.is_whitespace())
+        .map(|placed| placed.x + placed.advance)
+        .unwrap_or(0.0)
+}
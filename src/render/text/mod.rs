@@ -0,0 +1,401 @@
+pub mod font;
+pub mod layout;
+
+use std::ops::Range;
+
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::Result;
+
+use super::{
+    reflect, texture,
+    types::{self, CameraUniform, VertexDescription},
+    uniform,
+};
+
+pub use font::{Font, FontOptions, GlyphMode};
+pub use layout::{layout, Align, TextLayout, TextStyle};
+
+const INITIAL_CAPACITY: u64 = 6 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontId(usize);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, types::VertexDescription)]
+pub struct TextVertex {
+    position: Vec3,
+    tex_coords: Vec2,
+    color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Space {
+    Screen,
+    World,
+}
+
+#[derive(Debug)]
+struct Batch {
+    font: FontId,
+    space: Space,
+    vertices: Range<u32>,
+}
+
+#[derive(Debug)]
+struct FontEntry {
+    font: Font,
+    atlas: Option<texture::Texture>,
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+#[derive(Debug)]
+struct Pipelines {
+    screen: wgpu::RenderPipeline,
+    world: wgpu::RenderPipeline,
+}
+
+/// Draws text queued with `draw_screen`/`draw_world` since the last frame.
+///
+/// Screen-space text is positioned in pixels from the top-left corner and drawn over
+/// everything; world-space text is depth tested against the scene.
+#[derive(Debug)]
+pub struct TextRenderer {
+    fonts: Vec<FontEntry>,
+    atlas_layout: wgpu::BindGroupLayout,
+    bitmap: Pipelines,
+    sdf: Pipelines,
+    screen_camera: uniform::UniformBuffer<CameraUniform>,
+    vertices: Vec<TextVertex>,
+    batches: Vec<Batch>,
+    drawn: Vec<Batch>,
+    vertex_buffer: wgpu::Buffer,
+    capacity: u64,
+}
+
+impl TextRenderer {
+    /// `camera_bind_group_layout` must match the layout of `@group(1)` in `text.wgsl`.
+    /// Loads the bundled Hack font as the default font.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader_source = include_str!("../../text.wgsl");
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers("vs_main", &[TextVertex::desc()])?;
+
+        let atlas_layout =
+            reflection.create_bind_group_layout(device, 0, Some("text_atlas_bind_group_layout"))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Text Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[&atlas_layout, camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label: &str, fs_entry: &str, depth_compare| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[TextVertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fs_entry,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let bitmap = Pipelines {
+            screen: create_pipeline(
+                "Bitmap Text Pipeline",
+                "fs_bitmap",
+                wgpu::CompareFunction::Always,
+            ),
+            world: create_pipeline(
+                "Bitmap World Text Pipeline",
+                "fs_bitmap",
+                wgpu::CompareFunction::LessEqual,
+            ),
+        };
+        let sdf = Pipelines {
+            screen: create_pipeline("SDF Text Pipeline", "fs_sdf", wgpu::CompareFunction::Always),
+            world: create_pipeline(
+                "SDF World Text Pipeline",
+                "fs_sdf",
+                wgpu::CompareFunction::LessEqual,
+            ),
+        };
+
+        let mut screen_uniform = CameraUniform::new();
+        screen_uniform.update_view_proj(screen_projection(config.width, config.height));
+        let screen_camera = uniform::UniformBuffer::new(
+            device,
+            camera_bind_group_layout,
+            screen_uniform,
+            "text_screen_camera",
+        );
+
+        let mut text = Self {
+            fonts: Vec::new(),
+            atlas_layout,
+            bitmap,
+            sdf,
+            screen_camera,
+            vertices: Vec::new(),
+            batches: Vec::new(),
+            drawn: Vec::new(),
+            vertex_buffer: create_vertex_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+        };
+        text.load_font(
+            include_bytes!("../../fonts/Hack-Regular.ttf"),
+            FontOptions::default(),
+        )?;
+        Ok(text)
+    }
+
+    /// Parses a TTF/OTF font. Its atlas is uploaded the next time text is drawn.
+    pub fn load_font(&mut self, bytes: &[u8], options: FontOptions) -> Result<FontId> {
+        self.fonts.push(FontEntry {
+            font: Font::from_bytes(bytes, options)?,
+            atlas: None,
+            bind_group: None,
+        });
+        Ok(FontId(self.fonts.len() - 1))
+    }
+
+    pub fn default_font(&self) -> FontId {
+        FontId(0)
+    }
+
+    pub fn font(&mut self, font: FontId) -> &mut Font {
+        &mut self.fonts[font.0].font
+    }
+
+    /// Size of `text` laid out with `style`, without drawing it.
+    pub fn measure(&mut self, font: FontId, text: &str, style: &TextStyle) -> Vec2 {
+        layout(self.font(font), text, style).size
+    }
+
+    /// Queues `text` with its top-left corner at `position`, in pixels from the top-left
+    /// of the window.
+    pub fn draw_screen(&mut self, font: FontId, text: &str, position: Vec2, style: &TextStyle) {
+        let transform = Mat4::from_translation(position.extend(0.0));
+        self.queue(font, text, style, Space::Screen, transform);
+    }
+
+    /// Queues `text` in the plane of `transform`, top-left corner at its origin, reading
+    /// along +X with lines stacking down -Y. `style.size` is in world units.
+    pub fn draw_world(&mut self, font: FontId, text: &str, transform: Mat4, style: &TextStyle) {
+        let flip = Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0));
+        self.queue(font, text, style, Space::World, transform * flip);
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.screen_camera.update(queue, |uniform| {
+            uniform.update_view_proj(screen_projection(width, height))
+        });
+    }
+
+    /// Uploads changed atlases and this frame's vertices, and clears the queue for the
+    /// next frame. Call before recording the pass `draw` is used in.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        for entry in self.fonts.iter_mut() {
+            if !entry.font.dirty {
+                continue;
+            }
+            let atlas = entry
+                .atlas
+                .get_or_insert_with(|| create_atlas(device, "text_atlas"));
+            if entry.bind_group.is_none() {
+                entry.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.atlas_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&atlas.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+                        },
+                    ],
+                    label: Some("text_atlas_bind_group"),
+                }));
+            }
+
+            queue.write_texture(
+                atlas.texture.as_image_copy(),
+                entry.font.atlas(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(font::ATLAS_SIZE),
+                    rows_per_image: Some(font::ATLAS_SIZE),
+                },
+                atlas.texture.size(),
+            );
+            entry.font.dirty = false;
+        }
+
+        let count = self.vertices.len() as u64;
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+
+        self.vertices.clear();
+        self.drawn = std::mem::take(&mut self.batches);
+    }
+
+    /// Records the text prepared this frame into a pass with the scene's depth buffer
+    /// attached.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        for batch in self.drawn.iter() {
+            let entry = &self.fonts[batch.font.0];
+            let Some(bind_group) = &entry.bind_group else {
+                continue;
+            };
+            let pipelines = match entry.font.options().mode {
+                GlyphMode::Bitmap => &self.bitmap,
+                GlyphMode::Sdf { .. } => &self.sdf,
+            };
+            let (pipeline, camera) = match batch.space {
+                Space::Screen => (&pipelines.screen, self.screen_camera.bind_group()),
+                Space::World => (&pipelines.world, camera_bind_group),
+            };
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.set_bind_group(1, camera, &[]);
+            render_pass.draw(batch.vertices.clone(), 0..1);
+        }
+    }
+
+    fn queue(
+        &mut self,
+        font: FontId,
+        text: &str,
+        style: &TextStyle,
+        space: Space,
+        transform: Mat4,
+    ) {
+        let laid_out = layout(self.font(font), text, style);
+        let color = style.color.to_array();
+        let start = self.vertices.len() as u32;
+
+        for glyph in laid_out.glyphs {
+            let [min_u, min_v, max_u, max_v] = glyph.uv;
+            let corner = |x: f32, y: f32, u: f32, v: f32| TextVertex {
+                position: transform.transform_point3(Vec3::new(x, y, 0.0)),
+                tex_coords: Vec2::new(u, v),
+                color,
+            };
+            let top_left = corner(glyph.min.x, glyph.min.y, min_u, min_v);
+            let top_right = corner(glyph.max.x, glyph.min.y, max_u, min_v);
+            let bottom_left = corner(glyph.min.x, glyph.max.y, min_u, max_v);
+            let bottom_right = corner(glyph.max.x, glyph.max.y, max_u, max_v);
+            self.vertices.extend([
+                top_left,
+                bottom_left,
+                top_right,
+                top_right,
+                bottom_left,
+                bottom_right,
+            ]);
+        }
+
+        let end = self.vertices.len() as u32;
+        match self.batches.last_mut() {
+            Some(batch) if batch.font == font && batch.space == space => batch.vertices.end = end,
+            _ if end > start => self.batches.push(Batch {
+                font,
+                space,
+                vertices: start..end,
+            }),
+            _ => (),
+        }
+    }
+}
+
+/// Maps window pixels, y down, to clip space.
+pub(super) fn screen_projection(width: u32, height: u32) -> Mat4 {
+    Mat4::orthographic_rh(
+        0.0,
+        width.max(1) as f32,
+        height.max(1) as f32,
+        0.0,
+        -1.0,
+        1.0,
+    )
+}
+
+fn create_atlas(device: &wgpu::Device, label: &str) -> texture::Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: font::ATLAS_SIZE,
+            height: font::ATLAS_SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    texture::Texture {
+        texture,
+        view,
+        sampler,
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Text Vertex Buffer"),
+        size: capacity * std::mem::size_of::<TextVertex>() as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
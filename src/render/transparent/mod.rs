@@ -0,0 +1,385 @@
+pub mod oit;
+
+use std::ops::Range;
+
+use glam::{Mat4, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::Result;
+
+use super::{
+    camera, reflect, texture,
+    types::{self, VertexDescription},
+};
+
+/// How `TransparentRenderer` and alpha-blended particles get their blending order right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, strum::EnumIter)]
+pub enum TransparencyMode {
+    /// Instances are sorted back to front by their distance to the camera and alpha
+    /// blended in the main pass. Exact between instances, but intersecting or
+    /// self-overlapping meshes can still blend in the wrong order.
+    #[default]
+    Sorted,
+    /// Weighted blended order-independent transparency, see `oit::WeightedBlended`.
+    /// Suits many overlapping layers, like particles and foliage, where sorting is slow
+    /// or pops.
+    WeightedBlended,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, types::VertexDescription)]
+#[vertex(step_mode = "instance", location_offset = 5)]
+struct TransparentInstanceRaw {
+    model: Mat4,
+    color: Vec4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransparentMeshId(usize);
+
+/// GPU resources of one transparent mesh and where its copies are placed.
+#[derive(Debug)]
+pub struct TransparentMesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    /// Middle of the bounding box, the point instances are sorted by.
+    center: Vec3,
+    instances: Vec<Mat4>,
+    texture: texture::Texture,
+    texture_bind_group: wgpu::BindGroup,
+    /// Multiplies the texture, alpha included.
+    pub color: Vec4,
+    pub visible: bool,
+}
+
+impl TransparentMesh {
+    pub fn texture(&self) -> &texture::Texture {
+        &self.texture
+    }
+
+    /// Places copies of the mesh, like the scene's instances.
+    pub fn set_instances(&mut self, instances: &[types::Instance]) {
+        self.instances = instances
+            .iter()
+            .map(|instance| instance.to_raw().model())
+            .collect();
+    }
+}
+
+/// Consecutive instances of one mesh in the shared instance buffer, drawn with one call.
+#[derive(Debug)]
+struct Batch {
+    mesh: usize,
+    instances: Range<u32>,
+}
+
+/// Render queue for alpha-blended meshes, drawn after opaque geometry with depth
+/// testing but no depth writes. Every visible instance of every mesh goes into one
+/// instance buffer each frame, sorted back to front in `TransparencyMode::Sorted`, and
+/// runs of the same mesh are drawn together.
+#[derive(Debug)]
+pub struct TransparentRenderer {
+    mode: TransparencyMode,
+    sorted_pipeline: wgpu::RenderPipeline,
+    weighted_blended_pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    weighted_blended: oit::WeightedBlended,
+    meshes: Vec<TransparentMesh>,
+    instance_buffer: wgpu::Buffer,
+    batches: Vec<Batch>,
+}
+
+impl TransparentRenderer {
+    /// `camera_bind_group_layout` and `lighting_bind_group_layout` must match the layouts
+    /// of `@group(1)` and `@group(2)` in `transparent.wgsl`.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader_source = concat!(
+            include_str!("../../lighting.wgsl"),
+            include_str!("../../transparent.wgsl")
+        );
+        let reflection = reflect::ShaderReflection::new(shader_source)?;
+        reflection.validate_vertex_buffers(
+            "vs_main",
+            &[types::Vertex::desc(), TransparentInstanceRaw::desc()],
+        )?;
+
+        let texture_layout = reflection.create_bind_group_layout(
+            device,
+            0,
+            Some("transparent_texture_bind_group_layout"),
+        )?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Transparent Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Transparent Pipeline Layout"),
+            bind_group_layouts: &[
+                &texture_layout,
+                camera_bind_group_layout,
+                lighting_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label, entry_point, targets: &[Option<wgpu::ColorTargetState>]| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[types::Vertex::desc(), TransparentInstanceRaw::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets,
+                }),
+                // Both faces, since the back of a transparent surface shows through.
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let sorted_pipeline = create_pipeline(
+            "Sorted Transparent Pipeline",
+            "fs_main",
+            &[Some(wgpu::ColorTargetState {
+                format: config.format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        );
+        let weighted_blended_pipeline = create_pipeline(
+            "Weighted Blended Transparent Pipeline",
+            "fs_weighted_blended",
+            &oit::color_targets(),
+        );
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Transparent Instance Buffer"),
+            size: std::mem::size_of::<TransparentInstanceRaw>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            mode: TransparencyMode::default(),
+            sorted_pipeline,
+            weighted_blended_pipeline,
+            texture_layout,
+            weighted_blended: oit::WeightedBlended::new(device, config)?,
+            meshes: Vec::new(),
+            instance_buffer,
+            batches: Vec::new(),
+        })
+    }
+
+    pub fn mode(&self) -> TransparencyMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: TransparencyMode) {
+        self.mode = mode;
+    }
+
+    pub fn weighted_blended(&self) -> &oit::WeightedBlended {
+        &self.weighted_blended
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.weighted_blended.resize(device, config);
+    }
+
+    /// Uploads a mesh, without instances. Meshes without a texture are drawn in their
+    /// `color`, which starts out half transparent white.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertices: &[types::Vertex],
+        indices: &[u32],
+        texture: Option<&image::RgbaImage>,
+    ) -> Result<TransparentMeshId> {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Transparent Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Transparent Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let (min, max) = vertices.iter().map(types::Vertex::position).fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), position| (min.min(position), max.max(position)),
+        );
+
+        let image = match texture {
+            Some(image) => image::DynamicImage::ImageRgba8(image.clone()),
+            None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255; 4]),
+            )),
+        };
+        let texture =
+            texture::Texture::from_image(device, queue, &image, Some("transparent_texture"))?;
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("transparent_texture_bind_group"),
+        });
+
+        self.meshes.push(TransparentMesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            center: (min + max) * 0.5,
+            instances: Vec::new(),
+            texture,
+            texture_bind_group,
+            color: Vec4::new(1.0, 1.0, 1.0, 0.5),
+            visible: true,
+        });
+        Ok(TransparentMeshId(self.meshes.len() - 1))
+    }
+
+    pub fn mesh(&mut self, mesh: TransparentMeshId) -> &mut TransparentMesh {
+        &mut self.meshes[mesh.0]
+    }
+
+    /// Gathers this frame's instances into batches, sorted back to front from `camera`
+    /// unless order doesn't matter in the current mode.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &camera::Camera) {
+        let eye = camera.get_eye();
+        let mut items = self
+            .meshes
+            .iter()
+            .enumerate()
+            .filter(|(_, mesh)| mesh.visible)
+            .flat_map(|(index, mesh)| {
+                mesh.instances.iter().map(move |model| {
+                    let distance = model.transform_point3(mesh.center).distance_squared(eye);
+                    (index, *model, distance)
+                })
+            })
+            .collect::<Vec<_>>();
+        if self.mode == TransparencyMode::Sorted {
+            items.sort_by(|a, b| b.2.total_cmp(&a.2));
+        }
+
+        self.batches.clear();
+        for (index, &(mesh, ..)) in items.iter().enumerate() {
+            let index = index as u32;
+            match self.batches.last_mut() {
+                Some(batch) if batch.mesh == mesh => batch.instances.end = index + 1,
+                _ => self.batches.push(Batch {
+                    mesh,
+                    instances: index..index + 1,
+                }),
+            }
+        }
+
+        let raw = items
+            .iter()
+            .map(|&(mesh, model, _)| TransparentInstanceRaw {
+                model,
+                color: self.meshes[mesh].color,
+            })
+            .collect::<Vec<_>>();
+        let size = std::mem::size_of_val(raw.as_slice()) as wgpu::BufferAddress;
+        if size > self.instance_buffer.size() {
+            self.instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Transparent Instance Buffer"),
+                contents: bytemuck::cast_slice(&raw),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            });
+        } else if size > 0 {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw));
+        }
+    }
+
+    fn draw_batches<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, lighting_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+        for batch in &self.batches {
+            let mesh = &self.meshes[batch.mesh];
+            render_pass.set_bind_group(0, &mesh.texture_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.index_count, 0, batch.instances.clone());
+        }
+    }
+
+    /// Blends the sorted instances over the frame. Call after opaque geometry in a pass
+    /// with the scene's depth. Does nothing in `TransparencyMode::WeightedBlended`.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.mode == TransparencyMode::Sorted {
+            self.draw_batches(
+                render_pass,
+                &self.sorted_pipeline,
+                camera_bind_group,
+                lighting_bind_group,
+            );
+        }
+    }
+
+    /// Accumulates the instances in a pass with `oit::WeightedBlended::attachments`.
+    pub fn draw_weighted_blended<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lighting_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.draw_batches(
+            render_pass,
+            &self.weighted_blended_pipeline,
+            camera_bind_group,
+            lighting_bind_group,
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use core::panic;
 
+use super::uniform::ShaderType;
+
 #[rustfmt::skip]
 pub const VERTICES: &[Vertex] = &[
     Vertex { position:  glam::Vec3::new(-0.0868241, 0.49240386, 0.0), tex_coords:  glam::Vec2::new(0.4131759, 0.00759614), }, // A
@@ -16,6 +18,8 @@ pub const INDICES: &[u16] = &[
     2, 3, 4,
 ];
 
+pub use game_derive::VertexDescription;
+
 pub trait VertexDescription {
     type Data: bytemuck::Pod + bytemuck::Zeroable;
     const ATTRIBS: &'static [wgpu::VertexAttribute];
@@ -23,30 +27,33 @@ pub trait VertexDescription {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, VertexDescription)]
 pub struct Vertex {
     position: glam::Vec3,
     tex_coords: glam::Vec2,
 }
 
-impl VertexDescription for Vertex {
-    type Data = Self;
-    const ATTRIBS: &'static [wgpu::VertexAttribute] =
-        &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
-
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self::Data>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: Self::ATTRIBS,
+impl Vertex {
+    pub fn new(position: glam::Vec3, tex_coords: glam::Vec2) -> Self {
+        Self {
+            position,
+            tex_coords,
         }
     }
+
+    pub fn position(&self) -> glam::Vec3 {
+        self.position
+    }
+
+    pub fn tex_coords(&self) -> glam::Vec2 {
+        self.tex_coords
+    }
 }
 
 // We need this for Rust to store our data correctly for the shaders
 #[repr(C)]
 // This is so we can store this in a buffer
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
 pub struct CameraUniform {
     view_proj: glam::Mat4,
 }
@@ -96,23 +103,15 @@ impl<'a> FromIterator<&'a Instance> for Vec<InstanceRaw> {
     }
 }
 
-impl VertexDescription for Instance {
-    type Data = InstanceRaw;
-    const ATTRIBS: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
-        5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4
-    ];
-
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self::Data>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: Self::ATTRIBS,
-        }
-    }
-}
-
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, VertexDescription)]
+#[vertex(step_mode = "instance", location_offset = 5)]
 pub struct InstanceRaw {
     model: glam::Mat4,
 }
+
+impl InstanceRaw {
+    pub fn model(&self) -> glam::Mat4 {
+        self.model
+    }
+}
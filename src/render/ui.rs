@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use derivative::Derivative;
+use winit::{
+    event::{DeviceEvent, ElementState, Event, WindowEvent},
+    window::Window,
+};
+
+use super::texture;
+
+/// Immediate-mode UI (egui) drawn over the scene.
+///
+/// Build the UI once per rendered frame with `frame`; events go through `handle_event`
+/// first, which reports whether the UI took them so game bindings can ignore them.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct Ui {
+    window: Arc<Window>,
+    context: egui::Context,
+    #[derivative(Debug = "ignore")]
+    state: egui_winit::State,
+    #[derivative(Debug = "ignore")]
+    renderer: egui_wgpu::Renderer,
+    #[derivative(Debug = "ignore")]
+    shapes: Vec<egui::epaint::ClippedShape>,
+    #[derivative(Debug = "ignore")]
+    textures_delta: egui::TexturesDelta,
+    #[derivative(Debug = "ignore")]
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    pixels_per_point: f32,
+}
+
+impl Ui {
+    pub fn new(
+        window: Arc<Window>,
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+    ) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            &window,
+            Some(window.scale_factor() as f32),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        // Drawn in the overlay pass, which has the scene depth buffer attached.
+        let renderer = egui_wgpu::Renderer::new(
+            device,
+            color_format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            1,
+        );
+
+        Self {
+            pixels_per_point: window.scale_factor() as f32,
+            window,
+            context,
+            state,
+            renderer,
+            shapes: Vec::new(),
+            textures_delta: egui::TexturesDelta::default(),
+            paint_jobs: Vec::new(),
+        }
+    }
+
+    pub fn context(&self) -> &egui::Context {
+        &self.context
+    }
+
+    /// Feeds `event` to the UI. Returns `true` if the UI consumed it, e.g. a click on a
+    /// widget, typing into a text field or mouse motion while dragging a slider.
+    ///
+    /// Key and button releases are never reported as consumed, so a key held down
+    /// before the UI took focus doesn't stay pressed for the game.
+    pub fn handle_event(&mut self, event: &Event<()>) -> bool {
+        match event {
+            Event::WindowEvent { event, .. } => {
+                let consumed = self.state.on_window_event(&self.window, event).consumed;
+                let released = match event {
+                    WindowEvent::KeyboardInput { event, .. } => {
+                        event.state == ElementState::Released
+                    }
+                    WindowEvent::MouseInput { state, .. } => *state == ElementState::Released,
+                    _ => false,
+                };
+                consumed && !released
+            }
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { .. },
+                ..
+            } => self.context.is_using_pointer(),
+            _ => false,
+        }
+    }
+
+    /// Runs `build` to lay out this frame's UI. Call at most once per rendered frame,
+    /// before `Render::render`.
+    pub fn frame(&mut self, build: impl FnOnce(&egui::Context)) {
+        let input = self.state.take_egui_input(&self.window);
+        let output = self.context.run(input, build);
+        self.state
+            .handle_platform_output(&self.window, output.platform_output);
+
+        self.shapes = output.shapes;
+        self.pixels_per_point = output.pixels_per_point;
+        self.textures_delta.append(output.textures_delta);
+    }
+
+    /// Tessellates the last frame's UI and uploads its textures and buffers.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        for (id, delta) in self.textures_delta.set.iter() {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+
+        self.paint_jobs = self
+            .context
+            .tessellate(std::mem::take(&mut self.shapes), self.pixels_per_point);
+        // Paint callbacks aren't used, so there are no extra command buffers to submit.
+        self.renderer.update_buffers(
+            device,
+            queue,
+            encoder,
+            &self.paint_jobs,
+            &self.screen_descriptor(size),
+        );
+    }
+
+    /// Records the UI prepared this frame into `render_pass`.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.renderer
+            .render(render_pass, &self.paint_jobs, &self.screen_descriptor(size));
+    }
+
+    /// Frees textures the UI stopped using. Call after the frame is submitted.
+    pub fn end_frame(&mut self) {
+        for id in std::mem::take(&mut self.textures_delta).free {
+            self.renderer.free_texture(&id);
+        }
+    }
+
+    fn screen_descriptor(
+        &self,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> egui_wgpu::ScreenDescriptor {
+        egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: self.pixels_per_point,
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::marker::PhantomData;
+
+use wgpu::util::DeviceExt;
+
+pub use game_derive::ShaderType;
+
+/// A type whose Rust layout matches its WGSL host-shareable layout, so it can be
+/// uploaded byte for byte. Derive it for structs with `#[derive(ShaderType)]`, which
+/// checks every field offset against the WGSL rules at compile time.
+pub trait ShaderType: bytemuck::Pod {
+    /// `AlignOf(T)` in WGSL.
+    const ALIGN: usize;
+    /// `SizeOf(T)` in WGSL.
+    const SIZE: usize;
+    /// Alignment when used as a member in the uniform address space, where structs
+    /// and arrays are rounded up to 16 bytes.
+    const UNIFORM_ALIGN: usize = Self::ALIGN;
+    /// Bytes a uniform struct member must leave before the next member.
+    const UNIFORM_SPAN: usize = Self::SIZE;
+    /// Whether the layout also satisfies the stricter uniform address space rules.
+    const UNIFORM_VALID: bool = true;
+}
+
+#[doc(hidden)]
+pub const fn round_up(align: usize, offset: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+macro_rules! impl_shader_type {
+    ($($ty:ty => ($align:expr, $size:expr)),* $(,)?) => {
+        $(
+            impl ShaderType for $ty {
+                const ALIGN: usize = $align;
+                const SIZE: usize = $size;
+            }
+        )*
+    };
+}
+
+// `glam::Vec3A` and `glam::Mat3` are deliberately missing: their Rust sizes don't match
+// `vec3<f32>` (12 bytes) and `mat3x3<f32>` (48 bytes). Upload a `mat3x3` as `[Vec4; 3]`.
+impl_shader_type! {
+    f32 => (4, 4),
+    u32 => (4, 4),
+    i32 => (4, 4),
+    glam::Vec2 => (8, 8),
+    glam::UVec2 => (8, 8),
+    glam::IVec2 => (8, 8),
+    glam::Vec3 => (16, 12),
+    glam::UVec3 => (16, 12),
+    glam::IVec3 => (16, 12),
+    glam::Vec4 => (16, 16),
+    glam::UVec4 => (16, 16),
+    glam::IVec4 => (16, 16),
+    glam::Quat => (16, 16),
+    glam::Mat2 => (8, 16),
+    glam::Mat4 => (16, 64),
+}
+
+impl<T: ShaderType, const N: usize> ShaderType for [T; N]
+where
+    [T; N]: bytemuck::Pod,
+{
+    const ALIGN: usize = T::ALIGN;
+    const SIZE: usize = N * round_up(T::ALIGN, T::SIZE);
+    const UNIFORM_ALIGN: usize = round_up(16, T::UNIFORM_ALIGN);
+    const UNIFORM_VALID: bool = T::UNIFORM_VALID && round_up(T::ALIGN, T::SIZE).is_multiple_of(16);
+}
+
+pub trait AddressSpace {
+    const USAGE: wgpu::BufferUsages;
+}
+
+#[derive(Debug)]
+pub struct Uniform;
+
+impl AddressSpace for Uniform {
+    const USAGE: wgpu::BufferUsages = wgpu::BufferUsages::UNIFORM;
+}
+
+#[derive(Debug)]
+pub struct Storage;
+
+impl AddressSpace for Storage {
+    const USAGE: wgpu::BufferUsages = wgpu::BufferUsages::STORAGE;
+}
+
+pub type UniformBuffer<T> = ShaderBuffer<T, Uniform>;
+pub type StorageBuffer<T> = ShaderBuffer<T, Storage>;
+
+/// A single `T` in a GPU buffer, bound at binding 0 of its own bind group. The CPU copy
+/// is kept so it can be read back and partially updated without a GPU round trip.
+#[derive(Debug)]
+pub struct ShaderBuffer<T: ShaderType, S: AddressSpace> {
+    value: T,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    _space: PhantomData<S>,
+}
+
+pub(crate) struct AssertUniform<T>(PhantomData<T>);
+
+impl<T: ShaderType> AssertUniform<T> {
+    pub(crate) const VALID: () = assert!(
+        T::UNIFORM_VALID,
+        "type breaks the uniform address space layout rules, use a StorageBuffer or pad arrays and nested structs to 16 bytes"
+    );
+}
+
+impl<T: ShaderType> ShaderBuffer<T, Uniform> {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        value: T,
+        label: &str,
+    ) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = AssertUniform::<T>::VALID;
+        Self::create(device, layout, value, label)
+    }
+}
+
+impl<T: ShaderType> ShaderBuffer<T, Storage> {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        value: T,
+        label: &str,
+    ) -> Self {
+        Self::create(device, layout, value, label)
+    }
+}
+
+impl<T: ShaderType, S: AddressSpace> ShaderBuffer<T, S> {
+    fn create(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        value: T,
+        label: &str,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::bytes_of(&value),
+            usage: S::USAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some(label),
+        });
+
+        Self {
+            value,
+            buffer,
+            bind_group,
+            _space: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Replaces the value and writes it through the queue.
+    pub fn set(&mut self, queue: &wgpu::Queue, value: T) {
+        self.value = value;
+        self.write(queue);
+    }
+
+    /// Mutates the CPU copy in place and writes the result through the queue.
+    pub fn update<F: FnOnce(&mut T)>(&mut self, queue: &wgpu::Queue, f: F) {
+        f(&mut self.value);
+        self.write(queue);
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    fn write(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.value));
+    }
+}
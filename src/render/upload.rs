@@ -0,0 +1,420 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+};
+
+use wgpu::util::StagingBelt;
+
+use crate::{GameError, Result};
+
+/// Bytes copied to the GPU per frame before remaining uploads wait for the next frame.
+pub const DEFAULT_FRAME_BUDGET: u64 = 4 * 1024 * 1024;
+const BELT_CHUNK_SIZE: u64 = 1024 * 1024;
+const HISTORY_LEN: usize = 120;
+
+/// Streams large buffer and texture uploads through a `StagingBelt`, spreading them over
+/// as many frames as the per-frame byte budget requires instead of stalling one frame
+/// with `queue.write_buffer`/`write_texture`.
+///
+/// `record` copies into the frame's encoder, `finish` must be called before that encoder
+/// is submitted and `recall` after.
+#[derive(Debug)]
+pub struct Uploader {
+    belt: StagingBelt,
+    texture_staging: TextureStaging,
+    budget: u64,
+    pending: VecDeque<Upload>,
+    stats: UploadStats,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct UploadStats {
+    pub bytes_last_frame: u64,
+    pub pending_bytes: u64,
+    pub pending_uploads: usize,
+    /// Bytes uploaded per frame, most recent last.
+    pub history: VecDeque<u64>,
+}
+
+impl UploadStats {
+    pub fn average_bytes_per_frame(&self) -> f64 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        self.history.iter().sum::<u64>() as f64 / self.history.len() as f64
+    }
+}
+
+/// Tells the caller when every byte of an upload has been recorded into a frame.
+#[derive(Debug, Clone)]
+pub struct UploadHandle {
+    done: Arc<AtomicBool>,
+}
+
+impl UploadHandle {
+    pub fn is_complete(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Debug)]
+struct Upload {
+    target: UploadTarget,
+    data: Vec<u8>,
+    /// Bytes (buffers) or rows (textures) already copied.
+    progress: u64,
+    done: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+enum UploadTarget {
+    Buffer {
+        buffer: Arc<wgpu::Buffer>,
+        offset: wgpu::BufferAddress,
+    },
+    Texture {
+        texture: Arc<wgpu::Texture>,
+        mip_level: u32,
+        origin: wgpu::Origin3d,
+        size: wgpu::Extent3d,
+        bytes_per_row: u32,
+    },
+}
+
+impl Uploader {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            belt: StagingBelt::new(BELT_CHUNK_SIZE),
+            texture_staging: TextureStaging::new(),
+            budget,
+            pending: VecDeque::new(),
+            stats: UploadStats::default(),
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+    }
+
+    pub fn stats(&self) -> &UploadStats {
+        &self.stats
+    }
+
+    /// Queues `data` to be written at `offset` in `buffer`. Both must be multiples of
+    /// `wgpu::COPY_BUFFER_ALIGNMENT`, and the buffer needs `COPY_DST` usage.
+    pub fn upload_buffer(
+        &mut self,
+        buffer: Arc<wgpu::Buffer>,
+        offset: wgpu::BufferAddress,
+        data: Vec<u8>,
+    ) -> Result<UploadHandle> {
+        if !offset.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+            || !(data.len() as u64).is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+        {
+            return Err(GameError::UnalignedUpload(offset, data.len() as u64));
+        }
+
+        Ok(self.push(UploadTarget::Buffer { buffer, offset }, data))
+    }
+
+    /// Queues tightly packed texel rows for one layer of `texture`. Large images are
+    /// split by rows across frames, padding each row to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+    pub fn upload_texture(
+        &mut self,
+        texture: Arc<wgpu::Texture>,
+        mip_level: u32,
+        origin: wgpu::Origin3d,
+        size: wgpu::Extent3d,
+        data: Vec<u8>,
+    ) -> Result<UploadHandle> {
+        let format = texture.format();
+        let block_size = match (format.block_dimensions(), format.block_copy_size(None)) {
+            ((1, 1), Some(block_size)) => block_size,
+            _ => {
+                return Err(GameError::InvalidTextureUpload(format!(
+                    "{:?} can't be streamed row by row",
+                    format
+                )))
+            }
+        };
+
+        if size.depth_or_array_layers != 1 {
+            return Err(GameError::InvalidTextureUpload(
+                "only single layer uploads are supported".to_string(),
+            ));
+        }
+
+        let bytes_per_row = size.width * block_size;
+        let expected = bytes_per_row as usize * size.height as usize;
+        if data.len() != expected {
+            return Err(GameError::InvalidTextureUpload(format!(
+                "expected {} bytes of texel data, got {}",
+                expected,
+                data.len()
+            )));
+        }
+
+        Ok(self.push(
+            UploadTarget::Texture {
+                texture,
+                mip_level,
+                origin,
+                size,
+                bytes_per_row,
+            },
+            data,
+        ))
+    }
+
+    /// Records as many pending copies into `encoder` as this frame's budget allows.
+    pub fn record(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        self.texture_staging.receive_free();
+        let mut remaining = self.budget;
+        let mut uploaded = 0;
+
+        while remaining > 0 {
+            let Some(upload) = self.pending.front_mut() else {
+                break;
+            };
+
+            let (bytes, finished) = match &upload.target {
+                UploadTarget::Buffer { buffer, offset } => {
+                    let start = upload.progress;
+                    let len = (upload.data.len() as u64 - start).min(remaining)
+                        / wgpu::COPY_BUFFER_ALIGNMENT
+                        * wgpu::COPY_BUFFER_ALIGNMENT;
+                    let Some(size) = wgpu::BufferSize::new(len) else {
+                        break;
+                    };
+
+                    self.belt
+                        .write_buffer(encoder, buffer, offset + start, size, device)
+                        .copy_from_slice(&upload.data[start as usize..(start + len) as usize]);
+                    upload.progress += len;
+                    (len, upload.progress == upload.data.len() as u64)
+                }
+                UploadTarget::Texture {
+                    texture,
+                    mip_level,
+                    origin,
+                    size,
+                    bytes_per_row,
+                } => {
+                    let padded =
+                        wgpu::util::align_to(*bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+                    let first_row = upload.progress as u32;
+                    // Always make progress, even if a single row is over budget.
+                    let rows =
+                        ((remaining / padded as u64) as u32).clamp(1, size.height - first_row);
+
+                    let (staging, staging_offset) =
+                        self.texture_staging
+                            .write(device, padded as u64 * rows as u64, |view| {
+                                for row in 0..rows {
+                                    let src = ((first_row + row) * bytes_per_row) as usize;
+                                    let dst = (row * padded) as usize;
+                                    view[dst..dst + *bytes_per_row as usize].copy_from_slice(
+                                        &upload.data[src..src + *bytes_per_row as usize],
+                                    );
+                                }
+                            });
+
+                    encoder.copy_buffer_to_texture(
+                        wgpu::ImageCopyBuffer {
+                            buffer: staging,
+                            layout: wgpu::ImageDataLayout {
+                                offset: staging_offset,
+                                bytes_per_row: Some(padded),
+                                rows_per_image: Some(rows),
+                            },
+                        },
+                        wgpu::ImageCopyTexture {
+                            texture,
+                            mip_level: *mip_level,
+                            origin: wgpu::Origin3d {
+                                y: origin.y + first_row,
+                                ..*origin
+                            },
+                            aspect: wgpu::TextureAspect::All,
+                        },
+                        wgpu::Extent3d {
+                            width: size.width,
+                            height: rows,
+                            depth_or_array_layers: 1,
+                        },
+                    );
+
+                    upload.progress += rows as u64;
+                    (
+                        padded as u64 * rows as u64,
+                        upload.progress == size.height as u64,
+                    )
+                }
+            };
+
+            remaining = remaining.saturating_sub(bytes);
+            uploaded += bytes;
+
+            if finished {
+                upload.done.store(true, Ordering::Release);
+                self.pending.pop_front();
+            }
+        }
+
+        self.stats.bytes_last_frame = uploaded;
+        self.stats.history.push_back(uploaded);
+        if self.stats.history.len() > HISTORY_LEN {
+            self.stats.history.pop_front();
+        }
+        self.refresh_pending_stats();
+    }
+
+    /// Closes the staging buffers written this frame. Call before submitting.
+    pub fn finish(&mut self) {
+        self.belt.finish();
+        self.texture_staging.finish();
+    }
+
+    /// Reclaims staging buffers the GPU is done with. Call after submitting.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+        self.texture_staging.recall();
+    }
+
+    fn push(&mut self, target: UploadTarget, data: Vec<u8>) -> UploadHandle {
+        let done = Arc::new(AtomicBool::new(data.is_empty()));
+        if !data.is_empty() {
+            self.pending.push_back(Upload {
+                target,
+                data,
+                progress: 0,
+                done: done.clone(),
+            });
+            self.refresh_pending_stats();
+        }
+        UploadHandle { done }
+    }
+
+    fn refresh_pending_stats(&mut self) {
+        self.stats.pending_uploads = self.pending.len();
+        self.stats.pending_bytes = self
+            .pending
+            .iter()
+            .map(|upload| match upload.target {
+                UploadTarget::Buffer { .. } => upload.data.len() as u64 - upload.progress,
+                UploadTarget::Texture { bytes_per_row, .. } => {
+                    upload.data.len() as u64 - upload.progress * bytes_per_row as u64
+                }
+            })
+            .sum();
+    }
+}
+
+/// `StagingBelt` can only copy into buffers, so texture rows are staged in these chunks
+/// instead. They go through the same cycle: written while mapped, unmapped by `finish`,
+/// and remapped by `recall` to be reused once the GPU has consumed them.
+#[derive(Debug)]
+struct TextureStaging {
+    active: Vec<StagingChunk>,
+    closed: Vec<StagingChunk>,
+    free: Vec<StagingChunk>,
+    sender: mpsc::Sender<StagingChunk>,
+    receiver: mpsc::Receiver<StagingChunk>,
+}
+
+#[derive(Debug)]
+struct StagingChunk {
+    buffer: Arc<wgpu::Buffer>,
+    size: u64,
+    offset: u64,
+}
+
+impl TextureStaging {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            active: Vec::new(),
+            closed: Vec::new(),
+            free: Vec::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Reserves `size` bytes, fills them with `fill` and returns the buffer and offset to
+    /// copy from. Offsets are aligned to `COPY_BYTES_PER_ROW_ALIGNMENT`, which covers the
+    /// block size of every format `upload_texture` accepts.
+    fn write(
+        &mut self,
+        device: &wgpu::Device,
+        size: u64,
+        fill: impl FnOnce(&mut [u8]),
+    ) -> (&wgpu::Buffer, u64) {
+        let index = match self
+            .active
+            .iter()
+            .position(|chunk| chunk.offset + size <= chunk.size)
+        {
+            Some(index) => index,
+            None => {
+                let chunk = match self.free.iter().position(|chunk| chunk.size >= size) {
+                    Some(index) => self.free.swap_remove(index),
+                    None => StagingChunk {
+                        buffer: Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+                            label: Some("Texture Upload Staging Buffer"),
+                            size: size.max(BELT_CHUNK_SIZE),
+                            usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                            mapped_at_creation: true,
+                        })),
+                        size: size.max(BELT_CHUNK_SIZE),
+                        offset: 0,
+                    },
+                };
+                self.active.push(chunk);
+                self.active.len() - 1
+            }
+        };
+
+        let chunk = &mut self.active[index];
+        let offset = chunk.offset;
+        fill(
+            &mut chunk
+                .buffer
+                .slice(offset..offset + size)
+                .get_mapped_range_mut(),
+        );
+        chunk.offset =
+            wgpu::util::align_to(offset + size, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64);
+        (&chunk.buffer, offset)
+    }
+
+    fn finish(&mut self) {
+        for chunk in self.active.drain(..) {
+            chunk.buffer.unmap();
+            self.closed.push(chunk);
+        }
+    }
+
+    fn recall(&mut self) {
+        self.receive_free();
+        for mut chunk in self.closed.drain(..) {
+            chunk.offset = 0;
+            let sender = self.sender.clone();
+            let buffer = chunk.buffer.clone();
+            buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Write, move |result| {
+                    if result.is_ok() {
+                        let _ = sender.send(chunk);
+                    }
+                });
+        }
+    }
+
+    fn receive_free(&mut self) {
+        self.free.extend(self.receiver.try_iter());
+    }
+}
@@ -6,6 +6,8 @@ struct CameraUniform {
 
 @group(1) @binding(0) var<uniform> camera: CameraUniform;
 
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
@@ -21,6 +23,7 @@ struct InstanceInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
 };
 
 @vertex
@@ -35,9 +38,12 @@ fn vs_main(
         instance.model_matrix_3,
     );
 
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
@@ -50,6 +56,9 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // The scene's vertices have no normals, so it's the flat face's, as in `clustered.wgsl`.
+    let normal = normalize(cross(dpdy(in.world_position), dpdx(in.world_position)));
+    return vec4<f32>(sunlit(in.world_position, normal, color.rgb), color.a);
 }
 
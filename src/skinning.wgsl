@@ -0,0 +1,54 @@
+// Skinned mesh shader, see `render::skinning`.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1) var s_diffuse: sampler;
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+// World-space joint matrices, global transform times inverse bind matrix.
+@group(2) @binding(0) var<storage, read> joints: array<mat4x4<f32>>;
+
+@group(3) @binding(0) var<uniform> lighting: Lighting;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) joints: vec4<u32>,
+    @location(4) weights: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let skin = joints[in.joints.x] * in.weights.x
+        + joints[in.joints.y] * in.weights.y
+        + joints[in.joints.z] * in.weights.z
+        + joints[in.joints.w] * in.weights.w;
+
+    let world_position = skin * vec4<f32>(in.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    // Fine for the rotation and uniform scale joints usually have.
+    out.normal = (skin * vec4<f32>(in.normal, 0.0)).xyz;
+    out.tex_coords = in.tex_coords;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(sunlit(in.world_position, normalize(in.normal), color.rgb), color.a);
+}
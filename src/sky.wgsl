@@ -0,0 +1,76 @@
+// Analytic single scattering sky, see `render::atmosphere`. Sunlight is dimmed by the
+// air between the sun and the view ray, then scattered towards the camera by Rayleigh
+// and Mie phase functions, as much as the air along the view ray scatters in total.
+
+const PI: f32 = 3.14159265;
+const SUN_ILLUMINANCE: f32 = 20.0;
+// Angular radius, a little larger than the real sun so it reads on screen.
+const SUN_COS_RADIUS: f32 = 0.99985;
+// Henyey-Greenstein asymmetry of haze, most light scatters forward.
+const MIE_G: f32 = 0.76;
+
+struct Sky {
+    inverse_view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    // Towards the sun.
+    sun_direction: vec4<f32>,
+    // Zenith optical depth, Rayleigh in rgb and Mie in a.
+    optical_depth: vec4<f32>,
+    intensity: f32,
+};
+
+@group(0) @binding(0) var<uniform> sky: Sky;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // One triangle that covers the whole screen, on the far plane.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.ndc = uv * 2.0 - 1.0;
+    out.clip_position = vec4<f32>(out.ndc, 1.0, 1.0);
+    return out;
+}
+
+// Kasten-Young, relative to straight up.
+fn air_mass(cos_zenith: f32) -> f32 {
+    let cos_clamped = clamp(cos_zenith, 0.0, 1.0);
+    let zenith_degrees = degrees(acos(cos_clamped));
+    return 1.0 / (cos_clamped + 0.50572 * pow(96.07995 - zenith_degrees, -1.6364));
+}
+
+fn transmittance(cos_zenith: f32) -> vec3<f32> {
+    let extinction = sky.optical_depth.rgb + sky.optical_depth.a;
+    return exp(-extinction * air_mass(cos_zenith));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let far = sky.inverse_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let view = normalize(far.xyz / far.w - sky.eye.xyz);
+    let sun = sky.sun_direction.xyz;
+    let cos_theta = dot(view, sun);
+
+    let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+    let mie_phase = (1.0 - MIE_G * MIE_G)
+        / (4.0 * PI * pow(1.0 + MIE_G * MIE_G - 2.0 * MIE_G * cos_theta, 1.5));
+    let rayleigh = sky.optical_depth.rgb;
+    let mie = sky.optical_depth.a;
+    let scattering = (rayleigh * rayleigh_phase + mie * mie_phase) / (rayleigh + mie);
+
+    // Dark once the sun is well below the horizon.
+    let sunlight = SUN_ILLUMINANCE * transmittance(sun.y) * smoothstep(-0.1, 0.02, sun.y);
+    let view_depth = (rayleigh + mie) * air_mass(view.y);
+    var color = sunlight * scattering * (1.0 - exp(-view_depth));
+
+    if cos_theta > SUN_COS_RADIUS && view.y > 0.0 {
+        color += SUN_ILLUMINANCE * transmittance(view.y);
+    }
+    // Below the horizon, dim ground lit by the same light.
+    color = mix(color * 0.3, color, smoothstep(-0.05, 0.0, view.y));
+    return vec4<f32>(color * sky.intensity, 1.0);
+}
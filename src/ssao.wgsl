@@ -0,0 +1,118 @@
+// Screen-space ambient occlusion, see `render::ssao`. `fs_ssao` samples a hemisphere
+// around each pixel's normal, both reconstructed from depth, and the blur passes smooth
+// out the noise without bleeding across depth edges.
+
+const SAMPLE_COUNT: u32 = 16u;
+const BLUR_RADIUS: i32 = 4;
+// How quickly blur weights fall off with relative depth difference.
+const BLUR_SHARPNESS: f32 = 32.0;
+
+struct Ssao {
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+};
+
+@group(0) @binding(0) var<uniform> ssao: Ssao;
+@group(0) @binding(1) var depth_texture: texture_depth_2d;
+// Blurred by `fs_blur_horizontal` and `fs_blur_vertical`.
+@group(0) @binding(2) var ao_texture: texture_2d<f32>;
+
+fn clamp_texel(texel: vec2<i32>) -> vec2<i32> {
+    return clamp(texel, vec2<i32>(0), vec2<i32>(textureDimensions(depth_texture)) - 1);
+}
+
+fn view_position(texel: vec2<i32>) -> vec3<f32> {
+    let depth = textureLoad(depth_texture, texel, 0);
+    let uv = (vec2<f32>(texel) + 0.5) / vec2<f32>(textureDimensions(depth_texture));
+    let position = ssao.inverse_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+fn view_normal(texel: vec2<i32>, center: vec3<f32>) -> vec3<f32> {
+    let left = view_position(clamp_texel(texel - vec2<i32>(1, 0)));
+    let right = view_position(clamp_texel(texel + vec2<i32>(1, 0)));
+    let up = view_position(clamp_texel(texel - vec2<i32>(0, 1)));
+    let down = view_position(clamp_texel(texel + vec2<i32>(0, 1)));
+    // The neighbors nearer in depth, so the normal doesn't bend across edges.
+    let dx = select(center - left, right - center, abs(right.z - center.z) < abs(center.z - left.z));
+    let dy = select(center - up, down - center, abs(down.z - center.z) < abs(center.z - up.z));
+    return normalize(cross(dy, dx));
+}
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle that covers the whole screen.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_ssao(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    if textureLoad(depth_texture, texel, 0) >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+    let center = view_position(texel);
+    let normal = view_normal(texel, center);
+
+    // Orthonormal basis around the normal (Duff et al.).
+    let sign = select(-1.0, 1.0, normal.z >= 0.0);
+    let a = -1.0 / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+    let tangent = vec3<f32>(1.0 + sign * normal.x * normal.x * a, sign * b, -sign * normal.x);
+    let bitangent = vec3<f32>(b, sign + normal.y * normal.y * a, -normal.y);
+
+    // Interleaved gradient noise turns the kernel per pixel, the blur evens it out.
+    let noise = fract(52.9829189 * fract(dot(position.xy, vec2<f32>(0.06711056, 0.00583715))));
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    var occlusion = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        // Cosine weighted spiral over the hemisphere, with more samples close by.
+        let t = (f32(i) + 0.5) / f32(SAMPLE_COUNT);
+        let angle = f32(i) * 2.3999632 + noise * 6.2831853;
+        let sin_theta = sqrt(t);
+        let local = vec3<f32>(cos(angle) * sin_theta, sin(angle) * sin_theta, sqrt(1.0 - t))
+            * mix(0.1, 1.0, t * t) * ssao.radius;
+        let sample = center + tangent * local.x + bitangent * local.y + normal * local.z;
+
+        let clip = ssao.projection * vec4<f32>(sample, 1.0);
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        let scene = view_position(clamp_texel(vec2<i32>(uv * size))).z;
+        // Surfaces far in front of the pixel don't occlude it.
+        let range = smoothstep(0.0, 1.0, ssao.radius / abs(center.z - scene));
+        occlusion += select(0.0, range, scene >= sample.z + ssao.bias);
+    }
+    return vec4<f32>(pow(1.0 - occlusion / f32(SAMPLE_COUNT), ssao.intensity), 0.0, 0.0, 1.0);
+}
+
+// Gaussian weighted by how close each sample's depth is to the center's.
+fn blur(position: vec2<f32>, direction: vec2<i32>) -> vec4<f32> {
+    let texel = vec2<i32>(position);
+    let center_depth = view_position(texel).z;
+    var sum = 0.0;
+    var weight_sum = 0.0;
+    for (var i = -BLUR_RADIUS; i <= BLUR_RADIUS; i++) {
+        let sample_texel = clamp_texel(texel + direction * i);
+        let depth = view_position(sample_texel).z;
+        let spatial = exp(-f32(i * i) / 8.0);
+        let edge = exp(-abs(depth - center_depth) / max(abs(center_depth), 0.001) * BLUR_SHARPNESS);
+        let weight = spatial * edge;
+        sum += textureLoad(ao_texture, sample_texel, 0).r * weight;
+        weight_sum += weight;
+    }
+    return vec4<f32>(sum / weight_sum, 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return blur(position.xy, vec2<i32>(1, 0));
+}
+
+@fragment
+fn fs_blur_vertical(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return blur(position.xy, vec2<i32>(0, 1));
+}
@@ -0,0 +1,242 @@
+use std::{
+    cell::Cell,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde_json::{json, Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+use crate::{lock, GameError, Result};
+
+/// Installs a global `tracing` subscriber that streams every span to `path` as Chrome
+/// trace-event JSON. Events are buffered and written as they happen, so memory use doesn't
+/// grow with the length of the session; the array is closed when the returned guard is
+/// dropped. The file opens directly in Perfetto or `chrome://tracing`, which also accept
+/// it unclosed if the game didn't exit cleanly.
+pub fn init_chrome_trace<P: Into<PathBuf>>(path: P) -> Result<TraceGuard> {
+    let path = path.into();
+    let mut writer =
+        BufWriter::new(File::create(&path).map_err(|err| GameError::TraceError(err.to_string()))?);
+    writer
+        .write_all(b"[\n")
+        .map_err(|err| GameError::TraceError(err.to_string()))?;
+    let inner = Arc::new(Mutex::new(TraceInner {
+        path,
+        writer: Some(writer),
+        first: true,
+    }));
+
+    let layer = ChromeTraceLayer {
+        inner: inner.clone(),
+        start: instant::Instant::now(),
+    };
+
+    tracing_subscriber::registry()
+        .with(layer)
+        .try_init()
+        .map_err(|err| GameError::TraceError(err.to_string()))?;
+
+    Ok(TraceGuard { inner })
+}
+
+/// Closes the trace file when dropped.
+#[derive(Debug)]
+pub struct TraceGuard {
+    inner: Arc<Mutex<TraceInner>>,
+}
+
+impl TraceGuard {
+    /// Writes out buffered events, leaving the file readable while tracing continues.
+    pub fn flush(&self) -> Result<()> {
+        match &mut lock!(self.inner).writer {
+            Some(writer) => writer
+                .flush()
+                .map_err(|err| GameError::TraceError(err.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        let mut inner = lock!(self.inner);
+        let Some(mut writer) = inner.writer.take() else {
+            return;
+        };
+        match writer.write_all(b"\n]\n").and_then(|()| writer.flush()) {
+            Ok(()) => log::info!("Wrote trace to {}", inner.path.display()),
+            Err(err) => log::error!("Failed to write {}: {}", inner.path.display(), err),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TraceInner {
+    path: PathBuf,
+    /// `None` once the trace is closed or a write failed.
+    writer: Option<BufWriter<File>>,
+    first: bool,
+}
+
+impl TraceInner {
+    fn write(&mut self, event: &Value) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        let separator: &[u8] = if self.first { b"" } else { b",\n" };
+        let result = writer
+            .write_all(separator)
+            .and_then(|()| serde_json::to_writer(&mut *writer, event).map_err(Into::into));
+        self.first = false;
+
+        if let Err(err) = result {
+            log::error!("Stopped tracing to {}: {}", self.path.display(), err);
+            self.writer = None;
+        }
+    }
+}
+
+struct ChromeTraceLayer {
+    inner: Arc<Mutex<TraceInner>>,
+    start: instant::Instant,
+}
+
+/// Span fields, kept in the span's extensions so they can be attached as `args`.
+struct SpanArgs(Map<String, Value>);
+
+impl ChromeTraceLayer {
+    fn push(&self, mut event: Map<String, Value>) {
+        event.insert(
+            "ts".to_string(),
+            json!(self.start.elapsed().as_secs_f64() * 1_000_000.0),
+        );
+        event.insert("pid".to_string(), json!(1));
+        event.insert("tid".to_string(), json!(thread_id()));
+        lock!(self.inner).write(&Value::Object(event));
+    }
+}
+
+impl<S> Layer<S> for ChromeTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut args = ArgsVisitor(Map::new());
+        attrs.record(&mut args);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanArgs(args.0));
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(args) = span.extensions_mut().get_mut::<SpanArgs>() {
+                let mut visitor = ArgsVisitor(std::mem::take(&mut args.0));
+                values.record(&mut visitor);
+                args.0 = visitor.0;
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let args = span
+            .extensions()
+            .get::<SpanArgs>()
+            .map(|args| Value::Object(args.0.clone()))
+            .unwrap_or_default();
+
+        let mut event = Map::new();
+        event.insert("name".to_string(), json!(span.name()));
+        event.insert("cat".to_string(), json!(span.metadata().target()));
+        event.insert("ph".to_string(), json!("B"));
+        event.insert("args".to_string(), args);
+        self.push(event);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut event = Map::new();
+        event.insert("name".to_string(), json!(span.name()));
+        event.insert("cat".to_string(), json!(span.metadata().target()));
+        event.insert("ph".to_string(), json!("E"));
+        self.push(event);
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut args = ArgsVisitor(Map::new());
+        event.record(&mut args);
+        let name = args
+            .0
+            .remove("message")
+            .and_then(|message| message.as_str().map(str::to_string))
+            .unwrap_or_else(|| event.metadata().name().to_string());
+
+        let mut instant = Map::new();
+        instant.insert("name".to_string(), json!(name));
+        instant.insert("cat".to_string(), json!(event.metadata().target()));
+        instant.insert("ph".to_string(), json!("i"));
+        instant.insert("s".to_string(), json!("t"));
+        instant.insert("args".to_string(), Value::Object(args.0));
+        self.push(instant);
+    }
+}
+
+struct ArgsVisitor(Map<String, Value>);
+
+impl Visit for ArgsVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+/// Small sequential ids read better in trace viewers than `std::thread::ThreadId`.
+fn thread_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: Cell<u64> = const { Cell::new(0) };
+    }
+
+    ID.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT.fetch_add(1, Ordering::Relaxed));
+        }
+        id.get()
+    })
+}
@@ -0,0 +1,99 @@
+// Alpha-blended meshes, see `render::transparent`.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    // Multiplies the texture, alpha included.
+    @location(9) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    // Distance in front of the camera, for the weighted blended weight.
+    @location(2) view_depth: f32,
+    @location(3) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    out.color = instance.color;
+    out.view_depth = out.clip_position.w;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+fn surface_color(in: VertexOutput) -> vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.color;
+    // No vertex normals, as in `shader.wgsl`. Always facing the camera, so both sides
+    // of a pane are lit the same.
+    let normal = normalize(cross(dpdy(in.world_position), dpdx(in.world_position)));
+    return vec4<f32>(sunlit(in.world_position, normal, color.rgb), color.a);
+}
+
+// Sorted back to front and alpha blended straight into the frame.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return surface_color(in);
+}
+
+struct WeightedBlendedOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: vec4<f32>,
+};
+
+// Equation 7 of McGuire and Bavoil's "Weighted Blended Order-Independent Transparency",
+// favouring nearer and more opaque surfaces. Matches `particles.wgsl`.
+fn weight(view_depth: f32, alpha: f32) -> f32 {
+    let z = view_depth;
+    return alpha * clamp(10.0 / (1e-5 + pow(z / 5.0, 2.0) + pow(z / 200.0, 6.0)), 1e-2, 3e3);
+}
+
+// Unsorted into the accumulation targets of `render::transparent::oit`, resolved by
+// `oit_composite.wgsl`.
+@fragment
+fn fs_weighted_blended(in: VertexOutput) -> WeightedBlendedOutput {
+    let color = surface_color(in);
+    let w = weight(in.view_depth, color.a);
+
+    var out: WeightedBlendedOutput;
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * w;
+    out.revealage = vec4<f32>(color.a);
+    return out;
+}
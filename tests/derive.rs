@@ -0,0 +1,6 @@
+#[test]
+fn derives() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/shader_type.rs");
+    t.compile_fail("tests/ui/misaligned_vec3.rs");
+}
@@ -0,0 +1,11 @@
+use game_lib::render::uniform::ShaderType;
+
+// WGSL aligns a vec3 to 16 bytes, so `direction` belongs at offset 16, not 4.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct Light {
+    intensity: f32,
+    direction: glam::Vec3,
+}
+
+fn main() {}
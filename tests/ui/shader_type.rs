@@ -0,0 +1,27 @@
+use game_lib::render::{types::VertexDescription, uniform::ShaderType};
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+struct Light {
+    direction: glam::Vec3,
+    intensity: f32,
+    color: glam::Vec4,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexDescription)]
+#[vertex(step_mode = "instance")]
+struct Instance {
+    offset: glam::Vec3,
+    scale: f32,
+}
+
+fn main() {
+    assert_eq!((Light::ALIGN, Light::SIZE), (16, 32));
+    assert!(Light::UNIFORM_VALID);
+
+    let layout = Instance::desc();
+    assert_eq!(layout.array_stride, 16);
+    assert_eq!(layout.step_mode, wgpu::VertexStepMode::Instance);
+    assert_eq!(layout.attributes.len(), 2);
+}